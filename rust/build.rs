@@ -1,3 +1,7 @@
+use std::env;
+use std::fs;
+use std::path::Path;
+
 fn main() {
     #[cfg(target_os = "macos")]
     println!("cargo:rustc-link-arg=-Wl,-rpath,@loader_path");
@@ -7,4 +11,139 @@ fn main() {
 
     #[cfg(target_family = "windows")]
     println!("cargo:rustc-link-search=C:\\sdl2");
+
+    // Embedded fallback resources, see `xbase_embedded.rs`.
+    println!("cargo:rerun-if-changed=build.rs");
+    println!("cargo:rerun-if-changed=resources");
+
+    let out_dir = env::var("OUT_DIR").expect("OUT_DIR is set by cargo");
+    let out_dir = Path::new(&out_dir);
+
+    let font = fs::read_to_string("resources/font0.txt").expect("font source is readable");
+    fs::write(out_dir.join("font0.fon"), build_font(&font)).expect("font is writable");
+    fs::write(out_dir.join("color.pal"), build_palette()).expect("palette is writable");
+}
+
+// The number of glyphs in fallback font (7-bit ASCII).
+const FONT_GLYPH_COUNT: usize = 128;
+
+// Converts ASCII art in [source] into `.fon` file (as loaded by
+// `textFontLoad`).
+//
+// Layout is a header (glyph count, line height, letter spacing and two unused
+// pointers), followed by glyph descriptors (width and data offset), followed
+// by 1-bit glyph rows (padded to bytes).
+fn build_font(source: &str) -> Vec<u8> {
+    let mut glyphs: Vec<Option<Vec<&str>>> = vec![None; FONT_GLYPH_COUNT];
+    let mut current = None;
+    for line in source.lines() {
+        let line = line.trim_end();
+        if line.is_empty() || line.starts_with(';') {
+            continue;
+        }
+
+        if let Some(name) = line.strip_prefix("= ") {
+            let ch = match name {
+                "space" => b' ',
+                _ => name.as_bytes()[0],
+            };
+            glyphs[ch as usize] = Some(Vec::new());
+            current = Some(ch as usize);
+        } else {
+            let index = current.expect("glyph rows follow glyph header");
+            glyphs[index].as_mut().expect("glyph is defined").push(line);
+        }
+    }
+
+    for ch in b'a'..=b'z' {
+        if glyphs[ch as usize].is_none() {
+            glyphs[ch as usize] = glyphs[ch.to_ascii_uppercase() as usize].clone();
+        }
+    }
+
+    // One additional empty row separates lines of text.
+    let glyph_height = 7;
+    let line_height = glyph_height + 1;
+
+    let mut descriptors = Vec::new();
+    let mut data = Vec::new();
+    for glyph in &glyphs {
+        let rows = glyph.as_deref().unwrap_or(&[]);
+        assert!(rows.is_empty() || rows.len() == glyph_height);
+
+        let width = rows.first().map_or(0, |row| row.len());
+        descriptors.extend_from_slice(&(width as i32).to_le_bytes());
+        descriptors.extend_from_slice(&(data.len() as i32).to_le_bytes());
+
+        if width == 0 {
+            continue;
+        }
+
+        let row_size = width.div_ceil(8);
+        for y in 0..line_height {
+            let mut row = vec![0u8; row_size];
+            if let Some(pixels) = rows.get(y) {
+                assert_eq!(pixels.len(), width);
+                for (x, pixel) in pixels.bytes().enumerate() {
+                    if pixel == b'#' {
+                        row[x / 8] |= 0x80 >> (x % 8);
+                    }
+                }
+            }
+            data.extend_from_slice(&row);
+        }
+    }
+
+    let mut font = Vec::new();
+    font.extend_from_slice(&(FONT_GLYPH_COUNT as i32).to_le_bytes());
+    font.extend_from_slice(&(line_height as i32).to_le_bytes());
+    // Letter spacing.
+    font.extend_from_slice(&1i32.to_le_bytes());
+    // Glyphs and data pointers, which are ignored.
+    font.extend_from_slice(&0i32.to_le_bytes());
+    font.extend_from_slice(&0i32.to_le_bytes());
+    font.extend_from_slice(&descriptors);
+    font.extend_from_slice(&data);
+    font
+}
+
+// Builds minimal `color.pal` (as loaded by `colorPaletteLoad`): 6x6x6 color
+// cube and shades of gray (6-bit components), followed by the table which
+// maps every 15-bit color to the nearest palette entry.
+//
+// Index 0 is reserved (it's transparent in game art), so it's never chosen.
+fn build_palette() -> Vec<u8> {
+    let mut colors = vec![[0u8; 3]];
+    for r in 0..6u32 {
+        for g in 0..6u32 {
+            for b in 0..6u32 {
+                colors.push([r, g, b].map(|component| (component * 63 / 5) as u8));
+            }
+        }
+    }
+
+    let grays = 256 - colors.len();
+    for index in 0..grays {
+        let value = ((index + 1) * 63 / (grays + 1)) as u8;
+        colors.push([value, value, value]);
+    }
+
+    let mut palette = colors.concat();
+    for color in 0..0x8000usize {
+        // 5-bit components scaled to 6 bits.
+        let target = [(color >> 10) & 0x1F, (color >> 5) & 0x1F, color & 0x1F]
+            .map(|component| (component * 63 / 31) as i32);
+        let nearest = (1..colors.len())
+            .min_by_key(|&index| {
+                colors[index]
+                    .iter()
+                    .zip(target)
+                    .map(|(&component, target)| (component as i32 - target).pow(2))
+                    .sum::<i32>()
+            })
+            .expect("palette is not empty");
+        palette.push(nearest as u8);
+    }
+
+    palette
 }
@@ -1,10 +1,13 @@
-use std::ffi::{c_uint, c_void, CString};
+use std::ffi::{c_uint, c_void, CStr, CString};
 use std::mem;
 use std::ptr::{null, null_mut};
 use std::sync::atomic::{AtomicI32, AtomicPtr, Ordering};
-use libc::{c_char, c_int, c_long, c_short, c_uchar, c_ushort, free, malloc, memmove, memset, qsort, size_t, snprintf, strchr, strlen};
-use crate::platform_compat::{COMPAT_MAX_DIR, COMPAT_MAX_EXT, COMPAT_MAX_FNAME, COMPAT_MAX_PATH, rust_compat_splitpath, rust_compat_strdup, rust_compat_stricmp, compat_windows_path_to_native};
-use crate::xfile::{rust_xfile_close, rust_xfile_get_size, rust_xfile_open, xfile_read, xfile_read_char, xbase_open, XFile, XList, xfile_read_string, xfile_write_char, xlist_init, xlist_free};
+use libc::{c_char, c_int, c_long, c_short, c_uchar, c_ushort, free, malloc, memmove, memset, qsort, size_t, strlen};
+use crate::debug_print::debug_print;
+use crate::mod_manager::{mod_conflict_report, mod_conflicts, ModManifest};
+use crate::platform_compat::{compat_directory_part, compat_native_path, rust_compat_strdup, rust_compat_stricmp, compat_windows_path_to_native};
+use crate::vfs::Vfs;
+use crate::xfile::{rust_xfile_close, rust_xfile_get_size, rust_xfile_open, rust_xfile_write, xfile_read, xfile_read_char, xbase_open, XFile, XList, xfile_read_string, xfile_write_char, xlist_init, xlist_free};
 
 type FileReadProgressHandler = unsafe extern "C" fn();
 
@@ -103,6 +106,52 @@ pub unsafe extern "C" fn rust_db_open(file_path1: *mut c_char, _a2: c_int, file_
     0
 }
 
+// Opens mods listed in manifest at [manifest_path] on top of open xbases (see
+// [ModManifest]). Mods which cannot be opened are reported with
+// [debug_print] and skipped, in this case false is returned (but the rest of mods are open).
+//
+// When [report_path] is not NULL, the report of files provided by more than
+// one xbase is written there (as any other file written with
+// [rust_xfile_open], so it ends up in overlay directory, if any).
+#[no_mangle]
+pub unsafe extern "C" fn rust_db_mount_mods(manifest_path: *const c_char, report_path: *const c_char) -> bool {
+    assert_ne!(manifest_path, null());
+
+    let manifest = match ModManifest::load(&compat_native_path(manifest_path)) {
+        Ok(manifest) => manifest,
+        Err(err) => {
+            debug_print(format_args!("db: cannot load {}: {}", CStr::from_ptr(manifest_path).to_string_lossy(), err));
+            return false;
+        }
+    };
+
+    let vfs = Vfs::global();
+    let mut result = true;
+    for (path, err) in manifest.mount(vfs) {
+        debug_print(format_args!("db: cannot open mod {}: {}", path.to_string_lossy(), err));
+        result = false;
+    }
+
+    if !report_path.is_null() {
+        let report = mod_conflict_report(&mod_conflicts(vfs));
+
+        let wt = CString::new("wt").expect("valid string");
+        let stream = rust_xfile_open(report_path, wt.as_ptr());
+        if stream.is_null() {
+            return false;
+        }
+
+        let bytes_written = rust_xfile_write(report.as_ptr() as *const c_void, 1, report.len(), stream);
+        rust_xfile_close(stream);
+
+        if bytes_written != report.len() {
+            return false;
+        }
+    }
+
+    result
+}
+
 // TODO: sizePtr should be long*.
 #[no_mangle]
 pub unsafe extern "C" fn rust_db_get_file_size(file_path: *const c_char, size_ptr: *mut c_int) -> c_int {
@@ -445,7 +494,7 @@ pub unsafe extern "C" fn rust_db_fwrite_long_count(stream: *const XFile, arr: *m
 }
 
 unsafe extern "C" fn db_list_compare(p1: *const c_void, p2: *const c_void) -> c_int {
-    return rust_compat_stricmp(p1 as *const c_char, p2 as *const c_char);
+    return rust_compat_stricmp(*(p1 as *const *const c_char), *(p2 as *const *const c_char));
 }
 
 #[no_mangle]
@@ -471,7 +520,7 @@ pub unsafe extern "C" fn rust_file_name_list_init(pattern: *const c_char, file_n
         let mut file_names_length = (*xlist).file_names_length;
         let mut index = 0;
         while index < file_names_length - 1 {
-            if rust_compat_stricmp((*xlist).file_names.offset(index as isize) as *const c_char, (*xlist).file_names.offset(index as isize + 1) as *const c_char) == 0 {
+            if rust_compat_stricmp(*(*xlist).file_names.offset(index as isize), *(*xlist).file_names.offset(index as isize + 1)) == 0 {
                 let temp = *(*xlist).file_names.offset(index as isize + 1);
                 memmove((*xlist).file_names.offset(index as isize + 1) as *mut c_void,
                         (*xlist).file_names.offset(index as isize + 2) as *mut c_void,
@@ -486,20 +535,15 @@ pub unsafe extern "C" fn rust_file_name_list_init(pattern: *const c_char, file_n
 
         let is_wildcard = *pattern == '*' as c_char;
 
-        let sformat_sformat = CString::new("%s%s").expect("valid string");
         for index in 0..file_names_length {
             let name = *(*xlist).file_names.offset(index as isize);
-            let mut dir = [0 as c_char; COMPAT_MAX_DIR as usize];
-            let mut file_name = [0 as c_char; COMPAT_MAX_FNAME as usize];
-            let mut extension = [0 as c_char; COMPAT_MAX_EXT as usize];
             compat_windows_path_to_native(name);
-            rust_compat_splitpath(name, null_mut(), dir.as_mut_ptr(), file_name.as_mut_ptr(), extension.as_mut_ptr());
 
-            if !is_wildcard || dir[0] == '\0' as c_char || (strchr(dir.as_ptr(), '\\' as c_int) == null_mut() && strchr(dir.as_ptr(), '/' as c_int) == null_mut()) {
-                // NOTE: Quick and dirty fix to buffer overflow. See RE to
-                // understand the problem.
-                let mut path = [0 as c_char; COMPAT_MAX_PATH];
-                snprintf(path.as_mut_ptr(), mem::size_of_val(&path), sformat_sformat.as_ptr(), file_name, extension);
+            let name = CStr::from_ptr(name).to_bytes();
+            let dir = compat_directory_part(name);
+            if !is_wildcard || !dir.iter().any(|&ch| ch == b'\\' || ch == b'/') {
+                // NOTE: Copy file name first, [name] can be freed below.
+                let path = CString::new(&name[dir.len()..]).expect("no null characters");
                 free(*(*xlist).file_names.offset(length as isize) as *mut c_void);
                 *(*xlist).file_names.offset(length as isize) = rust_compat_strdup(path.as_ptr());
                 length += 1;
@@ -0,0 +1,352 @@
+use crate::dfile::dbase_normalize_path;
+use crate::platform_compat::compat_stricmp_ord;
+use libc::c_ulong;
+use libz_sys::{compress2, compressBound, Bytef, Z_DEFAULT_COMPRESSION, Z_OK};
+use std::collections::HashMap;
+use std::ffi::CString;
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+// The default minimal size of an entry that is worth compressing.
+//
+// Smaller entries are stored as is, compression overhead (zlib header and
+// checksum) makes them bigger than original data anyway.
+pub const DBASE_WRITER_DEFAULT_COMPRESSION_THRESHOLD: usize = 0x100;
+
+// The source of data for [DBaseWriterEntry].
+enum DBaseWriterSource {
+    File(PathBuf),
+    Data(Vec<u8>),
+}
+
+struct DBaseWriterEntry {
+    // The path of entry inside .DAT file (with backslashes as separators).
+    path: CString,
+
+    source: DBaseWriterSource,
+}
+
+// The entry as it is stored in the entries table of .DAT file.
+struct DBaseWriterRecord {
+    path: CString,
+    compressed: bool,
+    uncompressed_size: i32,
+    data_size: i32,
+    data_offset: i32,
+}
+
+// A builder of .DAT files (DAT2 layout, as read by [dbase_open]).
+//
+// The resulting file consists of entries data, followed by entries table
+// (sorted case-insensitively, as in original .DAT files), followed by footer
+// with the size of entries table and the size of the entire file.
+pub struct DBaseWriter {
+    entries: Vec<DBaseWriterEntry>,
+
+    // Indexes of [entries] by normalized path (see [dbase_normalize_path]).
+    index: HashMap<Vec<u8>, usize>,
+
+    // Entries with size below this value are stored uncompressed.
+    compression_threshold: usize,
+}
+
+impl Default for DBaseWriter {
+    fn default() -> Self {
+        Self {
+            entries: Vec::new(),
+            index: HashMap::new(),
+            compression_threshold: DBASE_WRITER_DEFAULT_COMPRESSION_THRESHOLD,
+        }
+    }
+}
+
+impl DBaseWriter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // Sets minimal entry size for compression. Pass [usize::MAX] to disable
+    // compression altogether.
+    pub fn set_compression_threshold(&mut self, threshold: usize) {
+        self.compression_threshold = threshold;
+    }
+
+    // Adds file at [file_path] as [archive_path] entry. The file is not read
+    // until [write] is called.
+    pub fn add_file(&mut self, archive_path: &str, file_path: &Path) -> io::Result<()> {
+        self.add_entry(
+            archive_path,
+            DBaseWriterSource::File(file_path.to_path_buf()),
+        )
+    }
+
+    // Adds in-memory [data] as [archive_path] entry.
+    pub fn add_data(&mut self, archive_path: &str, data: Vec<u8>) -> io::Result<()> {
+        self.add_entry(archive_path, DBaseWriterSource::Data(data))
+    }
+
+    // Recursively adds every file in [root]. Entry paths are relative to
+    // [root], so `root/art/intrface/iface.frm` becomes `art\intrface\iface.frm`.
+    pub fn add_directory(&mut self, root: &Path) -> io::Result<()> {
+        self.add_directory_with_prefix(root, "")
+    }
+
+    fn add_directory_with_prefix(&mut self, directory: &Path, prefix: &str) -> io::Result<()> {
+        let mut dir_entries = fs::read_dir(directory)?.collect::<io::Result<Vec<_>>>()?;
+        // Make the order of entries with the same name (modulo case)
+        // deterministic.
+        dir_entries.sort_by_key(|entry| entry.file_name());
+
+        for dir_entry in dir_entries {
+            let name = dir_entry.file_name();
+            let name = name.to_str().ok_or_else(|| {
+                io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!("non UTF-8 file name: {}", dir_entry.path().display()),
+                )
+            })?;
+
+            let archive_path = if prefix.is_empty() {
+                name.to_string()
+            } else {
+                format!("{}\\{}", prefix, name)
+            };
+
+            if dir_entry.file_type()?.is_dir() {
+                self.add_directory_with_prefix(&dir_entry.path(), &archive_path)?;
+            } else {
+                self.add_file(&archive_path, &dir_entry.path())?;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn add_entry(&mut self, archive_path: &str, source: DBaseWriterSource) -> io::Result<()> {
+        let path = dbase_writer_normalize_path(archive_path)?;
+
+        // Entries are looked up case-insensitively, so there is no way to
+        // have two entries which differ only in case. Later entry wins.
+        let key = dbase_normalize_path(path.as_bytes());
+        match self.index.get(&key) {
+            Some(&index) => self.entries[index] = DBaseWriterEntry { path, source },
+            None => {
+                self.index.insert(key, self.entries.len());
+                self.entries.push(DBaseWriterEntry { path, source });
+            }
+        }
+
+        Ok(())
+    }
+
+    // Writes .DAT file to [file_path].
+    pub fn write(&self, file_path: &Path) -> io::Result<()> {
+        let mut stream = BufWriter::new(File::create(file_path)?);
+        self.write_to(&mut stream)?;
+        stream.flush()
+    }
+
+    // Writes .DAT file to arbitrary [stream].
+    pub fn write_to<W: Write>(&self, stream: &mut W) -> io::Result<()> {
+        let mut entries = self.entries.iter().collect::<Vec<_>>();
+        entries.sort_by(|a, b| compat_stricmp_ord(a.path.as_ptr(), b.path.as_ptr()));
+
+        // Write data section.
+        let mut records = Vec::with_capacity(entries.len());
+        let mut offset: usize = 0;
+        for entry in entries {
+            let data = match &entry.source {
+                DBaseWriterSource::File(file_path) => fs::read(file_path)?,
+                DBaseWriterSource::Data(data) => data.clone(),
+            };
+
+            let compressed_data = if data.len() >= self.compression_threshold {
+                Some(dbase_writer_compress(&data)?)
+                    .filter(|compressed| compressed.len() < data.len())
+            } else {
+                None
+            };
+
+            let (compressed, stored_data) = match &compressed_data {
+                Some(compressed_data) => (true, compressed_data.as_slice()),
+                None => (false, data.as_slice()),
+            };
+
+            stream.write_all(stored_data)?;
+
+            records.push(DBaseWriterRecord {
+                path: entry.path.clone(),
+                compressed,
+                uncompressed_size: dbase_writer_checked_size(data.len())?,
+                data_size: dbase_writer_checked_size(stored_data.len())?,
+                data_offset: dbase_writer_checked_size(offset)?,
+            });
+
+            offset += stored_data.len();
+        }
+
+        // Write entries table.
+        let mut entries_data_size = 0;
+        entries_data_size += dbase_writer_write_i32(stream, records.len() as i32)?;
+        for record in &records {
+            let path = record.path.as_bytes();
+            entries_data_size += dbase_writer_write_i32(stream, path.len() as i32)?;
+            stream.write_all(path)?;
+            entries_data_size += path.len();
+            stream.write_all(&[record.compressed as u8])?;
+            entries_data_size += 1;
+            entries_data_size += dbase_writer_write_i32(stream, record.uncompressed_size)?;
+            entries_data_size += dbase_writer_write_i32(stream, record.data_size)?;
+            entries_data_size += dbase_writer_write_i32(stream, record.data_offset)?;
+        }
+
+        // Write footer.
+        let file_size = offset + entries_data_size + 8;
+        dbase_writer_write_i32(stream, dbase_writer_checked_size(entries_data_size)?)?;
+        dbase_writer_write_i32(stream, dbase_writer_checked_size(file_size)?)?;
+
+        Ok(())
+    }
+}
+
+// Converts [archive_path] into the form used in .DAT files: backslashes as
+// separators, no leading separators.
+fn dbase_writer_normalize_path(archive_path: &str) -> io::Result<CString> {
+    let path = archive_path
+        .split(['/', '\\'])
+        .filter(|component| !component.is_empty())
+        .collect::<Vec<_>>()
+        .join("\\");
+
+    if path.is_empty() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("invalid entry path: {:?}", archive_path),
+        ));
+    }
+
+    CString::new(path).map_err(|err| io::Error::new(io::ErrorKind::InvalidInput, err))
+}
+
+fn dbase_writer_checked_size(size: usize) -> io::Result<i32> {
+    i32::try_from(size).map_err(|_| {
+        io::Error::new(
+            io::ErrorKind::InvalidInput,
+            ".DAT file cannot be larger than 2 GB",
+        )
+    })
+}
+
+fn dbase_writer_write_i32<W: Write>(stream: &mut W, value: i32) -> io::Result<usize> {
+    stream.write_all(&value.to_le_bytes())?;
+    Ok(4)
+}
+
+// Compresses [data] into zlib stream suitable for [dfile_read_compressed].
+fn dbase_writer_compress(data: &[u8]) -> io::Result<Vec<u8>> {
+    unsafe {
+        let mut compressed_size = compressBound(data.len() as c_ulong);
+        let mut compressed = vec![0u8; compressed_size as usize];
+        if compress2(
+            compressed.as_mut_ptr() as *mut Bytef,
+            &mut compressed_size,
+            data.as_ptr() as *const Bytef,
+            data.len() as c_ulong,
+            Z_DEFAULT_COMPRESSION,
+        ) != Z_OK
+        {
+            return Err(io::Error::other("compression failed"));
+        }
+
+        compressed.truncate(compressed_size as usize);
+        Ok(compressed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dfile::DBase;
+    use crate::test_utils::temp_path;
+    use std::io::Read;
+
+    fn read_entry(dat_path: &Path, entry_path: &str) -> Option<Vec<u8>> {
+        let dbase = DBase::open(dat_path).ok()?;
+        let mut dfile = dbase.open_entry(entry_path).ok()?;
+        let mut data = Vec::new();
+        dfile.read_to_end(&mut data).ok()?;
+        Some(data)
+    }
+
+    #[test]
+    fn test_dbase_writer_round_trip() {
+        let dat_path = temp_path("round_trip.dat");
+        let large = (0..10000).map(|i| (i % 7) as u8).collect::<Vec<_>>();
+
+        let mut writer = DBaseWriter::new();
+        writer
+            .add_data("text/english/game/proto.msg", b"{100}{}{Hi}".to_vec())
+            .expect("");
+        writer
+            .add_data("ART\\INTRFACE\\IFACE.FRM", large.clone())
+            .expect("");
+        writer.add_data("empty.txt", Vec::new()).expect("");
+        writer
+            .add_data("Maps/Arcaves.map", vec![1, 2, 3])
+            .expect("");
+        writer.write(&dat_path).expect("");
+
+        assert_eq!(
+            read_entry(&dat_path, "TEXT\\ENGLISH\\GAME\\PROTO.MSG"),
+            Some(b"{100}{}{Hi}".to_vec())
+        );
+        assert_eq!(
+            read_entry(&dat_path, "art\\intrface\\iface.frm"),
+            Some(large)
+        );
+        assert_eq!(read_entry(&dat_path, "EMPTY.TXT"), Some(Vec::new()));
+        assert_eq!(
+            read_entry(&dat_path, "maps\\arcaves.map"),
+            Some(vec![1, 2, 3])
+        );
+        assert_eq!(read_entry(&dat_path, "maps\\missing.map"), None);
+
+        fs::remove_file(&dat_path).expect("");
+    }
+
+    #[test]
+    fn test_dbase_writer_add_directory() {
+        let root = temp_path("directory");
+        let dat_path = temp_path("directory.dat");
+        fs::create_dir_all(root.join("data").join("proto")).expect("");
+        fs::write(
+            root.join("data").join("proto").join("items.lst"),
+            b"00000001.pro",
+        )
+        .expect("");
+        fs::write(root.join("readme.txt"), b"patch").expect("");
+
+        let mut writer = DBaseWriter::new();
+        writer.add_directory(&root).expect("");
+        writer.write(&dat_path).expect("");
+
+        assert_eq!(
+            read_entry(&dat_path, "data\\proto\\items.lst"),
+            Some(b"00000001.pro".to_vec())
+        );
+        assert_eq!(read_entry(&dat_path, "readme.txt"), Some(b"patch".to_vec()));
+
+        fs::remove_dir_all(&root).expect("");
+        fs::remove_file(&dat_path).expect("");
+    }
+
+    #[test]
+    fn test_dbase_writer_replaces_duplicates() {
+        let mut writer = DBaseWriter::new();
+        writer.add_data("a\\b.txt", vec![1]).expect("");
+        writer.add_data("A/B.TXT", vec![2]).expect("");
+        assert_eq!(writer.entries.len(), 1);
+        assert!(writer.add_data("", vec![]).is_err());
+    }
+}
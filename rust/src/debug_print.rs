@@ -0,0 +1,60 @@
+// Diagnostic messages.
+//
+// The library never writes to stderr itself. Messages about problems which
+// are not reported to the caller otherwise are passed to the handler
+// registered with [rust_set_debug_print_handler] (the game routes them to
+// `debugPrint`). Without handler messages are dropped.
+
+use libc::c_char;
+use spin::RwLock;
+use std::ffi::CString;
+use std::fmt;
+
+pub type DebugPrintHandler = unsafe extern "C" fn(*const c_char);
+
+static G_DEBUG_PRINT_HANDLER: RwLock<Option<DebugPrintHandler>> = RwLock::new(None);
+
+// Sets [handler] to receive diagnostic messages (without trailing newline),
+// or disables it if [handler] is NULL.
+#[no_mangle]
+pub extern "C" fn rust_set_debug_print_handler(handler: Option<DebugPrintHandler>) {
+    *G_DEBUG_PRINT_HANDLER.write() = handler;
+}
+
+// Passes message formatted from [args] to the handler (if any).
+pub(crate) fn debug_print(args: fmt::Arguments) {
+    let handler = *G_DEBUG_PRINT_HANDLER.read();
+    if let Some(handler) = handler {
+        let message = fmt::format(args).replace('\0', "\\0");
+        let message = CString::new(message).expect("no null characters");
+        unsafe { handler(message.as_ptr()) };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use parking_lot::Mutex;
+    use std::ffi::CStr;
+
+    // Messages received by [handler].
+    static MESSAGES: Mutex<Vec<String>> = Mutex::new(Vec::new());
+
+    unsafe extern "C" fn handler(message: *const c_char) {
+        let message = CStr::from_ptr(message).to_string_lossy().into_owned();
+        MESSAGES.lock().push(message);
+    }
+
+    #[test]
+    fn test_debug_print() {
+        rust_set_debug_print_handler(Some(handler));
+        debug_print(format_args!("test: {}\0{}", 1, 2));
+        rust_set_debug_print_handler(None);
+        debug_print(format_args!("test: dropped"));
+
+        // Other tests can print messages concurrently.
+        let messages = MESSAGES.lock();
+        assert!(messages.iter().any(|message| message == "test: 1\\02"));
+        assert!(!messages.iter().any(|message| message == "test: dropped"));
+    }
+}
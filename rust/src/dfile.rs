@@ -1,702 +1,1712 @@
 use crate::fpattern::fpattern_match;
-use crate::platform_compat::{
-    compat_stricmp_ord, rust_compat_fopen, rust_compat_strdup, rust_get_file_size, COMPAT_MAX_PATH,
-};
-use libc::{
-    c_char, c_int, c_long, c_uchar, c_uint, fclose, fgetc, fread, free, fseek, malloc, size_t,
-    strcpy, ungetc, FILE, SEEK_CUR, SEEK_END, SEEK_SET,
-};
-use libz_sys::{
-    alloc_func, free_func, inflate, inflateEnd, inflateInit_, voidpf, z_stream, z_streamp, Bytef,
-    Z_NO_FLUSH, Z_OK,
-};
-use std::cell::RefCell;
-use std::ffi::{c_void, CString};
+use crate::inflater::{Inflater, INFLATER_WINDOW_BITS_RAW, INFLATER_WINDOW_BITS_ZLIB};
+use crate::lzss::LzssDecoder;
+use crate::memory_map::MemoryMap;
+use crate::platform_compat::{compat_native_path, compat_stricmp_ord};
+use libc::{c_char, c_int, c_long, size_t, SEEK_CUR, SEEK_END, SEEK_SET};
+use std::collections::HashMap;
+use std::ffi::{c_void, CStr, CString};
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, ErrorKind, Read, Seek, SeekFrom};
+#[cfg(not(target_family = "windows"))]
+use std::os::unix::fs::FileExt;
+#[cfg(target_family = "windows")]
+use std::os::windows::fs::FileExt;
+use std::path::{Path, PathBuf};
 use std::ptr::{null, null_mut};
-use std::rc::{Rc, Weak};
-use std::{mem, ptr};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::{error, fmt, mem, slice};
 
 // The size of decompression buffer for reading compressed [DFile]s.
-const DFILE_DECOMPRESSION_BUFFER_SIZE: u32 = 0x400;
+const DFILE_DECOMPRESSION_BUFFER_SIZE: usize = 0x400;
 
-// Specifies that [DFile] has unget compressed character.
-const DFILE_HAS_COMPRESSED_UNGETC: c_int = 0x10;
+// The size of read buffer of [DFile]s, which contains uncompressed data.
+const DFILE_BUFFER_SIZE: usize = 0x1000;
 
-// Specifies that [DFile] has unget character.
+// The distance (in terms of uncompressed data) between decompression
+// checkpoints of compressed [DFile]s.
 //
-// NOTE: There is an unused function at 0x4E5894 which ungets one character and
-// stores it in [ungotten]. Since that function is not used, this flag will
-// never be set.
-const DFILE_HAS_UNGETC: u32 = 0x01;
+// Every checkpoint keeps a copy of decompression state (which is about 40 KB
+// for zlib), so seeking backwards in compressed stream costs at most this much
+// decompression instead of decompressing everything from the beginning.
+const DFILE_CHECKPOINT_INTERVAL: u64 = 0x10000;
 
 // Specifies that [DFile] has reached end of stream.
-const DFILE_EOF: u32 = 0x02;
+const DFILE_EOF: c_int = 0x02;
 
 // Specifies that [DFile] is in error state.
 //
-// [dfileRewind] can be used to clear this flag.
-const DFILE_ERROR: u32 = 0x04;
+// [dfile_rewind] can be used to clear this flag.
+const DFILE_ERROR: c_int = 0x04;
 
 // Specifies that [DFile] was opened in text mode.
-const DFILE_TEXT: u32 = 0x08;
+const DFILE_TEXT: c_int = 0x08;
 
-#[derive(Clone)]
-struct DBaseEntry {
-    path: Option<CString>,
-    compressed: [i8; 1],
-    uncompressed_size: [i32; 1],
-    data_size: [i32; 1],
-    data_offset: [i32; 1],
+// Fallout 1 .DAT entry attributes.
+const DAT1_ENTRY_ATTRIBUTE_COMPRESSED: u32 = 0x40;
+
+// ZIP record signatures.
+const ZIP_END_OF_CENTRAL_DIRECTORY_SIGNATURE: u32 = 0x06054B50;
+const ZIP_CENTRAL_DIRECTORY_ENTRY_SIGNATURE: u32 = 0x02014B50;
+const ZIP_LOCAL_HEADER_SIGNATURE: u32 = 0x04034B50;
+
+// The sizes of fixed parts of ZIP records.
+const ZIP_END_OF_CENTRAL_DIRECTORY_SIZE: u64 = 22;
+const ZIP_CENTRAL_DIRECTORY_ENTRY_SIZE: usize = 46;
+const ZIP_LOCAL_HEADER_SIZE: usize = 30;
+
+// ZIP compression methods.
+const ZIP_METHOD_STORED: u16 = 0;
+const ZIP_METHOD_DEFLATED: u16 = 8;
+
+// ZIP entry flags.
+const ZIP_FLAG_ENCRYPTED: u16 = 0x01;
+
+// Specifies that [dbase_open] should memory-map .DAT files.
+static DBASE_MEMORY_MAPPING: AtomicBool = AtomicBool::new(false);
+
+#[no_mangle]
+pub extern "C" fn rust_dbase_set_memory_mapping(value: bool) {
+    DBASE_MEMORY_MAPPING.store(value, Ordering::Relaxed)
 }
 
-impl Default for DBaseEntry {
-    fn default() -> Self {
-        Self {
-            path: None,
-            compressed: [0 as i8; 1],
-            uncompressed_size: [0 as i32; 1],
-            data_size: [0 as i32; 1],
-            data_offset: [0 as i32; 1],
+pub fn dbase_memory_mapping() -> bool {
+    DBASE_MEMORY_MAPPING.load(Ordering::Relaxed)
+}
+
+// An error while opening or reading .DAT file.
+#[derive(Debug)]
+pub enum DatError {
+    // The underlying file cannot be read.
+    Io(io::Error),
+
+    // The footer (or the header in case of Fallout 1 .DAT) does not describe
+    // a .DAT file.
+    BadFooter,
+
+    // The entries table ends before all entries described by it are read.
+    TruncatedEntries,
+
+    // The entry at the given index (in the order of entries table) is
+    // malformed.
+    BadEntry { index: usize, reason: &'static str },
+
+    // There is no entry with the given path.
+    EntryNotFound(String),
+
+    // Compressed data of an entry cannot be decompressed.
+    Inflate(String),
+
+    // The file is a valid archive, but uses features which are not supported
+    // (such as ZIP64 or multi-volume ZIP archives).
+    Unsupported(&'static str),
+}
+
+impl fmt::Display for DatError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DatError::Io(err) => write!(f, "{}", err),
+            DatError::BadFooter => write!(f, "not a .DAT file"),
+            DatError::TruncatedEntries => write!(f, "entries table is truncated"),
+            DatError::BadEntry { index, reason } => write!(f, "entry #{}: {}", index, reason),
+            DatError::EntryNotFound(path) => write!(f, "entry not found: {}", path),
+            DatError::Inflate(message) => write!(f, "decompression failed: {}", message),
+            DatError::Unsupported(reason) => write!(f, "unsupported archive: {}", reason),
+        }
+    }
+}
+
+impl error::Error for DatError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            DatError::Io(err) => Some(err),
+            _ => None,
         }
     }
 }
 
+impl From<io::Error> for DatError {
+    fn from(err: io::Error) -> Self {
+        DatError::Io(err)
+    }
+}
+
+impl From<DatError> for io::Error {
+    fn from(err: DatError) -> Self {
+        match err {
+            DatError::Io(err) => err,
+            DatError::EntryNotFound(_) => io::Error::new(ErrorKind::NotFound, err),
+            _ => io::Error::new(ErrorKind::InvalidData, err),
+        }
+    }
+}
+
+// The layout of .DAT file.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DBaseFormat {
+    // Fallout 2 layout: little-endian entries table at the end of the file,
+    // zlib compression.
+    Dat2,
+
+    // Fallout 1 layout: big-endian directory blocks at the beginning of the
+    // file, LZSS compression.
+    Dat1,
+
+    // ZIP archive (stored and deflated entries only).
+    Zip,
+}
+
+// A file stored in .DAT file.
+#[derive(Clone, Debug)]
+pub struct DBaseEntry {
+    path: CString,
+    compressed: bool,
+    uncompressed_size: u32,
+    data_size: u32,
+    data_offset: u32,
+}
+
 impl DBaseEntry {
-    fn get_path_cstr(&self) -> *const c_char {
-        self.path.as_ref().map(|x| x.as_ptr()).unwrap_or(null())
+    // The path of the entry inside .DAT file (with backslashes as separators).
+    pub fn path(&self) -> &CStr {
+        &self.path
+    }
+
+    pub fn is_compressed(&self) -> bool {
+        self.compressed
+    }
+
+    pub fn uncompressed_size(&self) -> u32 {
+        self.uncompressed_size
+    }
+
+    // The size of (possibly compressed) entry data.
+    pub fn data_size(&self) -> u32 {
+        self.data_size
+    }
+
+    // The offset of entry data relative to the beginning of data section of
+    // .DAT file.
+    pub fn data_offset(&self) -> u32 {
+        self.data_offset
+    }
+
+    // The size of data occupied by the entry in data section (uncompressed
+    // entries are read up to their uncompressed size).
+    fn stored_size(&self) -> u32 {
+        if self.compressed {
+            self.data_size
+        } else {
+            self.uncompressed_size
+        }
+    }
+}
+
+// A problem found in an entry by [DBase::verify].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum DBaseEntryProblem {
+    // The data is not entirely inside data section.
+    OutOfBounds,
+
+    // The data overlaps with the data of the entry at the given index.
+    Overlap(usize),
+
+    // The data cannot be read or decompressed.
+    Unreadable(String),
+
+    // The size of decompressed data does not match uncompressed size of the
+    // entry.
+    //
+    // Decompression stops as soon as uncompressed size is exceeded, so in
+    // this case [actual] is only a lower bound.
+    SizeMismatch { actual: u64 },
+
+    // Compressed stream ends before the end of entry data.
+    TrailingData { consumed: u64 },
+}
+
+// The result of verification of a single entry.
+#[derive(Clone, Debug)]
+pub struct DBaseEntryReport {
+    // The index of the entry in [DBase::entries].
+    pub index: usize,
+
+    pub problems: Vec<DBaseEntryProblem>,
+}
+
+impl DBaseEntryReport {
+    pub fn is_ok(&self) -> bool {
+        self.problems.is_empty()
     }
 }
 
 // A representation of .DAT file.
 pub struct DBase {
     // The path of .DAT file that this structure represents.
-    path: Option<CString>,
+    path: PathBuf,
+
+    // The content of .DAT file, shared by all [DFile]s opened from this
+    // [DBase].
+    storage: DBaseStorage,
+
+    // The layout of .DAT file, which also defines compression method of
+    // compressed entries.
+    format: DBaseFormat,
 
     // The offset to the beginning of data section of .DAT file.
-    data_offset: i32,
+    data_offset: u64,
 
-    // The number of entries.
-    entries_length: [i32; 1],
+    // The size of data section of .DAT file.
+    data_section_size: u64,
 
-    // The array of entries.
-    entries: Option<Vec<DBaseEntry>>,
+    // The entries in the order of entries table.
+    entries: Vec<DBaseEntry>,
 
-    // The head of linked list of open file handles.
-    dfile_head: Option<Rc<RefCell<DFile>>>,
+    // The indices of [entries] by normalized path, see
+    // [dbase_normalize_path].
+    index: HashMap<Vec<u8>, usize>,
 }
 
-impl Default for DBase {
-    fn default() -> Self {
+impl DBase {
+    // Reads entries table of .DAT file at [path].
+    //
+    // The format is detected automatically: Fallout 2 .DAT is tried first
+    // (it's footer is cheap to validate), then ZIP archive, then Fallout 1
+    // .DAT.
+    pub fn open(path: &Path) -> Result<DBase, DatError> {
+        let file = Arc::new(File::open(path)?);
+        let file_size = file.metadata()?.len();
+        DBase::read(path, &file, file_size)
+    }
+
+    // Same as [open], but the entire .DAT file is memory-mapped.
+    //
+    // Uncompressed entries are then read straight from the mapping (see
+    // [DFile::mapped_data]), and compressed entries are decompressed from it
+    // without intermediate buffers.
+    pub fn open_mapped(path: &Path) -> Result<DBase, DatError> {
+        let file = Arc::new(File::open(path)?);
+        let file_size = file.metadata()?.len();
+        let mut dbase = DBase::read(path, &file, file_size)?;
+
+        // NOTE: Valid .DAT file is never empty, which cannot be mapped.
+        dbase.storage = DBaseStorage::Map(Arc::new(MemoryMap::new(&file, file_size)?));
+
+        Ok(dbase)
+    }
+
+    fn read(path: &Path, file: &Arc<File>, file_size: u64) -> Result<DBase, DatError> {
+        let mut dbase = match dbase_read_dat2(file, file_size) {
+            Ok(dbase) => dbase,
+            Err(err) => {
+                let readers: [DBaseReader; 2] = [dbase_read_zip, dbase_read_dat1];
+                let mut other_dbase = None;
+                for reader in readers {
+                    match reader(file, file_size) {
+                        Ok(dbase) => {
+                            other_dbase = Some(dbase);
+                            break;
+                        }
+                        Err(DatError::BadFooter) => {}
+                        Err(err) => return Err(err),
+                    }
+                }
+
+                // Report the reason why it's not a Fallout 2 .DAT, unless it
+                // looks like one of the other formats.
+                other_dbase.ok_or(err)?
+            }
+        };
+
+        dbase.path = path.to_path_buf();
+        dbase.index = dbase_build_index(&dbase.entries);
+
+        Ok(dbase)
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn format(&self) -> DBaseFormat {
+        self.format
+    }
+
+    // Specifies that .DAT file is memory-mapped, see [open_mapped].
+    pub fn is_mapped(&self) -> bool {
+        matches!(self.storage, DBaseStorage::Map(_))
+    }
+
+    // Returns entries in the order of entries table (which is sorted by path
+    // in well-formed .DAT files).
+    pub fn entries(&self) -> slice::Iter<'_, DBaseEntry> {
+        self.entries.iter()
+    }
+
+    // Finds entry by path (case-insensitive, either slashes or backslashes
+    // can be used as separators).
+    pub fn entry(&self, path: &str) -> Option<&DBaseEntry> {
+        self.find_entry(path.as_bytes())
+            .map(|index| &self.entries[index])
+    }
+
+    // Opens entry at [path] (see [entry]) for reading.
+    pub fn open_entry(&self, path: &str) -> Result<DFile, DatError> {
+        let index = self
+            .find_entry(path.as_bytes())
+            .ok_or_else(|| DatError::EntryNotFound(path.to_string()))?;
+        self.open_entry_at(index)
+    }
+
+    fn find_entry(&self, path: &[u8]) -> Option<usize> {
+        self.index.get(&dbase_normalize_path(path)).copied()
+    }
+
+    fn open_entry_at(&self, index: usize) -> Result<DFile, DatError> {
+        let entry = self.entries[index].clone();
+
+        let decoder = if !entry.compressed {
+            DFileDecoder::Stored
+        } else if self.format == DBaseFormat::Dat1 {
+            DFileDecoder::Lzss(Box::new(LzssDecoder::new()))
+        } else {
+            DFileDecoder::Zlib(dbase_new_inflater(self.format)?)
+        };
+
+        // Uncompressed data is read directly into the read buffer, so it's
+        // larger than decompression buffer.
+        let buffer_size = if entry.compressed {
+            DFILE_DECOMPRESSION_BUFFER_SIZE
+        } else {
+            DFILE_BUFFER_SIZE
+        };
+
+        let source = DFileSource::new(
+            self.storage.clone(),
+            self.data_offset + entry.data_offset as u64,
+            entry.stored_size() as u64,
+            buffer_size,
+        );
+
+        Ok(DFile {
+            entry,
+            source,
+            decoder,
+            buffer: Vec::new(),
+            buffer_position: 0,
+            buffer_length: 0,
+            decoded: 0,
+            checkpoints: Vec::new(),
+            flags: 0,
+        })
+    }
+
+    // Checks every entry: it's data must be inside data section, must not
+    // overlap with other entries, and must decompress into exactly
+    // uncompressed size consuming exactly data size of compressed data.
+    //
+    // Returns a report for every entry (in the order of [entries]).
+    //
+    // NOTE: [open] rejects entries outside of data section, the check is here
+    // so that the report is complete on it's own.
+    pub fn verify(&self) -> Vec<DBaseEntryReport> {
+        let mut reports: Vec<_> = (0..self.entries.len())
+            .map(|index| DBaseEntryReport {
+                index,
+                problems: Vec::new(),
+            })
+            .collect();
+
+        // Find overlaps by sweeping entries ordered by data offset, while
+        // tracking entries which have not ended yet (every one of them
+        // overlaps with the next entry).
+        let mut ranges: Vec<_> = self
+            .entries
+            .iter()
+            .enumerate()
+            .filter(|(_, entry)| entry.stored_size() != 0)
+            .map(|(index, entry)| {
+                let start = entry.data_offset as u64;
+                (start, start + entry.stored_size() as u64, index)
+            })
+            .collect();
+        ranges.sort_unstable();
+
+        let mut active: Vec<(u64, usize)> = Vec::new();
+        for (start, end, index) in ranges {
+            if end > self.data_section_size {
+                reports[index].problems.push(DBaseEntryProblem::OutOfBounds);
+            }
+
+            active.retain(|&(active_end, _)| active_end > start);
+            for &(_, active_index) in &active {
+                reports[index]
+                    .problems
+                    .push(DBaseEntryProblem::Overlap(active_index));
+                reports[active_index]
+                    .problems
+                    .push(DBaseEntryProblem::Overlap(index));
+            }
+            active.push((end, index));
+        }
+
+        for (index, entry) in self.entries.iter().enumerate() {
+            let problems = &mut reports[index].problems;
+            if let Err(err) = self.verify_entry_data(entry, problems) {
+                problems.push(DBaseEntryProblem::Unreadable(err.to_string()));
+            }
+        }
+
+        reports
+    }
+
+    // Decompresses entry data (without keeping it) and checks it's sizes.
+    fn verify_entry_data(
+        &self,
+        entry: &DBaseEntry,
+        problems: &mut Vec<DBaseEntryProblem>,
+    ) -> Result<(), DatError> {
+        let data_size = entry.stored_size() as u64;
+        let mut source = DFileSource::new(
+            self.storage.clone(),
+            self.data_offset + entry.data_offset as u64,
+            data_size,
+            DFILE_BUFFER_SIZE,
+        );
+
+        if !entry.compressed {
+            loop {
+                let length = source.fill_buf()?.len();
+                if length == 0 {
+                    return Ok(());
+                }
+                source.consume(length);
+            }
+        }
+
+        let uncompressed_size = entry.uncompressed_size as u64;
+        let mut buffer = vec![0u8; DFILE_BUFFER_SIZE];
+        let mut decoded = 0;
+
+        if self.format == DBaseFormat::Dat1 {
+            let mut decoder = LzssDecoder::new();
+            let mut source_error = None;
+            while decoded <= uncompressed_size {
+                let bytes_decoded = decoder.decode(&mut buffer, || match source.next_byte() {
+                    Ok(ch) => ch,
+                    Err(err) => {
+                        source_error = Some(err);
+                        None
+                    }
+                });
+                decoded += bytes_decoded as u64;
+
+                if bytes_decoded < buffer.len() {
+                    break;
+                }
+            }
+
+            if let Some(err) = source_error {
+                return Err(err.into());
+            }
+        } else {
+            let mut inflater = dbase_new_inflater(self.format)?;
+            while decoded <= uncompressed_size {
+                let input = source.fill_buf()?;
+                let input_length = input.len();
+                let progress = inflater
+                    .inflate(input, &mut buffer)
+                    .map_err(DatError::Inflate)?;
+                source.consume(progress.consumed);
+                decoded += progress.produced as u64;
+
+                if progress.finished {
+                    break;
+                }
+
+                if input_length == 0 && progress.produced == 0 {
+                    return Err(DatError::Inflate(
+                        "unexpected end of compressed data".to_string(),
+                    ));
+                }
+            }
+        }
+
+        if decoded != uncompressed_size {
+            problems.push(DBaseEntryProblem::SizeMismatch { actual: decoded });
+        } else if source.position() < data_size {
+            problems.push(DBaseEntryProblem::TrailingData {
+                consumed: source.position(),
+            });
+        }
+
+        Ok(())
+    }
+}
+
+// Converts entry path into the key of [DBase::index].
+//
+// The key is uppercased, uses backslashes as separators, and has no empty or
+// `.` components, so `art/critters//./hmjmpsaa.frm` and
+// `\ART\CRITTERS\HMJMPSAA.FRM` are the same key.
+pub(crate) fn dbase_normalize_path(path: &[u8]) -> Vec<u8> {
+    let mut key = Vec::with_capacity(path.len());
+    for component in path.split(|&ch| ch == b'/' || ch == b'\\') {
+        if component.is_empty() || component == b"." {
+            continue;
+        }
+
+        if !key.is_empty() {
+            key.push(b'\\');
+        }
+        key.extend(component.iter().map(u8::to_ascii_uppercase));
+    }
+    key
+}
+
+// Builds lookup table of [entries].
+//
+// NOTE: Entries table is not required to be sorted (some third-party packers
+// produce unsorted tables), and can contain paths which are the same after
+// normalization. The first such entry wins.
+fn dbase_build_index(entries: &[DBaseEntry]) -> HashMap<Vec<u8>, usize> {
+    let mut index = HashMap::with_capacity(entries.len());
+    for (entry_index, entry) in entries.iter().enumerate() {
+        index
+            .entry(dbase_normalize_path(entry.path.as_bytes()))
+            .or_insert(entry_index);
+    }
+    index
+}
+
+// Creates decompression stream for compressed entries of Fallout 2 .DAT
+// (zlib wrapped data) or ZIP archive (raw deflate data).
+fn dbase_new_inflater(format: DBaseFormat) -> Result<Inflater, DatError> {
+    let window_bits = if format == DBaseFormat::Zip {
+        INFLATER_WINDOW_BITS_RAW
+    } else {
+        INFLATER_WINDOW_BITS_ZLIB
+    };
+
+    Inflater::new(window_bits)
+        .ok_or_else(|| DatError::Inflate("cannot initialize decompression stream".to_string()))
+}
+
+// Reads exactly [buf.len()] bytes at [offset] of [file].
+//
+// Positional reads do not depend on (and do not change) file position, so the
+// same file can be read by any number of [DFile]s.
+#[cfg(not(target_family = "windows"))]
+fn dbase_read_at(file: &File, buf: &mut [u8], offset: u64) -> io::Result<()> {
+    file.read_exact_at(buf, offset)
+}
+
+// NOTE: Windows version of positional read moves file position, which is
+// fine since nothing depends on it.
+#[cfg(target_family = "windows")]
+fn dbase_read_at(file: &File, mut buf: &mut [u8], mut offset: u64) -> io::Result<()> {
+    while !buf.is_empty() {
+        match file.seek_read(buf, offset) {
+            Ok(0) => return Err(io::Error::from(ErrorKind::UnexpectedEof)),
+            Ok(bytes_read) => {
+                buf = &mut buf[bytes_read..];
+                offset += bytes_read as u64;
+            }
+            Err(err) if err.kind() == ErrorKind::Interrupted => {}
+            Err(err) => return Err(err),
+        }
+    }
+
+    Ok(())
+}
+
+// The content of .DAT file.
+#[derive(Clone)]
+enum DBaseStorage {
+    // The file is only read with positional reads (see [dbase_read_at]).
+    File(Arc<File>),
+
+    // The entire file is memory-mapped.
+    Map(Arc<MemoryMap>),
+}
+
+// The data of an entry as it's stored in .DAT file (i.e. compressed data for
+// compressed entries).
+//
+// When .DAT file is memory-mapped the data is never copied, the entire entry
+// is served as one buffer directly from the mapping.
+struct DFileSource {
+    // The content of .DAT file shared with [DBase].
+    storage: DBaseStorage,
+
+    // The offset of entry data from the beginning of .DAT file.
+    data_offset: u64,
+
+    data_size: u64,
+
+    // The number of bytes read so far from [storage] (including buffered
+    // ones). The range is 0..data_size.
+    //
+    // Memory-mapped data is not buffered, so this is also the position.
+    bytes_read: u64,
+
+    // The buffer of data read from [storage] (only used when .DAT file is not
+    // memory-mapped).
+    buffer: Vec<u8>,
+    buffer_position: usize,
+    buffer_length: usize,
+}
+
+impl DFileSource {
+    fn new(storage: DBaseStorage, data_offset: u64, data_size: u64, buffer_size: usize) -> Self {
+        let buffer_size = match storage {
+            DBaseStorage::File(_) => buffer_size,
+            DBaseStorage::Map(_) => 0,
+        };
+
         Self {
-            path: None,
-            data_offset: 0,
-            entries_length: [0 as i32; 1],
-            entries: None,
-            dfile_head: None,
+            storage,
+            data_offset,
+            data_size,
+            bytes_read: 0,
+            buffer: vec![0u8; buffer_size],
+            buffer_position: 0,
+            buffer_length: 0,
+        }
+    }
+
+    // The position in entry data of the next byte to be consumed.
+    fn position(&self) -> u64 {
+        self.bytes_read - (self.buffer_length - self.buffer_position) as u64
+    }
+
+    // The entire entry data, when .DAT file is memory-mapped.
+    fn mapped_data(&self) -> Option<&[u8]> {
+        match &self.storage {
+            DBaseStorage::File(_) => None,
+            DBaseStorage::Map(map) => {
+                // NOTE: Entries are checked to be inside of data section in
+                // [dbase_make_entry].
+                let start = self.data_offset as usize;
+                Some(&map[start..start + self.data_size as usize])
+            }
+        }
+    }
+
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        let file = match &self.storage {
+            DBaseStorage::File(file) => file,
+            DBaseStorage::Map(_) => {
+                let bytes_read = self.bytes_read as usize;
+                let data = self.mapped_data().unwrap_or_default();
+                return Ok(&data[bytes_read..]);
+            }
+        };
+
+        if self.buffer_position == self.buffer_length {
+            let bytes_to_read = (self.data_size - self.bytes_read).min(self.buffer.len() as u64);
+            dbase_read_at(
+                file,
+                &mut self.buffer[..bytes_to_read as usize],
+                self.data_offset + self.bytes_read,
+            )?;
+            self.bytes_read += bytes_to_read;
+            self.buffer_position = 0;
+            self.buffer_length = bytes_to_read as usize;
+        }
+
+        Ok(&self.buffer[self.buffer_position..self.buffer_length])
+    }
+
+    fn consume(&mut self, amount: usize) {
+        match self.storage {
+            DBaseStorage::File(_) => {
+                self.buffer_position = (self.buffer_position + amount).min(self.buffer_length);
+            }
+            DBaseStorage::Map(_) => {
+                self.bytes_read = (self.bytes_read + amount as u64).min(self.data_size);
+            }
+        }
+    }
+
+    fn next_byte(&mut self) -> io::Result<Option<u8>> {
+        let ch = self.fill_buf()?.first().copied();
+        if ch.is_some() {
+            self.consume(1);
+        }
+        Ok(ch)
+    }
+
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        // Large reads bypass the buffer.
+        if let DBaseStorage::File(file) = &self.storage {
+            if self.buffer_position == self.buffer_length && buf.len() >= self.buffer.len() {
+                let bytes_to_read = (self.data_size - self.bytes_read).min(buf.len() as u64);
+                dbase_read_at(
+                    file,
+                    &mut buf[..bytes_to_read as usize],
+                    self.data_offset + self.bytes_read,
+                )?;
+                self.bytes_read += bytes_to_read;
+                return Ok(bytes_to_read as usize);
+            }
         }
+
+        let available = self.fill_buf()?;
+        let bytes_read = available.len().min(buf.len());
+        buf[..bytes_read].copy_from_slice(&available[..bytes_read]);
+        self.consume(bytes_read);
+        Ok(bytes_read)
+    }
+
+    // Moves to [position] in entry data.
+    fn seek(&mut self, position: u64) {
+        let buffer_start = self.bytes_read - self.buffer_length as u64;
+        if position >= buffer_start && position <= self.bytes_read {
+            self.buffer_position = (position - buffer_start) as usize;
+            return;
+        }
+
+        self.bytes_read = position;
+        self.buffer_position = 0;
+        self.buffer_length = 0;
     }
 }
 
-impl DBase {
-    fn get_path_cstr(&self) -> *const c_char {
-        self.path.as_ref().map(|x| x.as_ptr()).unwrap_or(null())
+enum DFileDecoder {
+    // Entry is not compressed.
+    Stored,
+
+    // Fallout 2 or ZIP compressed entry.
+    Zlib(Inflater),
+
+    // Fallout 1 compressed entry.
+    Lzss(Box<LzssDecoder>),
+}
+
+impl DFileDecoder {
+    fn try_clone(&self) -> Option<Self> {
+        match self {
+            DFileDecoder::Stored => Some(DFileDecoder::Stored),
+            DFileDecoder::Zlib(inflater) => inflater.try_clone().map(DFileDecoder::Zlib),
+            DFileDecoder::Lzss(decoder) => Some(DFileDecoder::Lzss(decoder.clone())),
+        }
+    }
+
+    // Restarts decompression from the beginning.
+    fn reset(&mut self) -> bool {
+        match self {
+            DFileDecoder::Stored => true,
+            DFileDecoder::Zlib(inflater) => inflater.reset(),
+            DFileDecoder::Lzss(decoder) => {
+                decoder.reset();
+                true
+            }
+        }
     }
 }
 
+// A point in compressed stream where decompression can be resumed from.
+struct DFileCheckpoint {
+    // The position in uncompressed data.
+    position: u64,
+
+    // The position in compressed data.
+    compressed_position: u64,
+
+    // The copy of [DFile::decoder].
+    decoder: DFileDecoder,
+}
+
 // A handle to open entry in .DAT file.
+//
+// Reading is buffered, so there is no need to wrap it into [BufReader].
 pub struct DFile {
-    dbase: Weak<RefCell<DBase>>,
-    entry: *mut DBaseEntry,
-    flags: c_int,
+    entry: DBaseEntry,
 
-    // The stream of .DAT file opened for reading in binary mode.
-    //
-    // This stream is not shared across open handles. Instead every [DFile]
-    // opens it's own stream via [fopen], which is then closed via [fclose] in
-    // [dfileClose].
-    stream: *mut FILE,
+    source: DFileSource,
 
-    // The inflate stream used to decompress data.
-    //
-    // This value is NULL if entry is not compressed.
-    decompression_stream: z_streamp,
+    decoder: DFileDecoder,
 
-    // The decompression buffer of size [DFILE_DECOMPRESSION_BUFFER_SIZE].
-    //
-    // This value is NULL if entry is not compressed.
-    decompression_buffer: *mut c_uchar,
+    // The buffer of decompressed data (only used for compressed entries).
+    buffer: Vec<u8>,
+    buffer_position: usize,
+    buffer_length: usize,
 
-    // The last ungot character.
+    // The number of bytes decompressed so far.
     //
-    // See [DFILE_HAS_UNGETC] notes.
-    ungotten: c_int,
+    // This value is only used when reading compressed streams. The range is
+    // 0..entry.uncompressed_size.
+    decoded: u64,
 
-    // The last ungot compressed character.
+    // The decompression checkpoints recorded so far, every
+    // [DFILE_CHECKPOINT_INTERVAL] bytes of uncompressed data.
     //
-    // This value is used when reading compressed text streams to detect
-    // Windows end of line sequence \r\n.
-    compressed_ungotten: c_int,
+    // Checkpoints are recorded while reading (including reading done while
+    // seeking), and used to speed up seeking backwards.
+    checkpoints: Vec<DFileCheckpoint>,
+
+    // The state of C-style API, see [DFILE_EOF], [DFILE_ERROR], and
+    // [DFILE_TEXT].
+    flags: c_int,
+}
+
+impl DFile {
+    pub fn entry(&self) -> &DBaseEntry {
+        &self.entry
+    }
+
+    // The size of uncompressed data.
+    pub fn size(&self) -> u64 {
+        self.entry.uncompressed_size as u64
+    }
 
-    // The number of bytes read so far from compressed stream.
+    // The entire data of uncompressed entry, when .DAT file is memory-mapped
+    // (see [DBase::open_mapped]).
     //
-    // This value is only used when reading compressed streams. The range is
-    // 0..entry->dataSize.
-    compressed_bytes_read: c_int,
+    // This does not depend on (and does not change) the position in stream.
+    pub fn mapped_data(&self) -> Option<&[u8]> {
+        match self.decoder {
+            DFileDecoder::Stored => self.source.mapped_data(),
+            _ => None,
+        }
+    }
 
     // The position in read stream.
     //
     // This value is tracked in terms of uncompressed data (even in compressed
-    // streams). The range is 0..entry->uncompressedSize.
-    position: c_long,
+    // streams).
+    pub fn position(&self) -> u64 {
+        match self.decoder {
+            DFileDecoder::Stored => self.source.position(),
+            _ => self.decoded - (self.buffer_length - self.buffer_position) as u64,
+        }
+    }
 
-    // Next [DFile] in linked list.
+    // Decompresses next chunk of data into [output].
     //
-    // [DFile]s are stored in [DBase] in reverse order, so it's actually a
-    // previous opened file, not next.
-    next: Option<Rc<RefCell<DFile>>>,
-}
+    // Decompression stops on every checkpoint boundary, so that decompression
+    // state can be saved. Returns the number of decompressed bytes, which is
+    // zero only at the end of data.
+    fn decode(&mut self, output: &mut [u8]) -> Result<usize, DatError> {
+        let size = self.size();
+        if self.decoded >= size || output.is_empty() {
+            return Ok(0);
+        }
 
-impl Drop for DFile {
-    fn drop(&mut self) {
-        unsafe {
-            if (*self.entry).compressed[0] == 1 {
-                if inflateEnd(self.decompression_stream) != Z_OK {
-                    // rc = -1;
+        // NOTE: Boundaries are computed from current position rather than
+        // from the number of checkpoints, since saving checkpoint can fail
+        // (see [DFileDecoder::try_clone]). Such checkpoint is skipped, but
+        // later ones are still saved.
+        let last_checkpoint_position = self
+            .checkpoints
+            .last()
+            .map_or(0, |checkpoint| checkpoint.position);
+        if self.decoded.is_multiple_of(DFILE_CHECKPOINT_INTERVAL)
+            && self.decoded > last_checkpoint_position
+        {
+            self.save_checkpoint();
+        }
+
+        let next_boundary =
+            (self.decoded / DFILE_CHECKPOINT_INTERVAL + 1) * DFILE_CHECKPOINT_INTERVAL;
+
+        let chunk_size = (output.len() as u64)
+            .min(size - self.decoded)
+            .min(next_boundary - self.decoded) as usize;
+        let output = &mut output[..chunk_size];
+
+        let bytes_decoded = match &mut self.decoder {
+            DFileDecoder::Stored => 0,
+            DFileDecoder::Zlib(inflater) => {
+                let mut bytes_decoded = 0;
+                while bytes_decoded < chunk_size {
+                    let input = self.source.fill_buf()?;
+                    let progress = inflater
+                        .inflate(input, &mut output[bytes_decoded..])
+                        .map_err(DatError::Inflate)?;
+                    self.source.consume(progress.consumed);
+                    bytes_decoded += progress.produced;
+
+                    if progress.finished || (progress.consumed == 0 && progress.produced == 0) {
+                        break;
+                    }
                 }
+                bytes_decoded
             }
+            DFileDecoder::Lzss(decoder) => {
+                let source = &mut self.source;
+                let mut source_error = None;
+                let bytes_decoded = decoder.decode(output, || match source.next_byte() {
+                    Ok(ch) => ch,
+                    Err(err) => {
+                        source_error = Some(err);
+                        None
+                    }
+                });
+
+                if let Some(err) = source_error {
+                    return Err(err.into());
+                }
 
-            if self.decompression_stream != null_mut() {
-                free(self.decompression_stream as *mut c_void);
+                bytes_decoded
             }
+        };
+
+        self.decoded += bytes_decoded as u64;
+
+        if bytes_decoded < chunk_size {
+            return Err(DatError::Inflate(
+                "unexpected end of compressed data".to_string(),
+            ));
+        }
+
+        Ok(bytes_decoded)
+    }
+
+    // Saves current decompression state as a new checkpoint.
+    fn save_checkpoint(&mut self) {
+        let Some(decoder) = self.decoder.try_clone() else {
+            return;
+        };
+
+        self.checkpoints.push(DFileCheckpoint {
+            position: self.decoded,
+            compressed_position: self.source.position(),
+            decoder,
+        });
+    }
 
-            if self.decompression_buffer != null_mut() {
-                free(self.decompression_buffer as *mut c_void);
+    // Restores decompression state saved in checkpoint at [index].
+    fn restore_checkpoint(&mut self, index: usize) -> Result<(), DatError> {
+        let checkpoint = &self.checkpoints[index];
+
+        self.decoder = checkpoint
+            .decoder
+            .try_clone()
+            .ok_or_else(|| DatError::Inflate("cannot copy decompression stream".to_string()))?;
+        self.source.seek(checkpoint.compressed_position);
+        self.decoded = checkpoint.position;
+
+        Ok(())
+    }
+
+    // Restarts decompression from the beginning.
+    fn rewind(&mut self) -> Result<(), DatError> {
+        if !self.decoder.reset() {
+            return Err(DatError::Inflate(
+                "cannot reset decompression stream".to_string(),
+            ));
+        }
+
+        self.source.seek(0);
+        self.decoded = 0;
+
+        Ok(())
+    }
+
+    // Moves to [position] (which must not be past the end of data).
+    fn seek_to(&mut self, position: u64) -> Result<(), DatError> {
+        if let DFileDecoder::Stored = self.decoder {
+            self.source.seek(position);
+            return Ok(());
+        }
+
+        // Target position is within decompressed data which is still in the
+        // buffer.
+        let buffer_start = self.decoded - self.buffer_length as u64;
+        if position >= buffer_start && position <= self.decoded {
+            self.buffer_position = (position - buffer_start) as usize;
+            return Ok(());
+        }
+
+        self.buffer_position = 0;
+        self.buffer_length = 0;
+
+        // We cannot go backwards in compressed stream, so we either start from
+        // the closest checkpoint (or current position if it's closer), or from
+        // the beginning.
+        let checkpoint_index = self
+            .checkpoints
+            .iter()
+            .rposition(|checkpoint| checkpoint.position <= position);
+
+        let checkpoint_position = checkpoint_index
+            .map(|index| self.checkpoints[index].position)
+            .unwrap_or(0);
+
+        if position < self.decoded || checkpoint_position > self.decoded {
+            match checkpoint_index {
+                Some(index) => self.restore_checkpoint(index)?,
+                None => self.rewind()?,
             }
+        }
 
-            if self.stream != null_mut() {
-                fclose(self.stream);
+        // Skip data until we reach specified position.
+        let mut buffer = self.take_buffer();
+        let mut result = Ok(());
+        while self.decoded < position {
+            let chunk_size = (position - self.decoded).min(buffer.len() as u64) as usize;
+            if let Err(err) = self.decode(&mut buffer[..chunk_size]) {
+                result = Err(err);
+                break;
             }
         }
+        self.buffer = buffer;
+
+        result
     }
-}
 
-impl DFile {
-    fn new(entry: *mut DBaseEntry, stream: *mut FILE) -> Self {
-        Self {
-            dbase: Weak::new(),
-            entry,
-            flags: 0,
-            stream,
-            decompression_stream: z_streamp::from(null_mut()),
-            decompression_buffer: null_mut(),
-            ungotten: 0,
-            compressed_ungotten: 0,
-            compressed_bytes_read: 0,
-            position: 0,
-            next: None,
+    // Takes the buffer of decompressed data (allocating it if needed) so that
+    // it can be filled via [decode].
+    fn take_buffer(&mut self) -> Vec<u8> {
+        let buffer = mem::take(&mut self.buffer);
+        if buffer.is_empty() {
+            vec![0u8; DFILE_BUFFER_SIZE]
+        } else {
+            buffer
         }
     }
 }
 
-pub struct DFileFindData {
-    // The name of file that was found during previous search.
-    pub file_name: [c_char; COMPAT_MAX_PATH],
+impl Read for DFile {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if let DFileDecoder::Stored = self.decoder {
+            return self.source.read(buf);
+        }
 
-    // The pattern to search.
-    //
-    // This value is set automatically when [dbaseFindFirstEntry] succeeds so
-    // that subsequent calls to [dbaseFindNextEntry] know what to look for.
-    pattern: [c_char; COMPAT_MAX_PATH],
+        // Large reads are decompressed directly.
+        if self.buffer_position == self.buffer_length && buf.len() >= DFILE_BUFFER_SIZE {
+            self.buffer_position = 0;
+            self.buffer_length = 0;
+            return Ok(self.decode(buf)?);
+        }
 
-    // The index of entry that was found during previous search.
-    //
-    // This value is set automatically when [dbaseFindFirstEntry] and
-    // [dbaseFindNextEntry] succeed so that subsequent calls to [dbaseFindNextEntry]
-    // knows where to start search from.
-    index: c_int,
+        let available = self.fill_buf()?;
+        let bytes_read = available.len().min(buf.len());
+        buf[..bytes_read].copy_from_slice(&available[..bytes_read]);
+        self.consume(bytes_read);
+        Ok(bytes_read)
+    }
 }
 
-impl Default for DFileFindData {
-    fn default() -> Self {
-        Self {
-            file_name: [0 as c_char; COMPAT_MAX_PATH],
-            pattern: [0 as c_char; COMPAT_MAX_PATH],
-            index: 0,
+impl BufRead for DFile {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        if let DFileDecoder::Stored = self.decoder {
+            return self.source.fill_buf();
         }
-    }
-}
 
-pub unsafe fn dfile_remove_node(stream: &DFile) -> c_int {
-    // Loop thru open file handles and find previous to remove current handle
-    // from linked list.
-    //
-    // NOTE: Compiled code is slightly different.
-    let dbase = stream.dbase.upgrade().expect("");
-    let mut curr = dbase.borrow().dfile_head.clone();
-    let mut prev: Option<Rc<RefCell<DFile>>> = None;
-    while curr.is_some() {
-        if ptr::eq(curr.as_ref().expect("").as_ptr(), stream) {
-            break;
+        if self.buffer_position == self.buffer_length {
+            let mut buffer = self.take_buffer();
+            let result = self.decode(&mut buffer);
+            self.buffer = buffer;
+            self.buffer_position = 0;
+            self.buffer_length = result?;
         }
 
-        prev = curr.clone();
-        curr = curr.expect("").borrow().next.clone();
+        Ok(&self.buffer[self.buffer_position..self.buffer_length])
     }
 
-    if curr.is_some() {
-        if prev.is_none() {
-            dbase.borrow_mut().dfile_head = stream.next.clone();
-        } else {
-            prev.expect("").borrow_mut().next = stream.next.clone();
+    fn consume(&mut self, amount: usize) {
+        if let DFileDecoder::Stored = self.decoder {
+            self.source.consume(amount);
+            return;
         }
+
+        self.buffer_position = (self.buffer_position + amount).min(self.buffer_length);
     }
+}
 
-    0
+impl Seek for DFile {
+    // Seeking past the end of data is not supported.
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let position = match pos {
+            SeekFrom::Start(offset) => Some(offset),
+            SeekFrom::End(offset) => self.size().checked_add_signed(offset),
+            SeekFrom::Current(offset) => self.position().checked_add_signed(offset),
+        };
+
+        let Some(position) = position.filter(|&position| position <= self.size()) else {
+            return Err(io::Error::new(
+                ErrorKind::InvalidInput,
+                "seek position is outside of entry",
+            ));
+        };
+
+        self.seek_to(position)?;
+
+        Ok(position)
+    }
+
+    fn stream_position(&mut self) -> io::Result<u64> {
+        Ok(self.position())
+    }
+}
+
+#[derive(Default)]
+pub(crate) struct DFileFindData<'a> {
+    // The name of file that was found during previous search.
+    pub file_name: &'a CStr,
+
+    // The pattern to search.
+    //
+    // This value is set automatically when [dbaseFindFirstEntry] succeeds so
+    // that subsequent calls to [dbaseFindNextEntry] know what to look for.
+    pattern: CString,
+
+    // The index of entry that was found during previous search.
+    //
+    // This value is set automatically when [dbaseFindFirstEntry] and
+    // [dbaseFindNextEntry] succeed so that subsequent calls to [dbaseFindNextEntry]
+    // knows where to start search from.
+    index: usize,
 }
 
-pub unsafe fn rust_dfile_open(
-    dbase_rc: &Rc<RefCell<DBase>>,
+pub(crate) unsafe fn rust_dfile_open(
+    dbase: &DBase,
     file_path: *const c_char,
     mode: *const c_char,
-) -> Option<Rc<RefCell<DFile>>> {
+) -> Option<DFile> {
     assert_ne!(file_path, null()); // dfile.c, 296
     assert_ne!(mode, null()); // dfile.c, 297
 
-    let dbase = &mut dbase_rc.as_ref().borrow_mut();
-
-    let entries = (*dbase).entries.as_mut().expect("");
-    let optional_entry = entries
-        .binary_search_by(|a| compat_stricmp_ord(a.get_path_cstr(), file_path))
-        .map(|i| &mut entries[i]);
-
-    if optional_entry.is_err() {
-        return None;
-    }
-
-    let entry = optional_entry.expect("valid entry") as *mut DBaseEntry;
+    let index = dbase.find_entry(CStr::from_ptr(file_path).to_bytes())?;
 
     if *mode != 'r' as c_char {
         return None;
     }
 
-    // Open stream to .DAT file.
-    let rb = CString::new("rb").expect("valid string");
-    let stream = rust_compat_fopen(dbase.get_path_cstr(), rb.as_ptr());
-    if stream == null_mut() {
-        return None;
+    let mut dfile = dbase.open_entry_at(index).ok()?;
+
+    if *mode.offset(1) == 't' as c_char {
+        dfile.flags |= DFILE_TEXT;
     }
 
-    let mut dfile = DFile::new(entry, stream);
+    Some(dfile)
+}
 
-    // Relocate stream to the beginning of data for specified entry.
-    if fseek(
-        dfile.stream,
-        ((*dbase).data_offset + (*entry).data_offset[0]) as c_long,
-        SEEK_SET,
-    ) != 0
-    {
-        return None;
-    }
+fn dfile_read_byte(stream: &mut DFile) -> Option<u8> {
+    let ch = *stream.fill_buf().ok()?.first()?;
+    stream.consume(1);
+    Some(ch)
+}
 
-    if (*entry).compressed[0] == 1 {
-        // Entry is compressed, setup decompression stream and decompression
-        // buffer. This step is not needed when previous instance of dfile is
-        // passed via parameter, which might already have stream and
-        // buffer allocated.
-        if dfile.decompression_stream == null_mut() {
-            dfile.decompression_stream = malloc(mem::size_of::<z_stream>()) as z_streamp;
-            if dfile.decompression_stream == null_mut() {
-                return None;
-            }
+// 0x4E5F9C
+fn dfile_read_char_internal(stream: &mut DFile) -> c_int {
+    let Some(mut ch) = dfile_read_byte(stream) else {
+        return -1;
+    };
 
-            dfile.decompression_buffer =
-                malloc(DFILE_DECOMPRESSION_BUFFER_SIZE as size_t) as *mut c_uchar;
-            if dfile.decompression_buffer == null_mut() {
-                return None;
-            }
+    if (stream.flags & DFILE_TEXT) != 0 && ch == b'\r' {
+        // This is a text stream, attempt to detect \r\n sequence.
+        if let Ok([b'\n', ..]) = stream.fill_buf() {
+            stream.consume(1);
+            ch = b'\n';
         }
+    }
 
-        (*dfile.decompression_stream).zalloc = mem::transmute::<*const c_void, alloc_func>(null());
-        (*dfile.decompression_stream).zfree = mem::transmute::<*const c_void, free_func>(null());
-        (*dfile.decompression_stream).opaque = mem::transmute::<*const c_void, voidpf>(null());
-        (*dfile.decompression_stream).next_in = dfile.decompression_buffer;
-        (*dfile.decompression_stream).avail_in = 0;
+    ch as c_int
+}
 
-        // Used ZLIB_VERSION
-        let version = CString::new("1.2.11").expect("valid string");
-        if inflateInit_(
-            dfile.decompression_stream,
-            version.as_ptr(),
-            mem::size_of::<z_stream>() as c_int,
-        ) != Z_OK
-        {
-            return None;
-        }
-    } else {
-        // Entry is not compressed, there is no need to keep decompression
-        // stream and decompression buffer (in case [dfile] was passed via
-        // parameter).
-        if dfile.decompression_stream != null_mut() {
-            free(dfile.decompression_stream as *mut c_void);
-            dfile.decompression_stream = null_mut();
-        }
+pub(crate) unsafe fn dbase_open(file_path: *const c_char) -> Option<DBase> {
+    assert_ne!(file_path, null()); // "filename", "dfile.c", 74
 
-        if dfile.decompression_buffer != null_mut() {
-            free(dfile.decompression_buffer as *mut c_void);
-            dfile.decompression_buffer = null_mut();
+    let path = compat_native_path(file_path);
+    if dbase_memory_mapping() {
+        // Fallback to regular reads if the file cannot be mapped.
+        if let Ok(dbase) = DBase::open_mapped(&path) {
+            return Some(dbase);
         }
     }
 
-    if *mode.offset(1) == 't' as c_char {
-        dfile.flags |= DFILE_TEXT as c_int;
+    DBase::open(&path).ok()
+}
+
+// Converts unexpected end of file while reading entries table into
+// [DatError::TruncatedEntries].
+fn dbase_truncated(err: io::Error) -> DatError {
+    if err.kind() == ErrorKind::UnexpectedEof {
+        DatError::TruncatedEntries
+    } else {
+        DatError::Io(err)
     }
+}
 
-    dfile.dbase = Rc::downgrade(&dbase_rc);
-    dfile.next = (*dbase).dfile_head.clone();
+fn dbase_read_u8(reader: &mut impl Read) -> Result<u8, DatError> {
+    let mut value = [0u8; 1];
+    reader.read_exact(&mut value).map_err(dbase_truncated)?;
+    Ok(value[0])
+}
 
-    let dfile = Rc::new(RefCell::new(dfile));
-    (*dbase).dfile_head = Some(dfile.clone());
+fn dbase_read_u32_be(reader: &mut impl Read) -> Result<u32, DatError> {
+    let mut value = [0u8; 4];
+    reader.read_exact(&mut value).map_err(dbase_truncated)?;
+    Ok(u32::from_be_bytes(value))
+}
 
-    Some(dfile)
+fn dbase_read_i32_le(reader: &mut impl Read) -> Result<i32, DatError> {
+    let mut value = [0u8; 4];
+    reader.read_exact(&mut value).map_err(dbase_truncated)?;
+    Ok(i32::from_le_bytes(value))
 }
 
-// 0x4E6078
-unsafe fn dfile_read_compressed(
-    stream: &mut DFile,
-    mut ptr: *const c_void,
-    mut size: size_t,
-) -> bool {
-    if ((*stream).flags & DFILE_HAS_COMPRESSED_UNGETC) != 0 {
-        let mut byte_buffer = ptr as *mut c_uchar;
-        *byte_buffer = ((*stream).compressed_ungotten & 0xFF) as c_uchar;
-        byte_buffer = byte_buffer.offset(1);
-        ptr = byte_buffer as *const c_void;
+// Reads entries table of .DAT file of particular format.
+type DBaseReader = fn(&Arc<File>, u64) -> Result<DBase, DatError>;
 
-        size -= 1;
+// Reads entries table of Fallout 2 .DAT file.
+fn dbase_read_dat2(file: &Arc<File>, file_size: u64) -> Result<DBase, DatError> {
+    let reader = &mut BufReader::new(file.as_ref());
 
-        stream.flags &= !DFILE_HAS_COMPRESSED_UNGETC;
-        stream.position += 1;
+    if file_size < 8 {
+        return Err(DatError::BadFooter);
+    }
 
-        if size == 0 {
-            return true;
-        }
+    // Reposition stream to read footer, which contains two 32-bits ints: the
+    // size of entries table, and the size of entire dbase content.
+    //
+    // NOTE: It appears that this approach allows existence of arbitrary data in
+    // the beginning of the .DAT file.
+    reader.seek(SeekFrom::Start(file_size - 8))?;
+    let entries_data_size = dbase_read_i32_le(reader)? as i64;
+    let dbase_data_size = dbase_read_i32_le(reader)? as i64;
+
+    // Footer of Fallout 1 .DAT is just the tail of the last entry, so there
+    // is a good chance it does not make sense.
+    if entries_data_size < 4
+        || dbase_data_size > file_size as i64
+        || entries_data_size > dbase_data_size - 8
+    {
+        return Err(DatError::BadFooter);
     }
 
-    (*(*stream).decompression_stream).next_out = ptr as *mut Bytef;
-    (*(*stream).decompression_stream).avail_out = size as c_uint;
+    // Data section is everything between the beginning of dbase content and
+    // the entries table.
+    let data_section_size = (dbase_data_size - 8 - entries_data_size) as u64;
+
+    // Read the entire entries table at once (it's size is checked against
+    // file size above).
+    reader.seek(SeekFrom::Start(file_size - 8 - entries_data_size as u64))?;
+    let mut entries_data = vec![0u8; entries_data_size as usize];
+    reader
+        .read_exact(&mut entries_data)
+        .map_err(dbase_truncated)?;
+
+    let mut entries_reader = entries_data.as_slice();
+    let entries_length = dbase_read_i32_le(&mut entries_reader)?;
+
+    // Every entry takes at least 17 bytes (4 bytes path length, 1 byte
+    // compression flag, and three 4-bytes sizes).
+    if entries_length < 0 || entries_length as i64 > entries_data_size / 17 {
+        return Err(DatError::TruncatedEntries);
+    }
 
-    loop {
-        if (*(*stream).decompression_stream).avail_out == 0 {
-            // Everything was decompressed.
-            break;
+    let mut entries = Vec::with_capacity(entries_length as usize);
+    for index in 0..entries_length as usize {
+        let path_length = dbase_read_i32_le(&mut entries_reader)?;
+        if path_length < 0 || path_length as usize > entries_reader.len() {
+            return Err(DatError::TruncatedEntries);
         }
 
-        if (*(*stream).decompression_stream).avail_in == 0 {
-            // No more unprocessed data, request next chunk.
-            let bytes_to_read = DFILE_DECOMPRESSION_BUFFER_SIZE
-                .min(((*(*stream).entry).data_size[0] - (*stream).compressed_bytes_read) as u32)
-                as size_t;
-
-            if fread(
-                (*stream).decompression_buffer as *mut c_void,
-                bytes_to_read,
-                1,
-                (*stream).stream,
-            ) != 1
-            {
-                break;
-            }
+        let (path, rest) = entries_reader.split_at(path_length as usize);
+        entries_reader = rest;
 
-            (*(*stream).decompression_stream).avail_in = bytes_to_read as c_uint;
-            (*(*stream).decompression_stream).next_in = (*stream).decompression_buffer;
+        let compressed = dbase_read_u8(&mut entries_reader)?;
+        let uncompressed_size = dbase_read_i32_le(&mut entries_reader)?;
+        let data_size = dbase_read_i32_le(&mut entries_reader)?;
+        let data_offset = dbase_read_i32_le(&mut entries_reader)?;
 
-            (*stream).compressed_bytes_read += bytes_to_read as c_int;
-        }
-        if inflate((*stream).decompression_stream, Z_NO_FLUSH) != Z_OK {
-            break;
+        // NOTE: Original code treats any value other than 1 as uncompressed.
+        if compressed > 1 {
+            return Err(DatError::BadEntry {
+                index,
+                reason: "invalid compression flag",
+            });
         }
-    }
 
-    if (*(*stream).decompression_stream).avail_out != 0 {
-        // There are some data still waiting, which means there was in error
-        // during decompression loop above.
-        return false;
+        entries.push(dbase_make_entry(
+            index,
+            path,
+            compressed == 1,
+            uncompressed_size as i64,
+            data_size as i64,
+            data_offset as i64,
+            data_section_size,
+        )?);
     }
 
-    (*stream).position += size as c_long;
-
-    true
+    Ok(DBase {
+        path: PathBuf::new(),
+        storage: DBaseStorage::File(file.clone()),
+        format: DBaseFormat::Dat2,
+        data_offset: file_size - dbase_data_size as u64,
+        data_section_size,
+        entries,
+        index: HashMap::new(),
+    })
 }
 
-// NOTE: Inlined.
+// Creates entry from untrusted values read from entries table.
 //
-// 0x4E613C
-unsafe fn dfile_unget_compressed(stream: &mut DFile, ch: c_int) {
-    stream.compressed_ungotten = ch;
-    stream.flags |= DFILE_HAS_COMPRESSED_UNGETC;
-    stream.position -= 1;
-}
-
-// 0x4E5F9C
-unsafe fn dfile_read_char_internal(stream: &mut DFile) -> c_int {
-    if (*(*stream).entry).compressed[0] == 1 {
-        let mut ch = ['\0' as c_char; 1];
-        if !dfile_read_compressed(
-            stream,
-            ch.as_mut_ptr() as *const c_void,
-            mem::size_of::<c_char>(),
-        ) {
-            return -1;
-        }
-
-        if ((*stream).flags & DFILE_TEXT as c_int) != 0 {
-            // NOTE: I'm not sure if they are comparing as chars or ints. Since
-            // character literals are ints, let's cast read characters to int as
-            // well.
-            if ch[0] == '\r' as c_char {
-                let mut next_ch = ['\0' as c_char; 1];
-                if dfile_read_compressed(
-                    stream,
-                    next_ch.as_mut_ptr() as *const c_void,
-                    mem::size_of::<c_char>(),
-                ) {
-                    if next_ch[0] == '\n' as c_char as c_char {
-                        ch[0] = next_ch[0];
-                    } else {
-                        // NOTE: Uninline.
-                        dfile_unget_compressed(stream, (next_ch[0] as c_int) & 0xFF);
-                    }
-                }
-            }
-        }
-
-        return (ch[0] as c_int) & 0xFF;
+// The entry is rejected if it's path cannot be used with C string APIs, or if
+// it's data is not entirely inside data section of [data_section_size] bytes.
+//
+// NOTE: Unlike original code, paths are not limited to [COMPAT_MAX_PATH].
+fn dbase_make_entry(
+    index: usize,
+    path: &[u8],
+    compressed: bool,
+    uncompressed_size: i64,
+    data_size: i64,
+    data_offset: i64,
+    data_section_size: u64,
+) -> Result<DBaseEntry, DatError> {
+    let bad_entry = |reason| DatError::BadEntry { index, reason };
+
+    if path.is_empty() {
+        return Err(bad_entry("path is empty"));
     }
 
-    if (*(*stream).entry).uncompressed_size[0] < 0
-        || (*stream).position >= (*(*stream).entry).uncompressed_size[0] as c_long
-    {
-        return -1;
+    let path = CString::new(path).map_err(|_| bad_entry("path contains null character"))?;
+
+    if uncompressed_size < 0 || data_size < 0 || data_offset < 0 {
+        return Err(bad_entry("negative size or offset"));
     }
 
-    let mut ch = fgetc((*stream).stream);
-    if ch != -1 {
-        if ((*stream).flags & DFILE_TEXT as c_int) != 0 {
-            // This is a text stream, attempt to detect \r\n sequence.
-            if ch == '\r' as c_int {
-                if (*stream).position + 1 < ((*(*stream).entry).uncompressed_size[0] as c_long) {
-                    let next_ch = fgetc((*stream).stream);
-                    if next_ch == '\n' as c_int {
-                        ch = next_ch;
-                        (*stream).position += 1;
-                    } else {
-                        ungetc(next_ch, (*stream).stream);
-                    }
-                }
-            }
-        }
+    // See [DBaseEntry::stored_size].
+    let stored_size = if compressed {
+        data_size
+    } else {
+        uncompressed_size
+    };
 
-        (*stream).position += 1;
+    if (data_offset + stored_size) as u64 > data_section_size {
+        return Err(bad_entry("data is outside of data section"));
     }
 
-    ch
+    Ok(DBaseEntry {
+        path,
+        compressed,
+        uncompressed_size: uncompressed_size as u32,
+        data_size: data_size as u32,
+        data_offset: data_offset as u32,
+    })
 }
 
-pub unsafe fn dbase_open(file_path: *const c_char) -> Option<Rc<RefCell<DBase>>> {
-    assert_ne!(file_path, null()); // "filename", "dfile.c", 74
+// Reads a name prefixed with it's length (one byte), as used in Fallout 1
+// .DAT files.
+fn dbase_read_dat1_name(reader: &mut impl Read) -> Option<Vec<u8>> {
+    let length = dbase_read_u8(reader).ok()? as usize;
+    let mut name = vec![0u8; length];
+    reader.read_exact(&mut name).ok()?;
 
-    let rb = CString::new("rb").expect("valid string");
-    let str = rb.as_ptr();
-    let stream = rust_compat_fopen(file_path, str);
-    if stream == null_mut() {
+    // Names are plain ASCII, anything else means it's not a Fallout 1 .DAT.
+    if name.iter().any(|&ch| !ch.is_ascii_graphic() && ch != b' ') {
         return None;
     }
 
-    let mut dbase = DBase::default();
+    Some(name)
+}
+
+// Reads directory tree of Fallout 1 .DAT file.
+//
+// The file starts with a header of four big-endian 32-bit ints (the number of
+// directories, two unknown values, the second of which is always zero, and a
+// timestamp), followed by directory names. Then for every directory there is
+// a block of four 32-bit ints (the number of files and three unknown values)
+// followed by file descriptions: name, attributes, offset, uncompressed size,
+// and compressed size.
+fn dbase_read_dat1(file: &Arc<File>, file_size: u64) -> Result<DBase, DatError> {
+    let reader = &mut BufReader::new(file.as_ref());
+    reader.seek(SeekFrom::Start(0))?;
+
+    // Anything wrong in the header means it's not a Fallout 1 .DAT.
+    let header = |err| match err {
+        DatError::TruncatedEntries => DatError::BadFooter,
+        err => err,
+    };
+
+    let directories_length = dbase_read_u32_be(reader).map_err(header)?;
 
-    unsafe fn close_on_error(stream: *mut FILE) {
-        fclose(stream);
+    // Every directory takes at least 17 bytes (name length and directory
+    // block header).
+    if directories_length == 0 || directories_length as u64 > file_size / 17 {
+        return Err(DatError::BadFooter);
     }
 
-    // Get file size, and reposition stream to read footer, which contains two
-    // 32-bits ints.
-    let file_size = rust_get_file_size(stream) as c_int;
+    dbase_read_u32_be(reader).map_err(header)?;
+    if dbase_read_u32_be(reader).map_err(header)? != 0 {
+        return Err(DatError::BadFooter);
+    }
+    dbase_read_u32_be(reader).map_err(header)?;
 
-    if fseek(
-        stream,
-        (file_size - mem::size_of::<c_int>() as c_int * 2) as c_long,
-        SEEK_SET,
-    ) != 0
-    {
-        close_on_error(stream);
-        return None;
+    let mut directory_names = Vec::with_capacity(directories_length as usize);
+    for _ in 0..directories_length {
+        let name = dbase_read_dat1_name(reader).ok_or(DatError::BadFooter)?;
+        directory_names.push(name);
     }
 
-    // Read the size of entries table.
-    let mut entries_data_size = [0 as c_int; 1];
-    if fread(
-        entries_data_size.as_mut_ptr() as *mut c_void,
-        mem::size_of_val(&entries_data_size),
-        1,
-        stream,
-    ) != 1
-    {
-        close_on_error(stream);
-        return None;
+    let mut entries = Vec::new();
+    for directory_name in &directory_names {
+        let files_length = dbase_read_u32_be(reader)?;
+        if files_length as u64 > file_size / 17 {
+            return Err(DatError::TruncatedEntries);
+        }
+
+        for _ in 0..3 {
+            dbase_read_u32_be(reader)?;
+        }
+
+        for _ in 0..files_length {
+            let file_name = dbase_read_dat1_name(reader).ok_or(DatError::TruncatedEntries)?;
+            let attributes = dbase_read_u32_be(reader)?;
+            let offset = dbase_read_u32_be(reader)?;
+            let uncompressed_size = dbase_read_u32_be(reader)?;
+            let compressed_size = dbase_read_u32_be(reader)?;
+
+            // Files in the root directory are stored in "." directory.
+            let mut path = Vec::with_capacity(directory_name.len() + file_name.len() + 1);
+            if directory_name.as_slice() != b"." {
+                path.extend_from_slice(directory_name);
+                path.push(b'\\');
+            }
+            path.extend_from_slice(&file_name);
+
+            let compressed = (attributes & DAT1_ENTRY_ATTRIBUTE_COMPRESSED) != 0;
+
+            // Offsets are relative to the beginning of the file.
+            entries.push(dbase_make_entry(
+                entries.len(),
+                &path,
+                compressed,
+                uncompressed_size as i64,
+                if compressed {
+                    compressed_size as i64
+                } else {
+                    uncompressed_size as i64
+                },
+                offset as i64,
+                file_size,
+            )?);
+        }
     }
 
-    // Read the size of entire dbase content.
-    //
-    // NOTE: It appears that this approach allows existence of arbitrary data in
-    // the beginning of the .DAT file.
-    let mut dbase_data_size = [0 as c_int; 1];
-    if fread(
-        dbase_data_size.as_mut_ptr() as *mut c_void,
-        mem::size_of_val(&dbase_data_size),
-        1,
-        stream,
-    ) != 1
-    {
-        close_on_error(stream);
-        return None;
+    // Entries are grouped by directories, sort them so that they are listed in
+    // the same order as entries of Fallout 2 .DAT.
+    entries.sort_by(|a, b| compat_stricmp_ord(a.path.as_ptr(), b.path.as_ptr()));
+
+    Ok(DBase {
+        path: PathBuf::new(),
+        storage: DBaseStorage::File(file.clone()),
+        format: DBaseFormat::Dat1,
+        data_offset: 0,
+        data_section_size: file_size,
+        entries,
+        index: HashMap::new(),
+    })
+}
+
+fn dbase_read_u16_le(reader: &mut impl Read) -> Result<u16, DatError> {
+    let mut value = [0u8; 2];
+    reader.read_exact(&mut value).map_err(dbase_truncated)?;
+    Ok(u16::from_le_bytes(value))
+}
+
+fn dbase_read_u32_le(reader: &mut impl Read) -> Result<u32, DatError> {
+    let mut value = [0u8; 4];
+    reader.read_exact(&mut value).map_err(dbase_truncated)?;
+    Ok(u32::from_le_bytes(value))
+}
+
+// Reads central directory of ZIP archive.
+//
+// Entry paths are converted to use backslashes (as in .DAT files), directory
+// entries are skipped.
+fn dbase_read_zip(file: &Arc<File>, file_size: u64) -> Result<DBase, DatError> {
+    if file_size < ZIP_END_OF_CENTRAL_DIRECTORY_SIZE {
+        return Err(DatError::BadFooter);
     }
 
-    // Reposition stream to the beginning of the entries table.
-    if fseek(
-        stream,
-        (file_size - entries_data_size[0] as c_int - mem::size_of::<c_int>() as c_int * 2)
-            as c_long,
-        SEEK_SET,
-    ) != 0
+    // End of central directory record is followed by a comment of up to 64 KB,
+    // so it has to be searched for.
+    let tail_size = file_size.min(ZIP_END_OF_CENTRAL_DIRECTORY_SIZE + u16::MAX as u64);
+    let mut tail = vec![0u8; tail_size as usize];
+    dbase_read_at(file, &mut tail, file_size - tail_size)?;
+
+    let signature = ZIP_END_OF_CENTRAL_DIRECTORY_SIGNATURE.to_le_bytes();
+    let record_position = (0..=tail.len() - ZIP_END_OF_CENTRAL_DIRECTORY_SIZE as usize)
+        .rev()
+        .find(|&position| tail[position..position + 4] == signature)
+        .ok_or(DatError::BadFooter)?;
+
+    let reader = &mut &tail[record_position + 4..];
+    let disk_number = dbase_read_u16_le(reader)?;
+    let central_directory_disk_number = dbase_read_u16_le(reader)?;
+    let disk_entries_length = dbase_read_u16_le(reader)?;
+    let entries_length = dbase_read_u16_le(reader)?;
+    let central_directory_size = dbase_read_u32_le(reader)?;
+    let central_directory_offset = dbase_read_u32_le(reader)?;
+
+    if disk_number != 0
+        || central_directory_disk_number != 0
+        || disk_entries_length != entries_length
     {
-        close_on_error(stream);
-        return None;
+        return Err(DatError::Unsupported("multi-volume ZIP archive"));
     }
 
-    if fread(
-        dbase.entries_length.as_mut_ptr() as *mut c_void,
-        mem::size_of_val(&dbase.entries_length),
-        1,
-        stream,
-    ) != 1
-    {
-        close_on_error(stream);
-        return None;
+    if entries_length == u16::MAX || central_directory_offset == u32::MAX {
+        return Err(DatError::Unsupported("ZIP64 archive"));
     }
 
-    let entries = Box::new(vec![
-        DBaseEntry::default();
-        dbase.entries_length[0] as usize
-    ]);
-    dbase.entries = Some(*entries);
-    if dbase.entries.is_none() {
-        close_on_error(stream);
-        return None;
+    let central_directory_end = central_directory_offset as u64 + central_directory_size as u64;
+    if central_directory_end > file_size - tail_size + record_position as u64 {
+        return Err(DatError::TruncatedEntries);
     }
 
-    // Read entries one by one, stopping on any error.
-    let mut entry_index = 0;
-    for i in 0..dbase.entries_length[0] {
-        let entry = &mut dbase.entries.as_mut().expect("")[i as usize];
+    let mut central_directory = vec![0u8; central_directory_size as usize];
+    dbase_read_at(
+        file,
+        &mut central_directory,
+        central_directory_offset as u64,
+    )?;
+
+    let mut entries = Vec::with_capacity(entries_length as usize);
+    let reader = &mut central_directory.as_slice();
+    for index in 0..entries_length as usize {
+        if dbase_read_u32_le(reader)? != ZIP_CENTRAL_DIRECTORY_ENTRY_SIGNATURE {
+            return Err(DatError::BadEntry {
+                index,
+                reason: "invalid central directory entry signature",
+            });
+        }
 
-        let mut path_length = [0 as c_int; 1];
-        if fread(
-            path_length.as_mut_ptr() as *mut c_void,
-            mem::size_of_val(&path_length),
-            1,
-            stream,
-        ) != 1
-        {
-            break;
+        let mut header = [0u8; ZIP_CENTRAL_DIRECTORY_ENTRY_SIZE - 4];
+        reader.read_exact(&mut header).map_err(dbase_truncated)?;
+        let u16_at = |offset: usize| u16::from_le_bytes([header[offset], header[offset + 1]]);
+        let u32_at = |offset: usize| {
+            u32::from_le_bytes([
+                header[offset],
+                header[offset + 1],
+                header[offset + 2],
+                header[offset + 3],
+            ])
+        };
+
+        // NOTE: Offsets are relative to the end of signature.
+        let flags = u16_at(4);
+        let method = u16_at(6);
+        let compressed_size = u32_at(16);
+        let uncompressed_size = u32_at(20);
+        let path_length = u16_at(24) as usize;
+        let extra_length = u16_at(26) as usize;
+        let comment_length = u16_at(28) as usize;
+        let local_header_offset = u32_at(38);
+
+        if path_length + extra_length + comment_length > reader.len() {
+            return Err(DatError::TruncatedEntries);
         }
 
-        let path = malloc(path_length[0] as size_t + 1) as *mut c_char;
-        if path == null_mut() {
-            break;
+        let (path, rest) = reader.split_at(path_length);
+        *reader = &rest[extra_length + comment_length..];
+
+        // Directories are implied by file paths.
+        if path.ends_with(b"/") || path.ends_with(b"\\") {
+            continue;
         }
 
-        if fread(path as *mut c_void, path_length[0] as size_t, 1, stream) != 1 {
-            break;
+        if (flags & ZIP_FLAG_ENCRYPTED) != 0 {
+            return Err(DatError::BadEntry {
+                index,
+                reason: "encrypted entries are not supported",
+            });
         }
 
-        *path.offset(path_length[0] as isize) = '\0' as c_char;
-        (*entry).path = Some(CString::from_raw(path));
+        let compressed = match method {
+            ZIP_METHOD_STORED => false,
+            ZIP_METHOD_DEFLATED => true,
+            _ => {
+                return Err(DatError::BadEntry {
+                    index,
+                    reason: "unsupported compression method",
+                })
+            }
+        };
 
-        if fread(
-            (*entry).compressed.as_mut_ptr() as *mut c_void,
-            mem::size_of_val(&(*entry).compressed),
-            1,
-            stream,
-        ) != 1
-        {
-            break;
+        if !compressed && compressed_size != uncompressed_size {
+            return Err(DatError::BadEntry {
+                index,
+                reason: "stored entry size mismatch",
+            });
         }
 
-        if fread(
-            (*entry).uncompressed_size.as_mut_ptr() as *mut c_void,
-            mem::size_of_val(&(*entry).uncompressed_size),
-            1,
-            stream,
-        ) != 1
+        if compressed_size == u32::MAX
+            || uncompressed_size == u32::MAX
+            || local_header_offset == u32::MAX
         {
-            break;
+            return Err(DatError::Unsupported("ZIP64 archive"));
         }
 
-        if fread(
-            (*entry).data_size.as_mut_ptr() as *mut c_void,
-            mem::size_of_val(&(*entry).data_size),
-            1,
-            stream,
-        ) != 1
-        {
-            break;
+        // Entry data follows local header, which has it's own (possibly
+        // different) extra field.
+        let mut local_header = [0u8; ZIP_LOCAL_HEADER_SIZE];
+        if local_header_offset as u64 + ZIP_LOCAL_HEADER_SIZE as u64 > file_size {
+            return Err(DatError::BadEntry {
+                index,
+                reason: "data is outside of data section",
+            });
         }
-
-        if fread(
-            (*entry).data_offset.as_mut_ptr() as *mut c_void,
-            mem::size_of_val(&(*entry).data_offset),
-            1,
-            stream,
-        ) != 1
-        {
-            break;
+        dbase_read_at(file, &mut local_header, local_header_offset as u64)?;
+        if local_header[..4] != ZIP_LOCAL_HEADER_SIGNATURE.to_le_bytes() {
+            return Err(DatError::BadEntry {
+                index,
+                reason: "invalid local header signature",
+            });
         }
 
-        entry_index = i + 1;
-    }
-
-    if entry_index < dbase.entries_length[0] {
-        // We haven't reached the end, which means there was an error while
-        // reading entries.
-        close_on_error(stream);
-        return None;
+        let local_path_length = u16::from_le_bytes([local_header[26], local_header[27]]);
+        let local_extra_length = u16::from_le_bytes([local_header[28], local_header[29]]);
+        let data_offset = local_header_offset as u64
+            + ZIP_LOCAL_HEADER_SIZE as u64
+            + local_path_length as u64
+            + local_extra_length as u64;
+
+        let path = path
+            .iter()
+            .map(|&ch| if ch == b'/' { b'\\' } else { ch })
+            .collect::<Vec<_>>();
+
+        entries.push(dbase_make_entry(
+            index,
+            &path,
+            compressed,
+            uncompressed_size as i64,
+            compressed_size as i64,
+            data_offset as i64,
+            file_size,
+        )?);
     }
 
-    dbase.path = Some(CString::from_raw(rust_compat_strdup(file_path)));
-    dbase.data_offset = file_size as c_int - dbase_data_size[0] as c_int;
-
-    fclose(stream);
-
-    Some(Rc::new(RefCell::new(dbase)))
+    Ok(DBase {
+        path: PathBuf::new(),
+        storage: DBaseStorage::File(file.clone()),
+        format: DBaseFormat::Zip,
+        data_offset: 0,
+        data_section_size: file_size,
+        entries,
+        index: HashMap::new(),
+    })
 }
 
-pub unsafe fn dbase_find_first_entry(
-    dbase: &DBase,
-    find_file_data: *mut DFileFindData,
+pub(crate) unsafe fn dbase_find_first_entry<'a>(
+    dbase: &'a DBase,
+    find_file_data: *mut DFileFindData<'a>,
     pattern: *const c_char,
 ) -> bool {
-    for index in 0..(*dbase).entries_length[0] {
-        let entry = &(*dbase).entries.as_ref().expect("")[index as usize];
-        if fpattern_match(pattern, (*entry).get_path_cstr()) {
-            strcpy(
-                (*find_file_data).file_name.as_mut_ptr() as *mut c_char,
-                (*entry).get_path_cstr(),
-            );
-            strcpy(
-                (*find_file_data).pattern.as_mut_ptr() as *mut c_char,
-                pattern,
-            );
-            (*find_file_data).index = index as c_int;
+    for (index, entry) in dbase.entries.iter().enumerate() {
+        if fpattern_match(pattern, entry.path.as_ptr()) {
+            (*find_file_data).file_name = &entry.path;
+            (*find_file_data).pattern = CStr::from_ptr(pattern).to_owned();
+            (*find_file_data).index = index;
             return true;
         }
     }
@@ -704,17 +1714,14 @@ pub unsafe fn dbase_find_first_entry(
     false
 }
 
-pub unsafe fn dbase_find_next_entry(dbase: &DBase, find_file_data: *mut DFileFindData) -> bool {
-    for index in ((*find_file_data).index + 1)..(*dbase).entries_length[0] {
-        let entry = &(*dbase).entries.as_ref().expect("")[index as usize];
-        if fpattern_match(
-            (*find_file_data).pattern.as_mut_ptr() as *mut c_char,
-            (*entry).get_path_cstr(),
-        ) {
-            strcpy(
-                (*find_file_data).file_name.as_mut_ptr() as *mut c_char,
-                (*entry).get_path_cstr(),
-            );
+pub(crate) unsafe fn dbase_find_next_entry<'a>(
+    dbase: &'a DBase,
+    find_file_data: *mut DFileFindData<'a>,
+) -> bool {
+    let start = (*find_file_data).index + 1;
+    for (index, entry) in dbase.entries.iter().enumerate().skip(start) {
+        if fpattern_match((*find_file_data).pattern.as_ptr(), entry.path.as_ptr()) {
+            (*find_file_data).file_name = &entry.path;
             (*find_file_data).index = index;
             return true;
         }
@@ -723,50 +1730,36 @@ pub unsafe fn dbase_find_next_entry(dbase: &DBase, find_file_data: *mut DFileFin
     false
 }
 
-pub unsafe fn dfile_read_char(stream: &mut DFile) -> c_int {
+pub(crate) unsafe fn dfile_read_char(stream: &mut DFile) -> c_int {
     // assert_ne!(stream, null_mut()); // "stream", "dfile.c", 384
 
-    if ((*stream).flags & DFILE_EOF as c_int) != 0 || ((*stream).flags & DFILE_ERROR as c_int) != 0
-    {
+    if (stream.flags & DFILE_EOF) != 0 || (stream.flags & DFILE_ERROR) != 0 {
         return -1;
     }
 
-    if ((*stream).flags & DFILE_HAS_UNGETC as c_int) != 0 {
-        (*stream).flags &= !DFILE_HAS_UNGETC as c_int;
-        return (*stream).ungotten;
-    }
-
     let ch = dfile_read_char_internal(stream);
     if ch == -1 {
-        (*stream).flags |= DFILE_EOF as c_int;
+        stream.flags |= DFILE_EOF;
     }
 
     ch
 }
 
-pub unsafe fn dfile_read_string(
+pub(crate) unsafe fn dfile_read_string(
     string: *mut c_char,
-    mut size: c_int,
+    size: c_int,
     stream: &mut DFile,
 ) -> *const c_char {
     assert_ne!(string, null_mut()); // "s", "dfile.c", 407
     assert_ne!(size, 0); // "n", "dfile.c", 408
                          // assert_ne!(stream, null_mut()); // "stream", "dfile.c", 409
 
-    if ((*stream).flags & DFILE_EOF as c_int) != 0 || ((*stream).flags & DFILE_ERROR as c_int) != 0
-    {
+    if (stream.flags & DFILE_EOF) != 0 || (stream.flags & DFILE_ERROR) != 0 {
         return null();
     }
 
     let mut pch = string;
 
-    if ((*stream).flags & DFILE_HAS_UNGETC as c_int) != 0 {
-        *pch = ((*stream).ungotten & 0xFF as c_int) as c_char;
-        pch = pch.offset(1);
-        size -= 1;
-        (*stream).flags &= !DFILE_HAS_UNGETC as c_int;
-    }
-
     // Read up to size - 1 characters one by one saving space for the null
     // terminator.
     for _ in 0..(size - 1) {
@@ -793,8 +1786,8 @@ pub unsafe fn dfile_read_string(
     string
 }
 
-pub unsafe fn dfile_read(
-    mut ptr: *const c_void,
+pub(crate) unsafe fn dfile_read(
+    ptr: *const c_void,
     size: size_t,
     count: size_t,
     stream: &mut DFile,
@@ -802,53 +1795,28 @@ pub unsafe fn dfile_read(
     assert_ne!(ptr, null_mut()); // "ptr", "dfile.c", 499
                                  // assert_ne!(stream, null_mut()); // "stream", dfile.c, 500
 
-    if ((*stream).flags & DFILE_EOF as c_int) != 0 || ((*stream).flags & DFILE_ERROR as c_int) != 0
-    {
+    if (stream.flags & DFILE_EOF) != 0 || (stream.flags & DFILE_ERROR) != 0 {
         return 0;
     }
 
-    let mut remaining_size = (*(*stream).entry).uncompressed_size[0] as c_long - (*stream).position;
-    if ((*stream).flags & DFILE_HAS_UNGETC as c_int) != 0 {
-        remaining_size += 1;
-    }
+    let remaining_size = stream.size() - stream.position();
 
     let mut bytes_to_read = size * count;
-    if remaining_size < bytes_to_read as c_long {
+    if remaining_size < bytes_to_read as u64 {
         bytes_to_read = remaining_size as size_t;
-        (*stream).flags |= DFILE_EOF as c_int;
-    }
-
-    let mut extra_bytes_read = 0;
-    if ((*stream).flags & DFILE_HAS_UNGETC as c_int) != 0 {
-        let mut byte_buffer = ptr as *mut c_uchar;
-        *byte_buffer = ((*stream).ungotten & 0xFF as c_int) as c_uchar;
-        byte_buffer = byte_buffer.offset(1);
-        ptr = byte_buffer as *const c_void;
-
-        bytes_to_read -= 1;
-
-        (*stream).flags &= !DFILE_HAS_UNGETC as c_int;
-        extra_bytes_read = 1;
+        stream.flags |= DFILE_EOF;
     }
 
-    let bytes_read;
-    if (*(*stream).entry).compressed[0] == 1 {
-        if !dfile_read_compressed(stream, ptr, bytes_to_read) {
-            (*stream).flags |= DFILE_ERROR as c_int;
-            return 0;
-        }
-
-        bytes_read = bytes_to_read;
-    } else {
-        bytes_read =
-            fread(ptr as *mut c_void, 1, bytes_to_read, (*stream).stream) + extra_bytes_read;
-        (*stream).position += bytes_read as c_long;
+    let buffer = slice::from_raw_parts_mut(ptr as *mut u8, bytes_to_read);
+    if stream.read_exact(buffer).is_err() {
+        stream.flags |= DFILE_ERROR;
+        return 0;
     }
 
-    bytes_read / size
+    bytes_to_read / size
 }
 
-pub unsafe fn dfile_write(
+pub(crate) unsafe fn dfile_write(
     ptr: *const c_void,
     _size: size_t,
     count: size_t,
@@ -859,120 +1827,55 @@ pub unsafe fn dfile_write(
     count - 1
 }
 
-pub unsafe fn dfile_seek(stream: &mut DFile, offset: c_long, origin: c_int) -> c_int {
+// Moves to [offset] relative to [origin], returns 0 on success.
+//
+// Positions (and offsets) are always measured in bytes of entry data, even in
+// text streams where \r\n sequence is read as a single \n. This means that
+// [dfile_tell] after reading \r\n as \n returns position past both bytes,
+// and seeking back to any position returned by [dfile_tell] resumes reading
+// exactly where it was, whether the entry is compressed or not.
+//
+// NOTE: Original code only allows rewinding of text streams (i.e. any seek
+// with non-zero offset relative to current position or to the end fails).
+pub(crate) unsafe fn dfile_seek(stream: &mut DFile, offset: c_long, origin: c_int) -> c_int {
     // assert_ne!(stream, null_mut()); // "stream", "dfile.c", 569
 
-    if ((*stream).flags & DFILE_ERROR as c_int) != 0 {
+    if (stream.flags & DFILE_ERROR) != 0 {
         return 1;
     }
 
-    if ((*stream).flags & DFILE_TEXT as c_int) != 0 {
-        if offset != 0 && origin != SEEK_SET {
-            // NOTE: For unknown reason this function does not allow arbitrary
-            // seeks in text streams, whether compressed or not. It only
-            // supports rewinding. Probably because of reading functions which
-            // handle \r\n sequence as \n.
-            return 1;
-        }
-    }
-
     let offset_from_beginning = match origin {
         SEEK_SET => offset,
-        SEEK_CUR => (*stream).position + offset,
-        SEEK_END => (*(*stream).entry).uncompressed_size[0] as c_long + offset,
+        SEEK_CUR => stream.position() as c_long + offset,
+        SEEK_END => stream.size() as c_long + offset,
         _ => return 1,
     };
 
-    if offset_from_beginning >= (*(*stream).entry).uncompressed_size[0] as c_long {
-        return 1;
-    }
-
-    let pos = (*stream).position;
-    if offset_from_beginning == pos {
-        (*stream).flags &= !(DFILE_HAS_UNGETC | DFILE_EOF) as c_int;
-        return 0;
-    }
-
-    if offset_from_beginning != 0 {
-        if (*(*stream).entry).compressed[0] == 1 {
-            if offset_from_beginning < pos {
-                // We cannot go backwards in compressed stream, so the only way
-                // is to start from the beginning.
-                dfile_rewind(stream);
-            }
-
-            // Consume characters one by one until we reach specified offset.
-            while offset_from_beginning > (*stream).position {
-                if dfile_read_char_internal(stream) == -1 {
-                    return 1;
-                }
-            }
-        } else {
-            if fseek((*stream).stream, offset_from_beginning - pos, SEEK_CUR) != 0 {
-                (*stream).flags |= DFILE_ERROR as c_int;
-                return 1;
-            }
-
-            // FIXME: I'm not sure what this assignment means. This field is
-            // only meaningful when reading compressed streams.
-            (*stream).compressed_bytes_read = offset_from_beginning as c_int;
-        }
-
-        (*stream).flags &= !(DFILE_HAS_UNGETC | DFILE_EOF) as c_int;
-        return 0;
-    }
-
-    if fseek(
-        (*stream).stream,
-        ((*(*stream).dbase.upgrade().expect("")).borrow().data_offset
-            + (*(*stream).entry).data_offset[0]) as c_long,
-        SEEK_SET,
-    ) != 0
-    {
-        (*stream).flags |= DFILE_ERROR as c_int;
-        return 1;
-    }
-
-    if inflateEnd((*stream).decompression_stream) != Z_OK {
-        (*stream).flags |= DFILE_ERROR as c_int;
+    // NOTE: Original code does not allow seeking to the end of data, which
+    // makes position returned by [dfile_tell] at the end of data unusable.
+    if offset_from_beginning < 0 || offset_from_beginning as u64 > stream.size() {
         return 1;
     }
 
-    (*(*stream).decompression_stream).zalloc = mem::transmute::<*const c_void, alloc_func>(null());
-    (*(*stream).decompression_stream).zfree = mem::transmute::<*const c_void, free_func>(null());
-    (*(*stream).decompression_stream).opaque = mem::transmute::<*const c_void, voidpf>(null());
-    (*(*stream).decompression_stream).next_in = (*stream).decompression_buffer;
-    (*(*stream).decompression_stream).avail_in = 0;
-
-    //inflateInit_((strm), ZLIB_VERSION, (int)sizeof(z_stream))
-    // Used ZLIB_VERSION
-    let version = CString::new("1.2.11").expect("valid string");
-    if inflateInit_(
-        (*stream).decompression_stream,
-        version.as_ptr(),
-        mem::size_of::<z_stream>() as c_int,
-    ) != Z_OK
-    {
-        (*stream).flags |= DFILE_ERROR as c_int;
+    if stream.seek_to(offset_from_beginning as u64).is_err() {
+        stream.flags |= DFILE_ERROR;
         return 1;
     }
 
-    (*stream).position = 0;
-    (*stream).compressed_bytes_read = 0;
-    (*stream).flags &= !(DFILE_HAS_UNGETC | DFILE_EOF) as c_int;
+    stream.flags &= !DFILE_EOF;
 
     0
 }
 
-pub unsafe fn dfile_rewind(stream: &mut DFile) {
+pub(crate) unsafe fn dfile_rewind(stream: &mut DFile) {
     // assert_ne!(stream, null_mut()); // "stream", "dfile.c", 664
 
     dfile_seek(stream, 0, SEEK_SET);
 
-    (*stream).flags &= !DFILE_ERROR as c_int;
+    stream.flags &= !DFILE_ERROR;
 }
 
-pub unsafe fn dfile_print_formatted_args(
+pub(crate) unsafe fn dfile_print_formatted_args(
     _stream: &DFile,
     format: *const c_char,
     _args: *mut c_void,
@@ -982,33 +1885,880 @@ pub unsafe fn dfile_print_formatted_args(
     -1
 }
 
-pub unsafe fn dfile_write_char(_ch: c_int, _stream: &DFile) -> c_int {
+pub(crate) unsafe fn dfile_write_char(_ch: c_int, _stream: &DFile) -> c_int {
     -1
 }
 
-pub unsafe fn dfile_write_string(string: *const c_char, _stream: &DFile) -> c_int {
+pub(crate) unsafe fn dfile_write_string(string: *const c_char, _stream: &DFile) -> c_int {
     assert_ne!(string, null()); // "s", "dfile.c", 448
                                 // assert_ne!(stream, null()); // "stream", "dfile.c", 449
 
     -1
 }
 
-pub unsafe fn dfile_tell(stream: &DFile) -> c_long {
+pub(crate) unsafe fn dfile_tell(stream: &DFile) -> c_long {
     // assert_ne!(stream, null()); // "stream", "dfile.c", 654
 
-    stream.position
+    stream.position() as c_long
 }
 
-pub unsafe fn dfile_eof(stream: &DFile) -> c_int {
+pub(crate) unsafe fn dfile_eof(stream: &DFile) -> c_int {
     // assert_ne!(stream, null()); // "stream", "dfile.c", 685
 
-    stream.flags & DFILE_EOF as c_int
+    stream.flags & DFILE_EOF
 }
 
-pub unsafe fn dfile_get_size(stream: &DFile) -> c_long {
-    (*(*stream).entry).uncompressed_size[0] as c_long
+pub(crate) unsafe fn dfile_get_size(stream: &DFile) -> c_long {
+    stream.size() as c_long
 }
 
-pub unsafe fn dbase_find_close(_dbase: &DBase, _find_file_data: *const DFileFindData) -> bool {
+pub(crate) unsafe fn dbase_find_close(
+    _dbase: &DBase,
+    _find_file_data: *const DFileFindData,
+) -> bool {
     true
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dbase_writer::DBaseWriter;
+    use crate::test_utils::temp_path;
+
+    fn dat1_name(buffer: &mut Vec<u8>, name: &str) {
+        buffer.push(name.len() as u8);
+        buffer.extend_from_slice(name.as_bytes());
+    }
+
+    fn dat1_u32(buffer: &mut Vec<u8>, value: u32) {
+        buffer.extend_from_slice(&value.to_be_bytes());
+    }
+
+    // Builds Fallout 1 .DAT with one plain file in the root directory and one
+    // LZSS compressed file in a subdirectory.
+    fn build_dat1() -> Vec<u8> {
+        let plain = b"HELLO\r\nWORLD".to_vec();
+        let compressed = vec![
+            0xFF, 0xFE, b'X', b'Y', 0x00, 0x06, 0x07, b'A', b'B', b'C', 0xEE, 0xF0, 0x00, 0x00,
+        ];
+
+        let mut header = Vec::new();
+        dat1_u32(&mut header, 2);
+        dat1_u32(&mut header, 0x0A);
+        dat1_u32(&mut header, 0);
+        dat1_u32(&mut header, 0);
+        dat1_name(&mut header, ".");
+        dat1_name(&mut header, "ART\\CRITTERS");
+
+        // Both directory blocks have the same size.
+        let directory_block_size = 16 + 1 + 12 + 16;
+        let plain_offset = (header.len() + directory_block_size * 2) as u32;
+        let compressed_offset = plain_offset + plain.len() as u32;
+
+        dat1_u32(&mut header, 1);
+        dat1_u32(&mut header, 0x0A);
+        dat1_u32(&mut header, 0x10);
+        dat1_u32(&mut header, 0);
+        dat1_name(&mut header, "ZZZZZZZZ.TXT");
+        dat1_u32(&mut header, 0x20);
+        dat1_u32(&mut header, plain_offset);
+        dat1_u32(&mut header, plain.len() as u32);
+        dat1_u32(&mut header, 0);
+
+        dat1_u32(&mut header, 1);
+        dat1_u32(&mut header, 0x0A);
+        dat1_u32(&mut header, 0x10);
+        dat1_u32(&mut header, 0);
+        dat1_name(&mut header, "HMJMPSAA.FRM");
+        dat1_u32(&mut header, DAT1_ENTRY_ATTRIBUTE_COMPRESSED);
+        dat1_u32(&mut header, compressed_offset);
+        dat1_u32(&mut header, 8);
+        dat1_u32(&mut header, compressed.len() as u32);
+
+        header.extend_from_slice(&plain);
+        header.extend_from_slice(&compressed);
+        header
+    }
+
+    unsafe fn read_all(dbase: &DBase, path: &str, mode: &str) -> Option<Vec<u8>> {
+        let path = CString::new(path).expect("");
+        let mode = CString::new(mode).expect("");
+        let mut dfile = rust_dfile_open(dbase, path.as_ptr(), mode.as_ptr())?;
+        let mut data = Vec::new();
+        loop {
+            let ch = dfile_read_char(&mut dfile);
+            if ch == -1 {
+                break;
+            }
+            data.push(ch as u8);
+        }
+        Some(data)
+    }
+
+    #[test]
+    fn test_dbase_open_dat1() {
+        let dat_path = temp_path("master.dat");
+        std::fs::write(&dat_path, build_dat1()).expect("");
+
+        let dat_path_cstr = CString::new(dat_path.to_str().expect("")).expect("");
+        unsafe {
+            let dbase = dbase_open(dat_path_cstr.as_ptr()).expect("valid .DAT");
+            assert_eq!(dbase.format(), DBaseFormat::Dat1);
+            assert_eq!(dbase.entries().len(), 2);
+
+            assert_eq!(
+                read_all(&dbase, "zzzzzzzz.txt", "rb"),
+                Some(b"HELLO\r\nWORLD".to_vec())
+            );
+            assert_eq!(
+                read_all(&dbase, "zzzzzzzz.txt", "rt"),
+                Some(b"HELLO\nWORLD".to_vec())
+            );
+            assert_eq!(
+                read_all(&dbase, "art\\critters\\hmjmpsaa.frm", "rb"),
+                Some(b"XYABCABC".to_vec())
+            );
+
+            // Rewinding restarts decompression.
+            let path = CString::new("ART\\CRITTERS\\HMJMPSAA.FRM").expect("");
+            let mode = CString::new("rb").expect("");
+            let mut dfile = rust_dfile_open(&dbase, path.as_ptr(), mode.as_ptr()).expect("");
+            let mut buffer = [0u8; 8];
+            assert_eq!(
+                dfile_read(buffer.as_mut_ptr() as *const c_void, 1, 3, &mut dfile),
+                3
+            );
+            assert_eq!(dfile_seek(&mut dfile, 0, SEEK_SET), 0);
+            assert_eq!(
+                dfile_read(buffer.as_mut_ptr() as *const c_void, 1, 8, &mut dfile),
+                8
+            );
+            assert_eq!(&buffer, b"XYABCABC");
+        }
+
+        std::fs::remove_file(&dat_path).expect("");
+    }
+
+    // Produces compressible, but not trivially repetitive data.
+    fn build_data(size: usize) -> Vec<u8> {
+        let mut seed: u32 = 0x12345678;
+        (0..size)
+            .map(|index| {
+                seed = seed.wrapping_mul(1103515245).wrapping_add(12345);
+                if index % 3 == 0 {
+                    (seed >> 16) as u8
+                } else {
+                    (index / 100) as u8
+                }
+            })
+            .collect()
+    }
+
+    // Data which cannot be compressed, so it's stored as is by [DBaseWriter].
+    fn build_random_data(size: usize) -> Vec<u8> {
+        let mut seed: u32 = 0x87654321;
+        (0..size)
+            .map(|_| {
+                seed = seed.wrapping_mul(1103515245).wrapping_add(12345);
+                (seed >> 16) as u8
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_dfile_seek() {
+        let data = build_data(300000);
+
+        for (threshold, compressed) in [(0, true), (usize::MAX, false)] {
+            let dat_path = temp_path(&format!("seek_{}.dat", compressed));
+
+            let mut writer = DBaseWriter::new();
+            writer.set_compression_threshold(threshold);
+            writer.add_data("data.bin", data.clone()).expect("");
+            writer.write(&dat_path).expect("");
+
+            let dat_path_cstr = CString::new(dat_path.to_str().expect("")).expect("");
+            let path = CString::new("data.bin").expect("");
+            let mode = CString::new("rb").expect("");
+            unsafe {
+                let dbase = dbase_open(dat_path_cstr.as_ptr()).expect("valid .DAT");
+                let mut dfile = rust_dfile_open(&dbase, path.as_ptr(), mode.as_ptr()).expect("");
+                assert_eq!(dfile.entry().is_compressed(), compressed);
+
+                let offsets = [
+                    250000, 1000, 299990, 65535, 65536, 131073, 0, 200000, 199999,
+                ];
+                for offset in offsets {
+                    assert_eq!(dfile_seek(&mut dfile, offset, SEEK_SET), 0);
+                    assert_eq!(dfile_tell(&dfile), offset);
+
+                    let mut buffer = [0u8; 10];
+                    let buffer_ptr = buffer.as_mut_ptr() as *const c_void;
+                    assert_eq!(dfile_read(buffer_ptr, 1, 10, &mut dfile), 10);
+                    assert_eq!(&buffer, &data[offset as usize..offset as usize + 10]);
+                }
+
+                assert_eq!(dfile_seek(&mut dfile, -10, SEEK_END), 0);
+                assert_eq!(dfile_read_char(&mut dfile), data[data.len() - 10] as c_int);
+
+                if compressed {
+                    // Every interval of data has been decompressed, so
+                    // there is a checkpoint for every interval.
+                    assert_eq!(dfile.checkpoints.len(), 4);
+
+                    // Checkpoint which could not be saved does not prevent
+                    // saving later ones.
+                    let mut dfile =
+                        rust_dfile_open(&dbase, path.as_ptr(), mode.as_ptr()).expect("");
+                    assert_eq!(dfile_seek(&mut dfile, 70000, SEEK_SET), 0);
+                    dfile.checkpoints.clear();
+                    assert_eq!(dfile_seek(&mut dfile, 250000, SEEK_SET), 0);
+                    let positions: Vec<u64> = dfile
+                        .checkpoints
+                        .iter()
+                        .map(|checkpoint| checkpoint.position)
+                        .collect();
+                    assert_eq!(positions, [131072, 196608]);
+                }
+            }
+
+            std::fs::remove_file(&dat_path).expect("");
+        }
+    }
+
+    #[test]
+    fn test_dbase_read_seek() {
+        let data = build_data(100000);
+        let text = b"first\r\nsecond\r\nthird".to_vec();
+
+        let dat_path = temp_path("safe.dat");
+        let mut writer = DBaseWriter::new();
+        writer.set_compression_threshold(0);
+        writer.add_data("data\\data.bin", data.clone()).expect("");
+        writer.add_data("text.txt", text.clone()).expect("");
+        writer.write(&dat_path).expect("");
+
+        let dbase = DBase::open(&dat_path).expect("valid .DAT");
+        let paths: Vec<_> = dbase
+            .entries()
+            .map(|entry| entry.path().to_str().expect("").to_string())
+            .collect();
+        assert_eq!(paths, ["data\\data.bin", "text.txt"]);
+
+        let mut dfile = dbase.open_entry("DATA\\DATA.BIN").expect("");
+        assert!(dfile.entry().is_compressed());
+
+        let mut buffer = Vec::new();
+        dfile.read_to_end(&mut buffer).expect("");
+        assert_eq!(buffer, data);
+
+        assert_eq!(dfile.seek(SeekFrom::End(-100)).expect(""), 99900);
+        let mut buffer = [0u8; 100];
+        dfile.read_exact(&mut buffer).expect("");
+        assert_eq!(buffer, data[99900..]);
+        assert_eq!(dfile.read(&mut buffer).expect(""), 0);
+
+        assert_eq!(dfile.seek(SeekFrom::Current(-70000)).expect(""), 30000);
+        dfile.read_exact(&mut buffer).expect("");
+        assert_eq!(buffer, data[30000..30100]);
+        assert!(dfile.seek(SeekFrom::Current(100000)).is_err());
+
+        let dfile = dbase.open_entry("text.txt").expect("");
+        let lines: Vec<_> = dfile.lines().map(|line| line.expect("")).collect();
+        assert_eq!(lines, ["first", "second", "third"]);
+
+        assert!(matches!(
+            dbase.open_entry("missing.txt"),
+            Err(DatError::EntryNotFound(_))
+        ));
+
+        // Corrupted compressed data is reported as such.
+        let mut contents = std::fs::read(&dat_path).expect("");
+        contents[10..20].fill(0xFF);
+        std::fs::write(&dat_path, &contents).expect("");
+
+        let dbase = DBase::open(&dat_path).expect("valid .DAT");
+        let mut dfile = dbase.open_entry("data\\data.bin").expect("");
+        let err = dfile.read_to_end(&mut Vec::new()).expect_err("");
+        assert_eq!(err.kind(), ErrorKind::InvalidData);
+
+        std::fs::write(&dat_path, b"not a .DAT file").expect("");
+        assert!(matches!(DBase::open(&dat_path), Err(DatError::BadFooter)));
+
+        std::fs::remove_file(&dat_path).expect("");
+    }
+
+    // Builds .DAT with a single uncompressed entry "a.txt" containing "hello".
+    //
+    // The layout is: data (0..5), entries count (5..9), path length (9..13),
+    // path (13..18), compression flag (18), uncompressed size (19..23), data
+    // size (23..27), data offset (27..31), footer (31..39).
+    fn build_minimal_dat2() -> Vec<u8> {
+        let mut writer = DBaseWriter::new();
+        writer.set_compression_threshold(usize::MAX);
+        writer.add_data("a.txt", b"hello".to_vec()).expect("");
+
+        let mut contents = Vec::new();
+        writer.write_to(&mut contents).expect("");
+        assert_eq!(contents.len(), 39);
+        contents
+    }
+
+    fn open_contents(name: &str, contents: &[u8]) -> Result<DBase, DatError> {
+        let dat_path = temp_path(name);
+        std::fs::write(&dat_path, contents).expect("");
+        let result = DBase::open(&dat_path);
+        std::fs::remove_file(&dat_path).expect("");
+        result
+    }
+
+    #[test]
+    fn test_dbase_open_malformed() {
+        let contents = build_minimal_dat2();
+        assert!(open_contents("valid.dat", &contents).is_ok());
+
+        // Every truncation is rejected.
+        for length in 0..contents.len() {
+            assert!(open_contents("truncated.dat", &contents[..length]).is_err());
+        }
+
+        let patch = |offset: usize, bytes: &[u8]| {
+            let mut contents = contents.clone();
+            contents[offset..offset + bytes.len()].copy_from_slice(bytes);
+            open_contents("corrupted.dat", &contents)
+        };
+
+        let bad_entry = |result: Result<DBase, DatError>| match result {
+            Err(DatError::BadEntry { index, reason }) => {
+                assert_eq!(index, 0);
+                reason
+            }
+            Err(err) => panic!("unexpected error: {}", err),
+            Ok(_) => panic!("malformed .DAT accepted"),
+        };
+
+        // Huge sizes in footer.
+        assert!(matches!(
+            patch(31, &0x7FFFFFFFi32.to_le_bytes()),
+            Err(DatError::BadFooter)
+        ));
+        assert!(matches!(
+            patch(35, &(-1i32).to_le_bytes()),
+            Err(DatError::BadFooter)
+        ));
+
+        // Huge entries count and path length.
+        assert!(matches!(
+            patch(5, &0x7FFFFFFFi32.to_le_bytes()),
+            Err(DatError::TruncatedEntries)
+        ));
+        assert!(matches!(
+            patch(9, &(-1i32).to_le_bytes()),
+            Err(DatError::TruncatedEntries)
+        ));
+
+        assert_eq!(bad_entry(patch(14, b"\0")), "path contains null character");
+        assert_eq!(bad_entry(patch(18, &[7])), "invalid compression flag");
+        assert_eq!(
+            bad_entry(patch(19, &(-5i32).to_le_bytes())),
+            "negative size or offset"
+        );
+        assert_eq!(
+            bad_entry(patch(19, &6i32.to_le_bytes())),
+            "data is outside of data section"
+        );
+        assert_eq!(
+            bad_entry(patch(27, &1i32.to_le_bytes())),
+            "data is outside of data section"
+        );
+
+        // Arbitrary corruption never causes a panic, and whatever is accepted
+        // stays readable within the file.
+        for offset in 0..contents.len() {
+            for value in [0x00, 0x01, 0x7F, 0x80, 0xFF] {
+                if let Ok(dbase) = patch(offset, &[value]) {
+                    for entry in dbase.entries() {
+                        assert!(entry.data_offset() + entry.stored_size() <= 5);
+                    }
+                }
+            }
+        }
+    }
+
+    // Returns the offset of compression flag of entry at [index] in entries
+    // table of Fallout 2 .DAT.
+    fn dat2_entry_offset(contents: &[u8], index: usize) -> usize {
+        let footer = contents.len() - 8;
+        let entries_data_size =
+            i32::from_le_bytes(contents[footer..footer + 4].try_into().expect("")) as usize;
+
+        let mut offset = footer - entries_data_size + 4;
+        for _ in 0..index {
+            let path_length =
+                i32::from_le_bytes(contents[offset..offset + 4].try_into().expect("")) as usize;
+            offset += 4 + path_length + 13;
+        }
+
+        let path_length =
+            i32::from_le_bytes(contents[offset..offset + 4].try_into().expect("")) as usize;
+        offset + 4 + path_length
+    }
+
+    fn patch_i32(contents: &mut [u8], offset: usize, update: impl Fn(i32) -> i32) {
+        let value = i32::from_le_bytes(contents[offset..offset + 4].try_into().expect(""));
+        contents[offset..offset + 4].copy_from_slice(&update(value).to_le_bytes());
+    }
+
+    #[test]
+    fn test_dbase_verify() {
+        let mut writer = DBaseWriter::new();
+        writer.add_data("a.bin", build_data(10000)).expect("");
+        writer.add_data("b.bin", build_data(20000)).expect("");
+        writer.add_data("c.txt", b"plain".to_vec()).expect("");
+
+        let mut contents = Vec::new();
+        writer.write_to(&mut contents).expect("");
+
+        let dat_path = temp_path("verify.dat");
+        let verify = |contents: &[u8]| {
+            std::fs::write(&dat_path, contents).expect("");
+            let dbase = DBase::open(&dat_path).expect("valid .DAT");
+            dbase
+                .verify()
+                .into_iter()
+                .map(|report| report.problems)
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(verify(&contents), [vec![], vec![], vec![]]);
+
+        let a = dat2_entry_offset(&contents, 0);
+        let b = dat2_entry_offset(&contents, 1);
+        let c = dat2_entry_offset(&contents, 2);
+
+        let mut corrupted = contents.clone();
+        patch_i32(&mut corrupted, a + 1, |size| size + 1);
+        assert_eq!(
+            verify(&corrupted)[0],
+            [DBaseEntryProblem::SizeMismatch { actual: 10000 }]
+        );
+
+        let mut corrupted = contents.clone();
+        let a_data_size = i32::from_le_bytes(contents[a + 5..a + 9].try_into().expect(""));
+        patch_i32(&mut corrupted, a + 5, |size| size + 4);
+        let problems = verify(&corrupted);
+        assert_eq!(
+            problems[0],
+            [
+                DBaseEntryProblem::Overlap(1),
+                DBaseEntryProblem::TrailingData {
+                    consumed: a_data_size as u64
+                }
+            ]
+        );
+        assert_eq!(problems[1], [DBaseEntryProblem::Overlap(0)]);
+
+        let mut corrupted = contents.clone();
+        corrupted.copy_within(a + 9..a + 13, c + 9);
+        let problems = verify(&corrupted);
+        assert_eq!(problems[0], [DBaseEntryProblem::Overlap(2)]);
+        assert_eq!(problems[2], [DBaseEntryProblem::Overlap(0)]);
+
+        // Every overlapping pair is reported, not only pairs with the entry
+        // which ends the farthest: "c" is moved to the start of "b", and "a"
+        // is extended into both of them.
+        let mut corrupted = contents.clone();
+        patch_i32(&mut corrupted, a + 5, |size| size + 4);
+        corrupted.copy_within(b + 9..b + 13, c + 9);
+        let problems = verify(&corrupted);
+        assert_eq!(
+            problems[0],
+            [
+                DBaseEntryProblem::Overlap(2),
+                DBaseEntryProblem::Overlap(1),
+                DBaseEntryProblem::TrailingData {
+                    consumed: a_data_size as u64
+                }
+            ]
+        );
+        assert_eq!(
+            problems[1],
+            [DBaseEntryProblem::Overlap(0), DBaseEntryProblem::Overlap(2)]
+        );
+        assert_eq!(
+            problems[2],
+            [DBaseEntryProblem::Overlap(0), DBaseEntryProblem::Overlap(1)]
+        );
+
+        let mut corrupted = contents.clone();
+        let b_data_offset = i32::from_le_bytes(contents[b + 9..b + 13].try_into().expect(""));
+        corrupted[b_data_offset as usize + 10..b_data_offset as usize + 30].fill(0xAA);
+        let problems = verify(&corrupted);
+        assert!(matches!(
+            problems[1].as_slice(),
+            [DBaseEntryProblem::Unreadable(_)]
+        ));
+        assert!(problems[0].is_empty() && problems[2].is_empty());
+
+        std::fs::remove_file(&dat_path).expect("");
+    }
+
+    #[test]
+    fn test_dfile_shared_file() {
+        let data = build_data(50000);
+        let dat_path = temp_path("shared.dat");
+
+        let mut writer = DBaseWriter::new();
+        writer.add_data("compressed.bin", data.clone()).expect("");
+        writer
+            .add_data("plain.bin", build_random_data(20000))
+            .expect("");
+        writer.write(&dat_path).expect("");
+
+        let dbase = DBase::open(&dat_path).expect("valid .DAT");
+        let mut dfiles = [
+            dbase.open_entry("compressed.bin").expect(""),
+            dbase.open_entry("plain.bin").expect(""),
+            dbase.open_entry("compressed.bin").expect(""),
+        ];
+        let DBaseStorage::File(file) = &dbase.storage else {
+            panic!("unexpected storage");
+        };
+        assert_eq!(Arc::strong_count(file), 4);
+
+        // Interleaved reads do not affect each other.
+        let mut outputs = [Vec::new(), Vec::new(), Vec::new()];
+        for chunk_size in [1, 100, 5000, 7, 20000, 50000] {
+            for (dfile, output) in dfiles.iter_mut().zip(outputs.iter_mut()) {
+                let mut buffer = vec![0u8; chunk_size];
+                let bytes_read = dfile.read(&mut buffer).expect("");
+                output.extend_from_slice(&buffer[..bytes_read]);
+            }
+        }
+
+        for (dfile, output) in dfiles.iter_mut().zip(outputs.iter_mut()) {
+            dfile.read_to_end(output).expect("");
+        }
+
+        assert_eq!(outputs[0], data);
+        assert_eq!(outputs[1], build_random_data(20000));
+        assert_eq!(outputs[2], data);
+
+        drop(dfiles);
+        assert_eq!(Arc::strong_count(file), 1);
+
+        std::fs::remove_file(&dat_path).expect("");
+    }
+
+    #[test]
+    fn test_dbase_open_mapped() {
+        let data = build_data(200000);
+        let dat_path = temp_path("mapped.dat");
+        let plain_data = build_random_data(30000);
+
+        let mut writer = DBaseWriter::new();
+        writer.add_data("compressed.bin", data.clone()).expect("");
+        writer.add_data("plain.bin", plain_data.clone()).expect("");
+        writer.write(&dat_path).expect("");
+
+        let dbase = DBase::open_mapped(&dat_path).expect("valid .DAT");
+        assert!(dbase.is_mapped());
+        assert!(!DBase::open(&dat_path).expect("").is_mapped());
+
+        let mut plain = dbase.open_entry("plain.bin").expect("");
+        assert!(!plain.entry().is_compressed());
+        assert_eq!(plain.mapped_data(), Some(plain_data.as_slice()));
+        plain.seek(SeekFrom::Start(1000)).expect("");
+        let mut buffer = vec![0u8; 100];
+        plain.read_exact(&mut buffer).expect("");
+        assert_eq!(buffer, plain_data[1000..1100]);
+        assert_eq!(plain.fill_buf().expect("").len(), 30000 - 1100);
+
+        let mut compressed = dbase.open_entry("compressed.bin").expect("");
+        assert!(compressed.entry().is_compressed());
+        assert_eq!(compressed.mapped_data(), None);
+        let mut output = Vec::new();
+        compressed.read_to_end(&mut output).expect("");
+        assert_eq!(output, data);
+
+        // Seeking backwards restores checkpoints, which point into mapping.
+        for position in [150000, 70000, 5, 199999] {
+            compressed.seek(SeekFrom::Start(position)).expect("");
+            let mut buffer = [0u8; 1];
+            compressed.read_exact(&mut buffer).expect("");
+            assert_eq!(buffer[0], data[position as usize]);
+        }
+
+        assert!(dbase.verify().iter().all(|report| report.is_ok()));
+
+        drop(dbase);
+        std::fs::remove_file(&dat_path).expect("");
+    }
+
+    // Builds Fallout 2 .DAT with uncompressed [entries] in the given order
+    // (unlike [DBaseWriter], which sorts them).
+    fn build_unsorted_dat2(entries: &[(&str, &[u8])]) -> Vec<u8> {
+        let mut contents = Vec::new();
+        let mut table = Vec::new();
+        table.extend_from_slice(&(entries.len() as i32).to_le_bytes());
+        for (path, data) in entries {
+            table.extend_from_slice(&(path.len() as i32).to_le_bytes());
+            table.extend_from_slice(path.as_bytes());
+            table.push(0);
+            table.extend_from_slice(&(data.len() as i32).to_le_bytes());
+            table.extend_from_slice(&(data.len() as i32).to_le_bytes());
+            table.extend_from_slice(&(contents.len() as i32).to_le_bytes());
+            contents.extend_from_slice(data);
+        }
+
+        let file_size = contents.len() + table.len() + 8;
+        contents.extend_from_slice(&table);
+        contents.extend_from_slice(&(table.len() as i32).to_le_bytes());
+        contents.extend_from_slice(&(file_size as i32).to_le_bytes());
+        contents
+    }
+
+    #[test]
+    fn test_dbase_normalized_lookup() {
+        let contents = build_unsorted_dat2(&[
+            ("TEXT\\ENGLISH\\GAME\\MISC.MSG", b"misc"),
+            ("ART\\CRITTERS\\HMJMPSAA.FRM", b"frm"),
+            ("\\maps\\arcaves.map", b"map"),
+            ("art\\critters\\hmjmpsaa.frm", b"duplicate"),
+        ]);
+        let dbase = open_contents("normalized.dat", &contents).expect("valid .DAT");
+
+        let read = |path: &str| {
+            let mut data = Vec::new();
+            dbase.open_entry(path).ok()?.read_to_end(&mut data).ok()?;
+            Some(data)
+        };
+
+        assert_eq!(read("art/critters/hmjmpsaa.frm"), Some(b"frm".to_vec()));
+        assert_eq!(read("ART\\CRITTERS\\HMJMPSAA.FRM"), Some(b"frm".to_vec()));
+        assert_eq!(
+            read("./art//critters\\.\\HmJmPsAa.frm"),
+            Some(b"frm".to_vec())
+        );
+        assert_eq!(read("/text/english/game/misc.msg"), Some(b"misc".to_vec()));
+        assert_eq!(read("MAPS\\ARCAVES.MAP"), Some(b"map".to_vec()));
+        assert_eq!(read("maps"), None);
+        assert_eq!(read("arcaves.map"), None);
+
+        let path = CString::new("art/critters//hmjmpsaa.frm").expect("");
+        let mode = CString::new("rb").expect("");
+        let dfile = unsafe { rust_dfile_open(&dbase, path.as_ptr(), mode.as_ptr()) };
+        assert_eq!(dfile.map(|dfile| dfile.size()), Some(3));
+    }
+
+    #[test]
+    fn test_dfile_text_seek() {
+        let text = b"first\r\nsecond\r\n\r\nthird\rline\r\n".repeat(3000);
+
+        for (threshold, compressed) in [(0, true), (usize::MAX, false)] {
+            let dat_path = temp_path(&format!("text_seek_{}.dat", compressed));
+
+            let mut writer = DBaseWriter::new();
+            writer.set_compression_threshold(threshold);
+            writer.add_data("text.txt", text.clone()).expect("");
+            writer.write(&dat_path).expect("");
+
+            let dbase = DBase::open(&dat_path).expect("valid .DAT");
+            let path = CString::new("text.txt").expect("");
+            let mode = CString::new("rt").expect("");
+            unsafe {
+                let mut dfile = rust_dfile_open(&dbase, path.as_ptr(), mode.as_ptr()).expect("");
+                assert_eq!(dfile.entry().is_compressed(), compressed);
+
+                // Remember position before every character.
+                let mut positions = Vec::new();
+                let mut chars = Vec::new();
+                loop {
+                    let position = dfile_tell(&dfile);
+                    let ch = dfile_read_char(&mut dfile);
+                    if ch == -1 {
+                        break;
+                    }
+                    positions.push(position);
+                    chars.push(ch);
+                }
+
+                // \r\n is read as \n, but positions are in terms of entry data.
+                assert_eq!(chars.len(), text.len() - 4 * 3000);
+                assert_eq!(chars[..7], b"first\ns".map(|ch| ch as c_int));
+                assert_eq!(positions[6], 7);
+                assert_eq!(dfile_tell(&dfile), text.len() as c_long);
+
+                // Seeking to the end of data is allowed.
+                assert_eq!(dfile_seek(&mut dfile, 0, SEEK_END), 0);
+                assert_eq!(dfile_read_char(&mut dfile), -1);
+
+                for index in [chars.len() - 1, 100, 5, 70000, 6, 0, 40000] {
+                    assert_eq!(dfile_seek(&mut dfile, positions[index], SEEK_SET), 0);
+                    assert_eq!(dfile_read_char(&mut dfile), chars[index]);
+                    assert_eq!(
+                        dfile_tell(&dfile),
+                        positions
+                            .get(index + 1)
+                            .copied()
+                            .unwrap_or(text.len() as c_long)
+                    );
+                }
+
+                // Relative seeks are in terms of entry data as well.
+                assert_eq!(dfile_seek(&mut dfile, positions[40], SEEK_SET), 0);
+                assert_eq!(
+                    dfile_seek(&mut dfile, -(positions[40] - positions[30]), SEEK_CUR),
+                    0
+                );
+                assert_eq!(dfile_read_char(&mut dfile), chars[30]);
+                assert_eq!(dfile_seek(&mut dfile, -2, SEEK_END), 0);
+                assert_eq!(dfile_read_char(&mut dfile), b'\n' as c_int);
+                assert_eq!(dfile_read_char(&mut dfile), -1);
+
+                // Seeking into the middle of \r\n reads the second byte only.
+                assert_eq!(dfile_seek(&mut dfile, -1, SEEK_END), 0);
+                assert_eq!(dfile_read_char(&mut dfile), b'\n' as c_int);
+                assert_eq!(dfile_tell(&dfile), text.len() as c_long);
+            }
+
+            drop(dbase);
+            std::fs::remove_file(&dat_path).expect("");
+        }
+    }
+
+    // Builds ZIP archive with [entries] (path, data, and whether data should
+    // be deflated).
+    fn build_zip(entries: &[(&str, &[u8], bool)]) -> Vec<u8> {
+        use libz_sys::{compress2, uLongf, Z_BEST_COMPRESSION};
+
+        let mut contents = b"self-extractor stub".to_vec();
+        let mut central_directory = Vec::new();
+        for (path, data, deflate) in entries {
+            let stored_data = if *deflate {
+                let mut compressed = vec![0u8; data.len() * 2 + 64];
+                let mut compressed_size = compressed.len() as uLongf;
+                let rc = unsafe {
+                    compress2(
+                        compressed.as_mut_ptr(),
+                        &mut compressed_size,
+                        data.as_ptr(),
+                        data.len() as _,
+                        Z_BEST_COMPRESSION,
+                    )
+                };
+                assert_eq!(rc, 0);
+
+                // Strip zlib header and checksum to get raw deflate data.
+                compressed[2..compressed_size as usize - 4].to_vec()
+            } else {
+                data.to_vec()
+            };
+
+            let method: u16 = if *deflate { 8 } else { 0 };
+            let local_header_offset = contents.len() as u32;
+            let mut header = Vec::new();
+            header.extend_from_slice(&20u16.to_le_bytes());
+            header.extend_from_slice(&0u16.to_le_bytes());
+            header.extend_from_slice(&method.to_le_bytes());
+            header.extend_from_slice(&[0u8; 8]);
+            header.extend_from_slice(&(stored_data.len() as u32).to_le_bytes());
+            header.extend_from_slice(&(data.len() as u32).to_le_bytes());
+            header.extend_from_slice(&(path.len() as u16).to_le_bytes());
+
+            // Local header has extra field which is not in central directory.
+            contents.extend_from_slice(&0x04034B50u32.to_le_bytes());
+            contents.extend_from_slice(&header);
+            contents.extend_from_slice(&4u16.to_le_bytes());
+            contents.extend_from_slice(path.as_bytes());
+            contents.extend_from_slice(b"XXXX");
+            contents.extend_from_slice(&stored_data);
+
+            central_directory.extend_from_slice(&0x02014B50u32.to_le_bytes());
+            central_directory.extend_from_slice(&20u16.to_le_bytes());
+            central_directory.extend_from_slice(&header);
+            central_directory.extend_from_slice(&[0u8; 12]);
+            central_directory.extend_from_slice(&local_header_offset.to_le_bytes());
+            central_directory.extend_from_slice(path.as_bytes());
+        }
+
+        let central_directory_offset = contents.len() as u32;
+        contents.extend_from_slice(&central_directory);
+        contents.extend_from_slice(&0x06054B50u32.to_le_bytes());
+        contents.extend_from_slice(&[0u8; 4]);
+        contents.extend_from_slice(&(entries.len() as u16).to_le_bytes());
+        contents.extend_from_slice(&(entries.len() as u16).to_le_bytes());
+        contents.extend_from_slice(&(central_directory.len() as u32).to_le_bytes());
+        contents.extend_from_slice(&central_directory_offset.to_le_bytes());
+        contents.extend_from_slice(&7u16.to_le_bytes());
+        contents.extend_from_slice(b"comment");
+        contents
+    }
+
+    #[test]
+    fn test_dbase_open_zip() {
+        let data = build_data(150000);
+        let contents = build_zip(&[
+            ("maps/", b"", false),
+            ("maps/arcaves.map", &data, true),
+            ("text/english/game/misc.msg", b"{100}{}{Hello}", false),
+            ("readme.txt", b"", true),
+        ]);
+
+        let dat_path = temp_path("archive.zip");
+        std::fs::write(&dat_path, &contents).expect("");
+
+        for dbase in [
+            DBase::open(&dat_path).expect("valid .ZIP"),
+            DBase::open_mapped(&dat_path).expect("valid .ZIP"),
+        ] {
+            assert_eq!(dbase.format(), DBaseFormat::Zip);
+            let paths = dbase
+                .entries()
+                .map(|entry| entry.path().to_str().expect("").to_string())
+                .collect::<Vec<_>>();
+            assert_eq!(
+                paths,
+                [
+                    "maps\\arcaves.map",
+                    "text\\english\\game\\misc.msg",
+                    "readme.txt"
+                ]
+            );
+
+            let mut dfile = dbase.open_entry("MAPS\\ARCAVES.MAP").expect("");
+            assert!(dfile.entry().is_compressed());
+            let mut output = Vec::new();
+            dfile.read_to_end(&mut output).expect("");
+            assert_eq!(output, data);
+
+            dfile.seek(SeekFrom::Start(70000)).expect("");
+            let mut buffer = [0u8; 100];
+            dfile.read_exact(&mut buffer).expect("");
+            assert_eq!(buffer, data[70000..70100]);
+
+            let mut output = Vec::new();
+            let mut dfile = dbase.open_entry("text/english/game/misc.msg").expect("");
+            dfile.read_to_end(&mut output).expect("");
+            assert_eq!(output, b"{100}{}{Hello}");
+
+            let mut output = Vec::new();
+            let mut dfile = dbase.open_entry("readme.txt").expect("");
+            dfile.read_to_end(&mut output).expect("");
+            assert!(output.is_empty());
+
+            assert!(dbase.verify().iter().all(|report| report.is_ok()));
+        }
+
+        // Unsupported compression method.
+        let mut contents = build_zip(&[("a.txt", b"hello", false)]);
+        let central_directory_offset = contents.len() - 22 - 7 - 51;
+        contents[central_directory_offset + 10] = 14;
+        assert!(matches!(
+            open_contents("lzma.zip", &contents),
+            Err(DatError::BadEntry { index: 0, .. })
+        ));
+
+        std::fs::remove_file(&dat_path).expect("");
+    }
+}
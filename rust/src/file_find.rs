@@ -1,11 +1,7 @@
 #[cfg(not(target_family = "windows"))]
 use crate::fpattern::fpattern_match;
 #[cfg(not(target_family = "windows"))]
-use crate::platform_compat::COMPAT_MAX_PATH;
-#[cfg(not(target_family = "windows"))]
-use crate::platform_compat::{
-    rust_compat_makepath, rust_compat_splitpath, COMPAT_MAX_DIR, COMPAT_MAX_DRIVE,
-};
+use crate::platform_compat::compat_directory_part;
 use libc::c_char;
 #[cfg(target_family = "windows")]
 #[cfg(not(target_family = "windows"))]
@@ -13,12 +9,14 @@ use libc::DIR;
 #[cfg(not(target_family = "windows"))]
 use libc::DT_DIR;
 #[cfg(not(target_family = "windows"))]
-use libc::{closedir, opendir, readdir, strcpy};
+use libc::{closedir, opendir, readdir};
 #[cfg(not(target_family = "windows"))]
 use libc::{dirent, DIR};
 #[cfg(target_family = "windows")]
 use std::os::windows::raw::HANDLE;
 #[cfg(not(target_family = "windows"))]
+use std::ffi::{CStr, CString};
+#[cfg(not(target_family = "windows"))]
 use std::ptr::null;
 use std::ptr::null_mut;
 #[cfg(target_family = "windows")]
@@ -72,7 +70,8 @@ impl Default for DirectoryFileFindData {
 pub struct DirectoryFileFindData {
     dir: *mut DIR,
     entry: *const dirent,
-    path: [c_char; COMPAT_MAX_PATH],
+    // Pattern passed to [file_find_first] (not limited to [COMPAT_MAX_PATH]).
+    path: CString,
 }
 
 #[cfg(not(target_family = "windows"))]
@@ -81,7 +80,7 @@ impl Default for DirectoryFileFindData {
         DirectoryFileFindData {
             dir: null_mut(),
             entry: null(),
-            path: [0 as c_char; COMPAT_MAX_PATH],
+            path: CString::default(),
         }
     }
 }
@@ -103,55 +102,37 @@ pub unsafe fn file_find_first(path: *const c_char, find_data: *mut DirectoryFile
 
 #[cfg(not(target_family = "windows"))]
 pub unsafe fn file_find_first(path: *const c_char, find_data: *mut DirectoryFileFindData) -> bool {
-    strcpy((*find_data).path.as_mut_ptr(), path);
-
-    let mut drive = [0 as c_char; COMPAT_MAX_DRIVE as usize];
-    let mut dir = [0 as c_char; COMPAT_MAX_DIR as usize];
-    rust_compat_splitpath(
-        path,
-        drive.as_mut_ptr(),
-        dir.as_mut_ptr(),
-        null_mut(),
-        null_mut(),
-    );
-
-    let mut base_path = [0 as c_char; COMPAT_MAX_PATH];
-    rust_compat_makepath(
-        base_path.as_mut_ptr(),
-        drive.as_ptr(),
-        dir.as_ptr(),
-        null_mut(),
-        null(),
-    );
+    (*find_data).path = CStr::from_ptr(path).to_owned();
+
+    let base_path = compat_directory_part((*find_data).path.to_bytes());
+    let base_path = CString::new(base_path).expect("no null characters");
 
     (*find_data).dir = opendir(base_path.as_ptr());
-    if (*find_data).dir == null_mut() {
+    if (*find_data).dir.is_null() {
         return false;
     }
 
     (*find_data).entry = readdir((*find_data).dir);
-    while (*find_data).entry != null() {
-        let mut entry_path = [0 as c_char; COMPAT_MAX_PATH];
-        rust_compat_makepath(
-            entry_path.as_mut_ptr(),
-            drive.as_ptr(),
-            dir.as_ptr(),
-            file_find_get_name(find_data),
-            null(),
-        );
+    file_find_skip_mismatched(find_data)
+}
+
+// Skips entries (starting with the current one) which don't match the
+// pattern. The directory is closed when there are no more entries.
+#[cfg(not(target_family = "windows"))]
+unsafe fn file_find_skip_mismatched(find_data: *mut DirectoryFileFindData) -> bool {
+    while !(*find_data).entry.is_null() {
+        let mut entry_path = compat_directory_part((*find_data).path.to_bytes()).to_vec();
+        entry_path.extend_from_slice(CStr::from_ptr(file_find_get_name(find_data)).to_bytes());
+        let entry_path = CString::new(entry_path).expect("no null characters");
         if fpattern_match((*find_data).path.as_ptr(), entry_path.as_ptr()) {
-            break;
+            return true;
         }
         (*find_data).entry = readdir((*find_data).dir);
     }
 
-    if (*find_data).entry == null() {
-        closedir((*find_data).dir);
-        (*find_data).dir = null_mut();
-        return false;
-    }
-
-    true
+    closedir((*find_data).dir);
+    (*find_data).dir = null_mut();
+    false
 }
 
 #[cfg(target_family = "windows")]
@@ -163,39 +144,8 @@ pub unsafe fn file_find_next(find_data: *mut DirectoryFileFindData) -> bool {
 #[no_mangle]
 #[cfg(not(target_family = "windows"))]
 pub unsafe fn file_find_next(find_data: *mut DirectoryFileFindData) -> bool {
-    let mut drive = [0 as c_char; COMPAT_MAX_DRIVE as usize];
-    let mut dir = [0 as c_char; COMPAT_MAX_DIR as usize];
-    rust_compat_splitpath(
-        (*find_data).path.as_mut_ptr(),
-        drive.as_mut_ptr(),
-        dir.as_mut_ptr(),
-        null_mut(),
-        null_mut(),
-    );
-
     (*find_data).entry = readdir((*find_data).dir);
-    while (*find_data).entry != null() {
-        let mut entry_path = [0 as c_char; COMPAT_MAX_PATH];
-        rust_compat_makepath(
-            entry_path.as_mut_ptr(),
-            drive.as_ptr(),
-            dir.as_ptr(),
-            file_find_get_name(find_data),
-            null(),
-        );
-        if fpattern_match((*find_data).path.as_ptr(), entry_path.as_ptr()) {
-            break;
-        }
-        (*find_data).entry = readdir((*find_data).dir)
-    }
-
-    if (*find_data).entry == null() {
-        closedir((*find_data).dir);
-        (*find_data).dir = null_mut();
-        return false;
-    }
-
-    true
+    file_find_skip_mismatched(find_data)
 }
 
 #[cfg(target_family = "windows")]
@@ -0,0 +1,203 @@
+// A safe wrapper around zlib inflate stream.
+//
+// The stream is kept on the heap, since zlib keeps a back pointer to it in the
+// internal state, so it must never move after initialization.
+
+use libc::{c_int, c_uint};
+use libz_sys::{
+    inflate, inflateCopy, inflateEnd, inflateInit2_, inflateReset, z_stream, zlibVersion, Bytef,
+    Z_BUF_ERROR, Z_NO_FLUSH, Z_OK, Z_STREAM_END,
+};
+use std::ffi::CStr;
+use std::mem::{self, MaybeUninit};
+
+// The window bits for zlib wrapped data (as used by .DAT files).
+pub const INFLATER_WINDOW_BITS_ZLIB: c_int = 15;
+
+// The window bits for raw deflate data (as used by ZIP archives).
+pub const INFLATER_WINDOW_BITS_RAW: c_int = -15;
+
+// The result of a single [Inflater::inflate] call.
+pub struct InflateProgress {
+    // The number of input bytes consumed.
+    pub consumed: usize,
+
+    // The number of output bytes produced.
+    pub produced: usize,
+
+    // Specifies that the end of compressed stream was reached.
+    pub finished: bool,
+}
+
+pub struct Inflater {
+    // NOTE: The stream is never read as a Rust value. It's zero-initialized
+    // (which means default allocator in terms of zlib), and only accessed
+    // by zlib itself and via individual fields.
+    stream: Box<MaybeUninit<z_stream>>,
+}
+
+// SAFETY: The stream is exclusively owned, zlib does not use any thread-local
+// state.
+unsafe impl Send for Inflater {}
+
+// SAFETY: The stream is only modified via `&mut self` ([Inflater::try_clone]
+// only reads it).
+unsafe impl Sync for Inflater {}
+
+impl Inflater {
+    // Creates inflate stream, see [INFLATER_WINDOW_BITS_ZLIB] and
+    // [INFLATER_WINDOW_BITS_RAW].
+    pub fn new(window_bits: c_int) -> Option<Self> {
+        let mut inflater = Self {
+            stream: Box::new(MaybeUninit::zeroed()),
+        };
+
+        let rc = unsafe {
+            inflateInit2_(
+                inflater.stream.as_mut_ptr(),
+                window_bits,
+                zlibVersion(),
+                mem::size_of::<z_stream>() as c_int,
+            )
+        };
+        if rc != Z_OK {
+            // NOTE: Failed stream has no internal state, so [inflateEnd] in
+            // [Drop] is a no-op.
+            return None;
+        }
+
+        Some(inflater)
+    }
+
+    // Restarts decompression from the beginning.
+    pub fn reset(&mut self) -> bool {
+        unsafe { inflateReset(self.stream.as_mut_ptr()) == Z_OK }
+    }
+
+    // Creates an independent copy of decompression state.
+    pub fn try_clone(&self) -> Option<Self> {
+        let mut inflater = Self {
+            stream: Box::new(MaybeUninit::zeroed()),
+        };
+
+        // NOTE: [inflateCopy] does not modify source, but it's declared
+        // without const.
+        let rc = unsafe {
+            inflateCopy(
+                inflater.stream.as_mut_ptr(),
+                self.stream.as_ptr() as *mut z_stream,
+            )
+        };
+        if rc != Z_OK {
+            return None;
+        }
+
+        Some(inflater)
+    }
+
+    // Decompresses [input] into [output], returning the amount of consumed and
+    // produced data, or zlib error message.
+    pub fn inflate(&mut self, input: &[u8], output: &mut [u8]) -> Result<InflateProgress, String> {
+        let stream = self.stream.as_mut_ptr();
+
+        // zlib sizes are 32-bit, larger buffers are processed partially.
+        let avail_in = input.len().min(c_uint::MAX as usize) as c_uint;
+        let avail_out = output.len().min(c_uint::MAX as usize) as c_uint;
+
+        let rc = unsafe {
+            (*stream).next_in = input.as_ptr() as *mut Bytef;
+            (*stream).avail_in = avail_in;
+            (*stream).next_out = output.as_mut_ptr();
+            (*stream).avail_out = avail_out;
+            inflate(stream, Z_NO_FLUSH)
+        };
+
+        let (consumed, produced) = unsafe {
+            (
+                (avail_in - (*stream).avail_in) as usize,
+                (avail_out - (*stream).avail_out) as usize,
+            )
+        };
+
+        match rc {
+            // Z_BUF_ERROR only means no progress was possible, which is
+            // reported to the caller via zero [consumed] and [produced].
+            Z_OK | Z_BUF_ERROR => Ok(InflateProgress {
+                consumed,
+                produced,
+                finished: false,
+            }),
+            Z_STREAM_END => Ok(InflateProgress {
+                consumed,
+                produced,
+                finished: true,
+            }),
+            _ => Err(self.error_message(rc)),
+        }
+    }
+
+    fn error_message(&self, rc: c_int) -> String {
+        let msg = unsafe { (*self.stream.as_ptr()).msg };
+        if msg.is_null() {
+            format!("zlib error {}", rc)
+        } else {
+            unsafe { CStr::from_ptr(msg) }
+                .to_string_lossy()
+                .into_owned()
+        }
+    }
+}
+
+impl Drop for Inflater {
+    fn drop(&mut self) {
+        unsafe {
+            inflateEnd(self.stream.as_mut_ptr());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use libz_sys::{compress2, uLongf, Z_BEST_COMPRESSION};
+
+    #[test]
+    fn test_inflater_clone() {
+        let data: Vec<u8> = (0..10000u32).map(|index| (index % 251) as u8).collect();
+
+        let mut compressed = vec![0u8; data.len() * 2];
+        let mut compressed_size = compressed.len() as uLongf;
+        let rc = unsafe {
+            compress2(
+                compressed.as_mut_ptr(),
+                &mut compressed_size,
+                data.as_ptr(),
+                data.len() as _,
+                Z_BEST_COMPRESSION,
+            )
+        };
+        assert_eq!(rc, Z_OK);
+        compressed.truncate(compressed_size as usize);
+
+        let mut inflater = Inflater::new(INFLATER_WINDOW_BITS_ZLIB).expect("");
+        let mut output = vec![0u8; data.len()];
+        let progress = inflater
+            .inflate(&compressed, &mut output[..5000])
+            .expect("");
+        assert_eq!(progress.produced, 5000);
+        assert!(!progress.finished);
+
+        // Both copies continue independently from the same point.
+        let mut copy = inflater.try_clone().expect("");
+        let input = &compressed[progress.consumed..];
+        let mut copy_output = vec![0u8; 5000];
+        let copy_progress = copy.inflate(input, &mut copy_output).expect("");
+        let progress = inflater.inflate(input, &mut output[5000..]).expect("");
+        assert!(progress.finished && copy_progress.finished);
+        assert_eq!(output, data);
+        assert_eq!(copy_output, data[5000..]);
+
+        assert!(inflater.reset());
+        assert!(inflater.inflate(b"garbage", &mut output).is_err());
+    }
+}
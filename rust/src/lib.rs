@@ -1,12 +1,27 @@
 mod audio_engine;
-mod dfile;
+pub mod dbase_writer;
+mod debug_print;
+pub mod dfile;
 mod dinput;
 mod file_find;
 mod file_utils;
 mod fpattern;
 mod fps_limiter;
+mod inflater;
+mod lzss;
+mod memory_map;
+pub mod mod_manager;
 mod platform_compat;
+#[cfg(test)]
+mod test_utils;
 mod version;
+pub mod vfs;
 mod win32;
+pub mod xbase_backend;
+mod xbase_embedded;
+mod xbase_overlay;
+mod xbase_watcher;
 mod xfile;
+mod xfile_memory;
+mod xfile_trace;
 mod db;
@@ -0,0 +1,230 @@
+// LZSS decompression used by Fallout 1 .DAT files.
+//
+// Compressed entry is a sequence of blocks, every block starts with big-endian
+// 16-bit length. Zero length denotes the end of data, negative length denotes
+// raw (uncompressed) block of that many bytes, positive length denotes LZSS
+// compressed block. Every compressed block starts with fresh dictionary.
+
+// The size of the ring buffer.
+const LZSS_DICTIONARY_SIZE: usize = 4096;
+
+// The maximum length of a match.
+const LZSS_MAX_MATCH: usize = 18;
+
+// The minimum length of a match, shorter sequences are encoded as literals.
+const LZSS_MIN_MATCH: usize = 3;
+
+#[derive(Clone, Copy, PartialEq)]
+enum LzssBlock {
+    // Block header should be read next.
+    None,
+
+    // Reading uncompressed block.
+    Raw,
+
+    // Reading compressed block.
+    Compressed,
+
+    // End of data marker was read.
+    End,
+}
+
+// Streaming LZSS decoder.
+//
+// The decoder does not own compressed data, it pulls bytes one by one from the
+// source passed to [decode], so that it can be fed from any stream.
+#[derive(Clone)]
+pub struct LzssDecoder {
+    dictionary: [u8; LZSS_DICTIONARY_SIZE],
+    dictionary_index: usize,
+
+    block: LzssBlock,
+
+    // The number of input bytes left in current block.
+    block_remaining: usize,
+
+    // The flags of current group of 8 tokens. The high byte is used as a
+    // counter of remaining flags.
+    flags: u32,
+
+    // The position in dictionary of current match and the number of bytes
+    // left to copy.
+    match_offset: usize,
+    match_remaining: usize,
+}
+
+impl Default for LzssDecoder {
+    fn default() -> Self {
+        Self {
+            dictionary: [0x20; LZSS_DICTIONARY_SIZE],
+            dictionary_index: LZSS_DICTIONARY_SIZE - LZSS_MAX_MATCH,
+            block: LzssBlock::None,
+            block_remaining: 0,
+            flags: 0,
+            match_offset: 0,
+            match_remaining: 0,
+        }
+    }
+}
+
+impl LzssDecoder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // Resets the decoder to the beginning of data.
+    pub fn reset(&mut self) {
+        *self = Self::default();
+    }
+
+    fn reset_dictionary(&mut self) {
+        self.dictionary.fill(0x20);
+        self.dictionary_index = LZSS_DICTIONARY_SIZE - LZSS_MAX_MATCH;
+        self.flags = 0;
+        self.match_remaining = 0;
+    }
+
+    fn put(&mut self, ch: u8) {
+        self.dictionary[self.dictionary_index] = ch;
+        self.dictionary_index = (self.dictionary_index + 1) % LZSS_DICTIONARY_SIZE;
+    }
+
+    // Reads next byte of current block.
+    fn next_block_byte(&mut self, source: &mut impl FnMut() -> Option<u8>) -> Option<u8> {
+        if self.block_remaining == 0 {
+            return None;
+        }
+
+        let ch = source()?;
+        self.block_remaining -= 1;
+        Some(ch)
+    }
+
+    // Decompresses data into [buffer] pulling compressed bytes from [source].
+    //
+    // Returns the number of bytes written, which is less than the size of
+    // [buffer] only when end of data is reached (or [source] is exhausted).
+    pub fn decode(&mut self, buffer: &mut [u8], mut source: impl FnMut() -> Option<u8>) -> usize {
+        let mut written = 0;
+
+        while written < buffer.len() {
+            // Pending match from previous call.
+            if self.match_remaining != 0 {
+                let ch = self.dictionary[self.match_offset];
+                self.match_offset = (self.match_offset + 1) % LZSS_DICTIONARY_SIZE;
+                self.match_remaining -= 1;
+                self.put(ch);
+                buffer[written] = ch;
+                written += 1;
+                continue;
+            }
+
+            match self.block {
+                LzssBlock::End => break,
+                LzssBlock::None => {
+                    let Some(high) = source() else {
+                        break;
+                    };
+                    let Some(low) = source() else {
+                        break;
+                    };
+
+                    let length = i16::from_be_bytes([high, low]);
+                    if length == 0 {
+                        self.block = LzssBlock::End;
+                    } else if length < 0 {
+                        self.block = LzssBlock::Raw;
+                        self.block_remaining = length.unsigned_abs() as usize;
+                    } else {
+                        self.block = LzssBlock::Compressed;
+                        self.block_remaining = length as usize;
+                        self.reset_dictionary();
+                    }
+                }
+                LzssBlock::Raw => match self.next_block_byte(&mut source) {
+                    Some(ch) => {
+                        buffer[written] = ch;
+                        written += 1;
+                    }
+                    None => self.block = LzssBlock::None,
+                },
+                LzssBlock::Compressed => {
+                    if (self.flags & 0xFF00) == 0 {
+                        match self.next_block_byte(&mut source) {
+                            Some(flags) => self.flags = flags as u32 | 0xFF00,
+                            None => {
+                                self.block = LzssBlock::None;
+                                continue;
+                            }
+                        }
+                    }
+
+                    let is_literal = (self.flags & 1) != 0;
+                    self.flags >>= 1;
+
+                    if is_literal {
+                        match self.next_block_byte(&mut source) {
+                            Some(ch) => {
+                                self.put(ch);
+                                buffer[written] = ch;
+                                written += 1;
+                            }
+                            None => self.block = LzssBlock::None,
+                        }
+                    } else {
+                        let low = self.next_block_byte(&mut source);
+                        let high = self.next_block_byte(&mut source);
+                        match (low, high) {
+                            (Some(low), Some(high)) => {
+                                self.match_offset = low as usize | ((high as usize & 0xF0) << 4);
+                                self.match_remaining = (high as usize & 0x0F) + LZSS_MIN_MATCH;
+                            }
+                            _ => self.block = LzssBlock::None,
+                        }
+                    }
+                }
+            }
+        }
+
+        written
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn decode_all(data: &[u8], chunk_size: usize) -> Vec<u8> {
+        let mut decoder = LzssDecoder::new();
+        let mut input = data.iter().copied();
+        let mut output = Vec::new();
+        loop {
+            let mut buffer = vec![0u8; chunk_size];
+            let written = decoder.decode(&mut buffer, || input.next());
+            output.extend_from_slice(&buffer[..written]);
+            if written < chunk_size {
+                break;
+            }
+        }
+        output
+    }
+
+    #[test]
+    fn test_lzss_decode() {
+        let data = [
+            // Raw block of 5 bytes.
+            0xFF, 0xFB, b'H', b'E', b'L', b'L', b'O',
+            // Compressed block: 3 literals followed by a match of length 3
+            // at the initial dictionary position.
+            0x00, 0x06, 0x07, b'A', b'B', b'C', 0xEE, 0xF0,
+            // Compressed block referring to the initial (space-filled)
+            // dictionary, followed by end of data marker.
+            0x00, 0x03, 0x00, 0x00, 0x00, 0x00, 0x00,
+        ];
+
+        let expected = b"HELLOABCABC   ".to_vec();
+        assert_eq!(decode_all(&data, 100), expected);
+        assert_eq!(decode_all(&data, 1), expected);
+        assert_eq!(decode_all(&data, 4), expected);
+    }
+}
@@ -0,0 +1,123 @@
+// A read-only memory mapping of an entire file.
+//
+// NOTE: The mapping reflects the file on disk, so truncating the file while it
+// is mapped makes accessing the tail of the mapping fail (SIGBUS on Unix).
+// This is the reason mapping is opt-in, see [rust_dbase_set_memory_mapping].
+
+use std::fs::File;
+use std::io;
+use std::ops::Deref;
+use std::slice;
+
+#[cfg(not(target_family = "windows"))]
+use std::os::unix::io::AsRawFd;
+#[cfg(target_family = "windows")]
+use std::os::windows::io::AsRawHandle;
+#[cfg(target_family = "windows")]
+use windows::core::PCWSTR;
+#[cfg(target_family = "windows")]
+use windows::Win32::Foundation::{CloseHandle, HANDLE};
+#[cfg(target_family = "windows")]
+use windows::Win32::System::Memory::{
+    CreateFileMappingW, MapViewOfFile, UnmapViewOfFile, FILE_MAP_READ, MEMORYMAPPEDVIEW_HANDLE,
+    PAGE_READONLY,
+};
+
+pub struct MemoryMap {
+    ptr: *const u8,
+    len: usize,
+}
+
+// SAFETY: The mapping is read-only and is not tied to the thread which created
+// it.
+unsafe impl Send for MemoryMap {}
+unsafe impl Sync for MemoryMap {}
+
+impl MemoryMap {
+    // Maps the first [len] bytes of [file].
+    //
+    // Empty mappings are not supported by the OS, so [len] must not be zero.
+    #[cfg(not(target_family = "windows"))]
+    pub fn new(file: &File, len: u64) -> io::Result<Self> {
+        let len = memory_map_checked_len(len)?;
+
+        let ptr = unsafe {
+            libc::mmap(
+                std::ptr::null_mut(),
+                len,
+                libc::PROT_READ,
+                libc::MAP_PRIVATE,
+                file.as_raw_fd(),
+                0,
+            )
+        };
+        if ptr == libc::MAP_FAILED {
+            return Err(io::Error::last_os_error());
+        }
+
+        Ok(Self {
+            ptr: ptr as *const u8,
+            len,
+        })
+    }
+
+    #[cfg(target_family = "windows")]
+    pub fn new(file: &File, len: u64) -> io::Result<Self> {
+        let len = memory_map_checked_len(len)?;
+
+        unsafe {
+            let mapping = CreateFileMappingW(
+                HANDLE(file.as_raw_handle() as isize),
+                None,
+                PAGE_READONLY,
+                0,
+                0,
+                PCWSTR::null(),
+            )?;
+
+            // The view keeps the mapping alive, so the mapping handle is not
+            // needed anymore.
+            let view = MapViewOfFile(mapping, FILE_MAP_READ, 0, 0, len);
+            CloseHandle(mapping);
+
+            Ok(Self {
+                ptr: view?.0 as *const u8,
+                len,
+            })
+        }
+    }
+}
+
+fn memory_map_checked_len(len: u64) -> io::Result<usize> {
+    match usize::try_from(len) {
+        Ok(len) if len != 0 => Ok(len),
+        _ => Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "invalid memory mapping size",
+        )),
+    }
+}
+
+impl Deref for MemoryMap {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        unsafe { slice::from_raw_parts(self.ptr, self.len) }
+    }
+}
+
+impl Drop for MemoryMap {
+    #[cfg(not(target_family = "windows"))]
+    fn drop(&mut self) {
+        unsafe {
+            libc::munmap(self.ptr as *mut libc::c_void, self.len);
+        }
+    }
+
+    #[cfg(target_family = "windows")]
+    fn drop(&mut self) {
+        unsafe {
+            UnmapViewOfFile(MEMORYMAPPEDVIEW_HANDLE(self.ptr as isize));
+        }
+    }
+}
@@ -0,0 +1,342 @@
+// Mod load order.
+//
+// Load order is described by a manifest, which is a text file with one mod
+// per line:
+//
+//     ; enabled priority path
+//     1 10 mods\hd_portraits
+//     1 20 mods\restoration.dat
+//     0 30 mods\broken
+//
+// Empty lines and lines starting with `;` or `#` are ignored. Mods with higher
+// priority take precedence over mods with lower priority (and over xbases
+// which are already open). When priorities are equal, the mod listed later
+// takes precedence, which matches the order of [rust_xbase_reopen_all].
+
+use crate::dfile::dbase_normalize_path;
+use crate::vfs::Vfs;
+use std::collections::{HashMap, HashSet};
+use std::error;
+use std::ffi::CString;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+// A mod listed in the manifest.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ModEntry {
+    // The path to mod directory or .DAT file (in terms of [Vfs::open_xbase]).
+    pub path: CString,
+
+    pub enabled: bool,
+    pub priority: i32,
+}
+
+#[derive(Debug)]
+pub enum ModManifestError {
+    // The manifest cannot be read.
+    Io(io::Error),
+
+    // The line (1-based) is malformed.
+    BadLine { line: usize, reason: &'static str },
+}
+
+impl fmt::Display for ModManifestError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ModManifestError::Io(err) => write!(f, "{}", err),
+            ModManifestError::BadLine { line, reason } => write!(f, "line {}: {}", line, reason),
+        }
+    }
+}
+
+impl error::Error for ModManifestError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            ModManifestError::Io(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for ModManifestError {
+    fn from(err: io::Error) -> Self {
+        ModManifestError::Io(err)
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ModManifest {
+    // Mods in the order of manifest.
+    entries: Vec<ModEntry>,
+}
+
+impl ModManifest {
+    pub fn load(path: &Path) -> Result<Self, ModManifestError> {
+        let data = fs::read(path)?;
+        Self::parse(&String::from_utf8_lossy(&data))
+    }
+
+    pub fn parse(text: &str) -> Result<Self, ModManifestError> {
+        let mut entries = Vec::new();
+        for (index, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with(';') || line.starts_with('#') {
+                continue;
+            }
+
+            let bad_line = |reason| ModManifestError::BadLine {
+                line: index + 1,
+                reason,
+            };
+
+            let (enabled, rest) = line
+                .split_once(char::is_whitespace)
+                .ok_or_else(|| bad_line("missing priority"))?;
+            let enabled = match enabled {
+                "0" => false,
+                "1" => true,
+                _ => return Err(bad_line("enabled flag must be 0 or 1")),
+            };
+
+            let (priority, path) = rest
+                .trim_start()
+                .split_once(char::is_whitespace)
+                .ok_or_else(|| bad_line("missing path"))?;
+            let priority = priority
+                .parse()
+                .map_err(|_| bad_line("priority is not a number"))?;
+
+            // NOTE: Paths can contain spaces.
+            let path = CString::new(path.trim()).map_err(|_| bad_line("invalid path"))?;
+
+            entries.push(ModEntry {
+                path,
+                enabled,
+                priority,
+            });
+        }
+
+        Ok(Self { entries })
+    }
+
+    pub fn entries(&self) -> &[ModEntry] {
+        &self.entries
+    }
+
+    // Returns enabled mods in the order they should be opened, that is from
+    // the lowest precedence to the highest one.
+    pub fn load_order(&self) -> Vec<&ModEntry> {
+        let mut load_order = self
+            .entries
+            .iter()
+            .filter(|entry| entry.enabled)
+            .collect::<Vec<_>>();
+
+        // NOTE: Sort is stable, so equal priorities keep manifest order.
+        load_order.sort_by_key(|entry| entry.priority);
+        load_order
+    }
+
+    // Returns load order as semicolon-separated list of paths, which can be
+    // passed to [rust_xbase_reopen_all].
+    pub fn search_path(&self) -> CString {
+        let paths = self
+            .load_order()
+            .iter()
+            .map(|entry| entry.path.as_bytes())
+            .collect::<Vec<_>>();
+        CString::new(paths.join(&b';')).expect("no null characters")
+    }
+
+    // Opens enabled mods in [vfs] on top of already open xbases.
+    //
+    // Mods which cannot be opened are skipped, the rest of load order is
+    // mounted anyway. Returns the paths of skipped mods along with errors.
+    pub fn mount(&self, vfs: &Vfs) -> Vec<(CString, io::Error)> {
+        self.load_order()
+            .iter()
+            .filter_map(|entry| {
+                vfs.open_xbase(&entry.path)
+                    .err()
+                    .map(|err| (entry.path.clone(), err))
+            })
+            .collect()
+    }
+}
+
+// A file which is provided by more than one xbase.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ModConflict {
+    // The path of the file (as reported by the winning xbase).
+    pub path: CString,
+
+    // The paths of xbases which provide the file, in search order, so the
+    // first one is the one which wins.
+    pub sources: Vec<CString>,
+}
+
+// Finds files which are provided by more than one xbase of [vfs].
+//
+// Conflicts are sorted by path.
+pub fn mod_conflicts(vfs: &Vfs) -> Vec<ModConflict> {
+    let mut conflicts: Vec<ModConflict> = Vec::new();
+    let mut index = HashMap::new();
+    vfs.visit_xbases(&mut |source, backend| {
+        // Some sources (such as .DAT files with duplicated entries) can
+        // provide the same file twice.
+        let mut seen = HashSet::new();
+        backend.files(&mut |path| {
+            let key = dbase_normalize_path(path.to_bytes());
+            if !seen.insert(key.clone()) {
+                return true;
+            }
+
+            let conflict_index = *index.entry(key).or_insert_with(|| {
+                conflicts.push(ModConflict {
+                    path: path.to_owned(),
+                    sources: Vec::new(),
+                });
+                conflicts.len() - 1
+            });
+            conflicts[conflict_index].sources.push(source.to_owned());
+            true
+        });
+    });
+
+    conflicts.retain(|conflict| conflict.sources.len() > 1);
+    conflicts.sort_by_cached_key(|conflict| dbase_normalize_path(conflict.path.to_bytes()));
+    conflicts
+}
+
+// Formats [conflicts] as human-readable report.
+pub fn mod_conflict_report(conflicts: &[ModConflict]) -> String {
+    let mut report = String::new();
+    for conflict in conflicts {
+        report.push_str(&format!(
+            "{} <- {}\n",
+            conflict.path.to_string_lossy(),
+            conflict.sources[0].to_string_lossy()
+        ));
+        for source in &conflict.sources[1..] {
+            report.push_str(&format!("    shadows {}\n", source.to_string_lossy()));
+        }
+    }
+    report
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dbase_writer::DBaseWriter;
+    use crate::test_utils::{cstring, temp_path};
+    use std::path::PathBuf;
+
+    #[test]
+    fn test_mod_manifest_parse() {
+        let manifest = ModManifest::parse(
+            "; comment\n\
+             1 20 mods\\second\n\
+             \n\
+             0 5 mods\\disabled\n\
+             1 10 mods\\with space.dat\n\
+             1 20 mods\\third\n",
+        )
+        .expect("");
+        assert_eq!(manifest.entries().len(), 4);
+        assert_eq!(
+            manifest.search_path(),
+            cstring("mods\\with space.dat;mods\\second;mods\\third")
+        );
+
+        assert!(matches!(
+            ModManifest::parse("1 ten mods"),
+            Err(ModManifestError::BadLine { line: 1, .. })
+        ));
+        assert!(matches!(
+            ModManifest::parse("\n2 10 mods"),
+            Err(ModManifestError::BadLine { line: 2, .. })
+        ));
+        assert!(matches!(
+            ModManifest::parse("1 10"),
+            Err(ModManifestError::BadLine { line: 1, .. })
+        ));
+    }
+
+    #[test]
+    fn test_mod_conflicts() {
+        let base = temp_path("base.dat");
+        let first = temp_path("first");
+        let second = temp_path("second");
+        let broken = temp_path("broken");
+        fs::write(&broken, b"neither directory nor .DAT file").expect("");
+        fs::create_dir_all(first.join("art")).expect("");
+        fs::create_dir_all(second.join("ART")).expect("");
+        fs::write(first.join("art").join("shared.frm"), b"first").expect("");
+        fs::write(first.join("first.txt"), b"first").expect("");
+        fs::write(second.join("ART").join("SHARED.FRM"), b"second").expect("");
+        fs::write(second.join("readme.txt"), b"second").expect("");
+
+        let mut writer = DBaseWriter::new();
+        writer
+            .add_data("art\\shared.frm", b"base".to_vec())
+            .expect("");
+        writer.add_data("readme.txt", b"base".to_vec()).expect("");
+        writer.write(&base).expect("");
+
+        // The second mod has higher priority, despite listed first. Broken
+        // mod does not prevent others from being mounted.
+        let manifest = ModManifest::parse(&format!(
+            "1 20 {}\n1 15 {}\n1 10 {}\n",
+            second.display(),
+            broken.display(),
+            first.display()
+        ))
+        .expect("");
+
+        let vfs = Vfs::new();
+        vfs.open_xbase(&cstring(base.to_str().expect("")))
+            .expect("");
+        let failures = manifest.mount(&vfs);
+        assert_eq!(failures.len(), 1);
+        assert_eq!(failures[0].0, cstring(broken.to_str().expect("")));
+
+        assert_eq!(
+            vfs.read_file(&cstring("art\\shared.frm")),
+            Some(b"second".to_vec())
+        );
+
+        let conflicts = mod_conflicts(&vfs);
+        let summary = conflicts
+            .iter()
+            .map(|conflict| {
+                (
+                    String::from_utf8(dbase_normalize_path(conflict.path.to_bytes())).expect(""),
+                    conflict.sources.clone(),
+                )
+            })
+            .collect::<Vec<_>>();
+        let source = |path: &PathBuf| cstring(path.to_str().expect(""));
+        assert_eq!(
+            summary,
+            [
+                (
+                    "ART\\SHARED.FRM".to_string(),
+                    vec![source(&second), source(&first), source(&base)]
+                ),
+                (
+                    "README.TXT".to_string(),
+                    vec![source(&second), source(&base)]
+                ),
+            ]
+        );
+        assert!(mod_conflict_report(&conflicts).contains("shadows"));
+
+        vfs.close_all();
+        fs::remove_dir_all(&first).expect("");
+        fs::remove_dir_all(&second).expect("");
+        fs::remove_file(&base).expect("");
+        fs::remove_file(&broken).expect("");
+    }
+}
@@ -1,23 +1,41 @@
+#[cfg(not(target_family = "windows"))]
+use crate::debug_print::debug_print;
 use libc::{
     access, c_char, c_int, c_long, c_uint, c_ulong, fgets, fopen, fseek, ftell, lseek, remove,
     rename, strcpy, strlen, FILE, SEEK_CUR, SEEK_END, SEEK_SET,
 };
 #[cfg(not(target_family = "windows"))]
-use libc::{closedir, opendir, readdir, strchr, strncpy};
+use lazy_static::lazy_static;
+#[cfg(not(target_family = "windows"))]
+use libc::{strchr, strncpy};
 use libz_sys::{gzFile, gzgets, gzopen};
-use sdl2_sys::{SDL_itoa, SDL_strcasecmp, SDL_strdup, SDL_strlwr, SDL_strncasecmp, SDL_strupr};
+#[cfg(not(target_family = "windows"))]
+use parking_lot::Mutex;
+use sdl2_sys::{SDL_itoa, SDL_strdup, SDL_strlwr, SDL_strupr};
+#[cfg(not(target_family = "windows"))]
+use std::borrow::Cow;
 use std::cmp::Ordering;
 #[cfg(not(target_family = "windows"))]
-use std::ffi::CString;
+use std::collections::{HashMap, HashSet};
+use std::ffi::{CStr, CString};
+#[cfg(not(target_family = "windows"))]
+use std::ffi::{OsStr, OsString};
+#[cfg(not(target_family = "windows"))]
+use std::os::unix::ffi::{OsStrExt, OsStringExt};
+use std::path::{Path, PathBuf};
 use std::ptr::null_mut;
 #[cfg(not(target_family = "windows"))]
 use std::time::Instant;
+#[cfg(not(target_family = "windows"))]
+use std::{env, fs};
 
 pub const COMPAT_MAX_DRIVE: u8 = 3;
 pub const COMPAT_MAX_DIR: u16 = 256;
 pub const COMPAT_MAX_FNAME: u16 = 256;
 pub const COMPAT_MAX_EXT: u16 = 256;
 
+// The size of path buffers in C++ code. Rust code is not limited by it.
+#[allow(dead_code)]
 pub const COMPAT_MAX_PATH: usize = 260;
 
 #[cfg(not(target_family = "windows"))]
@@ -25,18 +43,39 @@ static NOW: spin::Once<Instant> = spin::Once::new();
 
 #[no_mangle]
 pub extern "C" fn rust_compat_stricmp(string1: *const c_char, string2: *const c_char) -> c_int {
-    unsafe { SDL_strcasecmp(string1, string2) }
+    compat_stricmp_ord(string1, string2) as c_int
 }
 
 pub fn compat_stricmp_ord(string1: *const c_char, string2: *const c_char) -> Ordering {
-    let result = rust_compat_stricmp(string1, string2);
-    if result < 0 {
-        Ordering::Less
-    } else if result > 0 {
-        Ordering::Greater
-    } else {
-        Ordering::Equal
-    }
+    let (string1, string2) = unsafe { (CStr::from_ptr(string1), CStr::from_ptr(string2)) };
+    compat_fold_case(string1.to_bytes()).cmp(compat_fold_case(string2.to_bytes()))
+}
+
+// Same as [compat_stricmp_ord], but compares at most [size] bytes of each
+// string (like `strncasecmp`, a character cut by the limit is compared as
+// invalid UTF-8).
+pub fn compat_strnicmp_ord(string1: *const c_char, string2: *const c_char, size: usize) -> Ordering {
+    let (string1, string2) = unsafe { (CStr::from_ptr(string1), CStr::from_ptr(string2)) };
+    let (string1, string2) = (string1.to_bytes(), string2.to_bytes());
+    let string1 = &string1[..string1.len().min(size)];
+    let string2 = &string2[..string2.len().min(size)];
+    compat_fold_case(string1).cmp(compat_fold_case(string2))
+}
+
+// Folds case of [string] for case-insensitive comparison.
+//
+// Valid UTF-8 characters are uppercased (which matches `SDL_strcasecmp` for
+// ASCII, so sort order of DAT entries is preserved). Bytes which are not valid
+// UTF-8 are kept as is, and sort after all characters.
+fn compat_fold_case(string: &[u8]) -> impl Iterator<Item = u32> + '_ {
+    string.utf8_chunks().flat_map(|chunk| {
+        chunk
+            .valid()
+            .chars()
+            .flat_map(char::to_uppercase)
+            .map(u32::from)
+            .chain(chunk.invalid().iter().map(|&byte| 0x110000 + byte as u32))
+    })
 }
 
 #[no_mangle]
@@ -45,7 +84,7 @@ pub extern "C" fn rust_compat_strnicmp(
     string2: *const c_char,
     size: c_ulong,
 ) -> c_int {
-    unsafe { SDL_strncasecmp(string1, string2, size) }
+    compat_strnicmp_ord(string1, string2, size as usize) as c_int
 }
 
 #[no_mangle]
@@ -239,6 +278,20 @@ pub unsafe extern "C" fn rust_compat_splitpath(
     set_component(ext, ext_start, end, COMPAT_MAX_EXT.into());
 }
 
+// Returns directory part of [path] including drive and trailing separator, that
+// is drive and dir of [rust_compat_splitpath] (which are limited to
+// [COMPAT_MAX_DRIVE] and [COMPAT_MAX_DIR]).
+pub fn compat_directory_part(path: &[u8]) -> &[u8] {
+    #[cfg(target_family = "windows")]
+    let is_separator = |ch: &u8| matches!(ch, b'/' | b'\\' | b':');
+
+    #[cfg(not(target_family = "windows"))]
+    let is_separator = |ch: &u8| *ch == b'/';
+
+    let end = path.iter().rposition(is_separator).map_or(0, |index| index + 1);
+    &path[..end]
+}
+
 #[no_mangle]
 pub extern "C" fn rust_compat_tell(fd: c_int) -> c_long {
     unsafe { lseek(fd, 0, SEEK_CUR) }
@@ -258,60 +311,299 @@ pub unsafe fn compat_windows_path_to_native(path: *mut c_char) {
     }
 }
 
-#[cfg(target_family = "windows")]
-fn compat_resolve_path(_path: *mut c_char) {}
+// Cached listing of a directory, see [compat_resolve_path].
+#[cfg(not(target_family = "windows"))]
+struct CompatDirectoryListing {
+    // Entry names grouped by case-folded name (see [compat_fold_case]).
+    names: HashMap<Vec<u32>, Vec<Vec<u8>>>,
+}
 
 #[cfg(not(target_family = "windows"))]
-unsafe fn compat_resolve_path(path: *mut c_char) {
-    let mut pch = path;
+impl CompatDirectoryListing {
+    fn read(directory: &[u8]) -> Option<Self> {
+        let mut names: HashMap<Vec<u32>, Vec<Vec<u8>>> = HashMap::new();
+        for entry in fs::read_dir(OsStr::from_bytes(directory)).ok()?.flatten() {
+            let name = entry.file_name().into_vec();
+            names
+                .entry(compat_fold_case(&name).collect())
+                .or_default()
+                .push(name);
+        }
 
-    let mut dir;
-    if *pch == '/' as c_char {
-        let str = CString::new("/").expect("valid c string");
-        dir = opendir(str.as_ptr());
-        pch = pch.offset(1);
-    } else {
-        let str = CString::new(".").expect("valid c string");
-        dir = opendir(str.as_ptr());
+        for variants in names.values_mut().filter(|variants| variants.len() > 1) {
+            variants.sort();
+        }
+
+        Some(Self { names })
     }
 
-    while dir != null_mut() {
-        let sep = unsafe { strchr(pch, '/' as c_int) };
-        let length = if sep != null_mut() {
-            (unsafe { sep.offset_from(pch) }) as usize
-        } else {
-            unsafe { strlen(pch) }
+    // Groups of entry names which differ only by case.
+    fn collisions(&self) -> impl Iterator<Item = &Vec<Vec<u8>>> {
+        self.names.values().filter(|variants| variants.len() > 1)
+    }
+
+    // Finds entry matching [name] case-insensitively. When there are several
+    // ones, exact match is preferred, otherwise the first one (in byte order)
+    // is used.
+    fn find(&self, name: &[u8]) -> Option<&[u8]> {
+        let variants = self.names.get(&compat_fold_case(name).collect::<Vec<_>>())?;
+        variants
+            .iter()
+            .find(|variant| variant.as_slice() == name)
+            .or(variants.first())
+            .map(Vec::as_slice)
+    }
+}
+
+#[cfg(not(target_family = "windows"))]
+#[derive(Default)]
+struct CompatPathCache {
+    // Listings of directories visited by [compat_resolve_path], keyed by
+    // absolute path (see [compat_path_cache_key]).
+    //
+    // Listings are invalidated when directories are modified with
+    // [rust_compat_fopen] and [compat_gzopen] (in write modes),
+    // [rust_compat_remove], [rust_compat_rename] and [rust_compat_mkdir].
+    // Other changes are only noticed after [compat_path_changed] or
+    // [rust_compat_clear_path_cache].
+    listings: HashMap<Vec<u8>, CompatDirectoryListing>,
+
+    // Incremented every time listings are invalidated, so that listings
+    // which were read concurrently with invalidation are not cached.
+    generation: u64,
+
+    // The current working directory, which is only queried once (until
+    // [rust_compat_clear_path_cache]).
+    current_directory: Option<Vec<u8>>,
+
+    // Directories which were reported to have entries differing only by
+    // case, so that every directory is reported once.
+    reported_directories: HashSet<Vec<u8>>,
+}
+
+#[cfg(not(target_family = "windows"))]
+lazy_static! {
+    // NOTE: The lock is never held while accessing file system.
+    static ref COMPAT_PATH_CACHE: Mutex<CompatPathCache> = Mutex::new(CompatPathCache::default());
+}
+
+// Appends [component] to directory [key], see [compat_path_cache_key].
+#[cfg(not(target_family = "windows"))]
+fn compat_path_cache_push(key: &mut Vec<u8>, component: &[u8]) {
+    if component.is_empty() || component == b"." {
+        return;
+    }
+
+    if key.last() != Some(&b'/') {
+        key.push(b'/');
+    }
+    key.extend_from_slice(component);
+}
+
+// Builds cache key of native [path], which is absolute path without empty and
+// `.` components.
+#[cfg(not(target_family = "windows"))]
+fn compat_path_cache_key(path: &[u8]) -> Option<Vec<u8>> {
+    let mut key = match path.first() {
+        Some(b'/') => b"/".to_vec(),
+        _ => compat_current_directory()?,
+    };
+    for component in path.split(|&ch| ch == b'/') {
+        compat_path_cache_push(&mut key, component);
+    }
+    Some(key)
+}
+
+// Returns the current working directory (as cached in [COMPAT_PATH_CACHE]).
+#[cfg(not(target_family = "windows"))]
+fn compat_current_directory() -> Option<Vec<u8>> {
+    if let Some(current_directory) = &COMPAT_PATH_CACHE.lock().current_directory {
+        return Some(current_directory.clone());
+    }
+
+    let current_directory = env::current_dir().ok()?.into_os_string().into_vec();
+    COMPAT_PATH_CACHE.lock().current_directory = Some(current_directory.clone());
+    Some(current_directory)
+}
+
+// Forgets cached listings affected by change of file or directory at native
+// [path], that is listings of its ancestors, and of [path] itself (with
+// subdirectories) if it's a directory.
+#[cfg(not(target_family = "windows"))]
+pub(crate) fn compat_path_changed(path: &Path) {
+    // Paths with `..` components can refer to any directory (see
+    // [compat_resolve_path]).
+    let key = compat_path_cache_key(path.as_os_str().as_bytes())
+        .filter(|key| !key.split(|&ch| ch == b'/').any(|component| component == b".."));
+    let Some(key) = key else {
+        rust_compat_clear_path_cache();
+        return;
+    };
+
+    let is_affected = |directory: &[u8]| {
+        let (ancestor, descendant) = match directory.len().cmp(&key.len()) {
+            Ordering::Less => (directory, key.as_slice()),
+            _ => (key.as_slice(), directory),
         };
+        descendant.starts_with(ancestor)
+            && (descendant.len() == ancestor.len()
+                || ancestor.last() == Some(&b'/')
+                || descendant[ancestor.len()] == b'/')
+    };
+
+    let mut cache = COMPAT_PATH_CACHE.lock();
+    cache.generation += 1;
+    cache.listings.retain(|directory, _| !is_affected(directory));
+}
 
-        let mut found = false;
+#[cfg(target_family = "windows")]
+pub(crate) fn compat_path_changed(_path: &Path) {}
 
-        let mut entry = unsafe { readdir(dir) };
-        while entry != null_mut() {
-            if strlen((*entry).d_name.as_ptr()) == length
-                && rust_compat_strnicmp(pch, (*entry).d_name.as_ptr(), length as c_ulong) == 0
-            {
-                strncpy(pch, (*entry).d_name.as_ptr(), length);
-                found = true;
-                break;
-            }
-            entry = readdir(dir);
-        }
+// Forgets all cached directory listings, which is needed when files are
+// created, removed, or renamed by other means than `compat_*` functions, or
+// when the current working directory is changed.
+#[no_mangle]
+pub extern "C" fn rust_compat_clear_path_cache() {
+    #[cfg(not(target_family = "windows"))]
+    {
+        let mut cache = COMPAT_PATH_CACHE.lock();
+        cache.generation += 1;
+        cache.listings.clear();
+        cache.current_directory = None;
+    }
+}
 
-        closedir(dir);
+// Calls [compat_path_changed] with native [path].
+#[cfg(not(target_family = "windows"))]
+fn compat_path_changed_native(path: &CStr) {
+    compat_path_changed(Path::new(OsStr::from_bytes(path.to_bytes())));
+}
 
-        if !found {
-            break;
+#[cfg(target_family = "windows")]
+fn compat_path_changed_native(_path: &CStr) {}
+
+// Specifies that stream opened with [mode] can create or truncate the file.
+unsafe fn compat_is_write_mode(mode: *const c_char) -> bool {
+    CStr::from_ptr(mode)
+        .to_bytes()
+        .iter()
+        .any(|&ch| ch == b'w' || ch == b'a')
+}
+
+// Specifies that [directory] contains entry named exactly [name].
+#[cfg(not(target_family = "windows"))]
+fn compat_path_cache_entry_exists(directory: &[u8], name: &[u8]) -> bool {
+    let mut path = directory.to_vec();
+    compat_path_cache_push(&mut path, name);
+    fs::symlink_metadata(OsStr::from_bytes(&path)).is_ok()
+}
+
+// Caches [listing] of [directory] read at [generation] of [COMPAT_PATH_CACHE],
+// unless listings were invalidated since then.
+#[cfg(not(target_family = "windows"))]
+fn compat_path_cache_insert(directory: &[u8], listing: CompatDirectoryListing, generation: u64) {
+    let is_reported = listing.collisions().next().is_none()
+        || !COMPAT_PATH_CACHE
+            .lock()
+            .reported_directories
+            .insert(directory.to_vec());
+    if !is_reported {
+        for variants in listing.collisions() {
+            debug_print(format_args!(
+                "compat: {}: entries differ only by case: {}",
+                String::from_utf8_lossy(directory),
+                variants
+                    .iter()
+                    .map(|name| String::from_utf8_lossy(name))
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ));
         }
+    }
 
-        if sep == null_mut() {
-            break;
+    let mut cache = COMPAT_PATH_CACHE.lock();
+    if cache.generation == generation {
+        cache.listings.insert(directory.to_vec(), listing);
+    }
+}
+
+#[cfg(target_family = "windows")]
+fn compat_resolve_path(path: &[u8]) -> Vec<u8> {
+    path.to_vec()
+}
+
+// Replaces every component of native [path] with the name of existing entry
+// which matches it case-insensitively (if any). Components which follow the
+// first missing one are left as is.
+//
+// NOTE: Listings of directories reached through `..` are not cached, since
+// their paths are not canonical (`..` cannot be resolved lexically because
+// of symbolic links), so [compat_path_changed] would not invalidate them.
+#[cfg(not(target_family = "windows"))]
+fn compat_resolve_path(path: &[u8]) -> Vec<u8> {
+    let root: &[u8] = if path.starts_with(b"/") { b"/" } else { b"" };
+    let Some(mut directory) = compat_path_cache_key(root) else {
+        return path.to_vec();
+    };
+
+    let mut components: Vec<Cow<[u8]>> = path.split(|&ch| ch == b'/').map(Cow::from).collect();
+
+    let mut is_canonical = true;
+    for component in &mut components {
+        if component.is_empty() || component.as_ref() == b"." || component.as_ref() == b".." {
+            is_canonical &= component.as_ref() != b"..";
+            compat_path_cache_push(&mut directory, component);
+            continue;
         }
 
-        *sep = '\0' as c_char;
-        dir = opendir(path);
-        *sep = '/' as c_char;
-        pch = sep.offset(1);
+        let (cached, generation) = if is_canonical {
+            let cache = COMPAT_PATH_CACHE.lock();
+            let cached = cache
+                .listings
+                .get(&directory)
+                .map(|listing| listing.find(component).map(<[u8]>::to_vec));
+            (cached, Some(cache.generation))
+        } else {
+            (None, None)
+        };
+        let name = match cached {
+            Some(Some(name)) => Some(name),
+            // Cached listing is only trusted for missing entries as long as
+            // the exact name is missing too (otherwise the entry was created
+            // behind our back).
+            Some(None) if !compat_path_cache_entry_exists(&directory, component) => None,
+            _ => {
+                let Some(listing) = CompatDirectoryListing::read(&directory) else {
+                    break;
+                };
+                let name = listing.find(component).map(<[u8]>::to_vec);
+                if let Some(generation) = generation {
+                    compat_path_cache_insert(&directory, listing, generation);
+                }
+                name
+            }
+        };
+
+        let Some(name) = name else {
+            break;
+        };
+        compat_path_cache_push(&mut directory, &name);
+        *component = Cow::Owned(name);
     }
+
+    components.join(&b'/')
+}
+
+// Converts [path] the same way as [rust_compat_fopen] does (see
+// [compat_windows_path_to_native] and [compat_resolve_path]).
+//
+// NOTE: Unlike C code, native paths are not limited to [COMPAT_MAX_PATH].
+unsafe fn compat_native_path_string(path: *const c_char) -> CString {
+    let mut native_path = CStr::from_ptr(path).to_bytes_with_nul().to_vec();
+    compat_windows_path_to_native(native_path.as_mut_ptr() as *mut c_char);
+    native_path.pop();
+
+    CString::new(compat_resolve_path(&native_path)).expect("no null characters")
 }
 
 #[cfg(target_family = "windows")]
@@ -331,11 +623,13 @@ unsafe fn native_mkdir(path: *const c_char) -> c_int {
 
 #[no_mangle]
 pub unsafe extern "C" fn rust_compat_mkdir(path: *const c_char) -> c_int {
-    let mut native_path = ['\0' as c_char; COMPAT_MAX_PATH as usize];
-    strcpy(native_path.as_mut_ptr(), path);
-    compat_windows_path_to_native(native_path.as_mut_ptr());
-    compat_resolve_path(native_path.as_mut_ptr());
-    native_mkdir(native_path.as_ptr())
+    let native_path = compat_native_path_string(path);
+
+    let rc = native_mkdir(native_path.as_ptr());
+    if rc == 0 {
+        compat_path_changed_native(&native_path);
+    }
+    rc
 }
 
 #[no_mangle]
@@ -354,19 +648,35 @@ pub unsafe extern "C" fn rust_compat_time_get_time() -> c_uint {
 
 #[no_mangle]
 pub unsafe extern "C" fn rust_compat_fopen(path: *const c_char, mode: *const c_char) -> *mut FILE {
-    let mut native_path = [0 as c_char; COMPAT_MAX_PATH as usize];
-    strcpy(native_path.as_mut_ptr(), path);
-    compat_windows_path_to_native(native_path.as_mut_ptr());
-    compat_resolve_path(native_path.as_mut_ptr());
-    fopen(native_path.as_ptr(), mode)
+    let native_path = compat_native_path_string(path);
+
+    let stream = fopen(native_path.as_ptr(), mode);
+    if !stream.is_null() && compat_is_write_mode(mode) {
+        compat_path_changed_native(&native_path);
+    }
+    stream
 }
 
 pub unsafe fn compat_gzopen(path: *const c_char, mode: *const c_char) -> gzFile {
-    let mut native_path = [0 as c_char; COMPAT_MAX_PATH as usize];
-    strcpy(native_path.as_mut_ptr(), path);
-    compat_windows_path_to_native(native_path.as_mut_ptr());
-    compat_resolve_path(native_path.as_mut_ptr());
-    gzopen(native_path.as_ptr(), mode)
+    let native_path = compat_native_path_string(path);
+
+    let stream = gzopen(native_path.as_ptr(), mode);
+    if !stream.is_null() && compat_is_write_mode(mode) {
+        compat_path_changed_native(&native_path);
+    }
+    stream
+}
+
+// Converts [path] the same way as [rust_compat_fopen] does, so that the result
+// can be used with Rust file APIs.
+pub unsafe fn compat_native_path(path: *const c_char) -> PathBuf {
+    let native_path = compat_native_path_string(path).into_bytes();
+
+    #[cfg(not(target_family = "windows"))]
+    return PathBuf::from(OsString::from_vec(native_path));
+
+    #[cfg(target_family = "windows")]
+    return PathBuf::from(String::from_utf8_lossy(&native_path).into_owned());
 }
 
 unsafe fn adjust_new_line(buffer: *mut c_char) {
@@ -409,11 +719,13 @@ pub unsafe fn compat_gzgets(
 
 #[no_mangle]
 pub unsafe extern "C" fn rust_compat_remove(path: *const c_char) -> c_int {
-    let mut native_path = [0 as c_char; COMPAT_MAX_PATH];
-    strcpy(native_path.as_mut_ptr(), path);
-    compat_windows_path_to_native(native_path.as_mut_ptr());
-    compat_resolve_path(native_path.as_mut_ptr());
-    remove(native_path.as_ptr())
+    let native_path = compat_native_path_string(path);
+
+    let rc = remove(native_path.as_ptr());
+    if rc == 0 {
+        compat_path_changed_native(&native_path);
+    }
+    rc
 }
 
 #[no_mangle]
@@ -421,25 +733,20 @@ pub unsafe extern "C" fn rust_compat_rename(
     old_file_name: *const c_char,
     new_file_name: *const c_char,
 ) -> c_int {
-    let mut native_old_file_name = [0 as c_char; COMPAT_MAX_PATH];
-    strcpy(native_old_file_name.as_mut_ptr(), old_file_name);
-    compat_windows_path_to_native(native_old_file_name.as_mut_ptr());
-    compat_resolve_path(native_old_file_name.as_mut_ptr());
+    let native_old_file_name = compat_native_path_string(old_file_name);
+    let native_new_file_name = compat_native_path_string(new_file_name);
 
-    let mut native_new_file_name = [0 as c_char; COMPAT_MAX_PATH];
-    strcpy(native_new_file_name.as_mut_ptr(), new_file_name);
-    compat_windows_path_to_native(native_new_file_name.as_mut_ptr());
-    compat_resolve_path(native_new_file_name.as_mut_ptr());
-
-    rename(native_old_file_name.as_ptr(), native_new_file_name.as_ptr())
+    let rc = rename(native_old_file_name.as_ptr(), native_new_file_name.as_ptr());
+    if rc == 0 {
+        compat_path_changed_native(&native_old_file_name);
+        compat_path_changed_native(&native_new_file_name);
+    }
+    rc
 }
 
 #[no_mangle]
 pub unsafe extern "C" fn rust_compat_access(path: *const c_char, mode: c_int) -> c_int {
-    let mut native_path = [0 as c_char; COMPAT_MAX_PATH];
-    strcpy(native_path.as_mut_ptr(), path);
-    compat_windows_path_to_native(native_path.as_mut_ptr());
-    compat_resolve_path(native_path.as_mut_ptr());
+    let native_path = compat_native_path_string(path);
     access(native_path.as_ptr(), mode)
 }
 
@@ -462,6 +769,7 @@ pub unsafe extern "C" fn rust_get_file_size(stream: *mut FILE) -> c_long {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::test_utils::temp_path;
     use std::ffi::{CStr, CString};
 
     fn to_string(input: &mut [u8]) -> String {
@@ -597,4 +905,178 @@ mod tests {
         assert_eq!("*", to_string(fname.as_mut_slice()));
         assert_eq!(".pro", to_string(ext.as_mut_slice()));
     }
+
+    #[cfg(not(target_family = "windows"))]
+    #[test]
+    fn test_compat_path_cache() {
+        let directory = temp_path("path_cache");
+        fs::create_dir_all(directory.join("Data")).expect("");
+        fs::write(directory.join("Data/Readme.TXT"), b"readme").expect("");
+
+        let path = |name: &str| {
+            CString::new(directory.join(name).into_os_string().into_vec()).expect("")
+        };
+        let exists = |name: &str| unsafe {
+            let read_mode = CString::new("rb").expect("");
+            let stream = rust_compat_fopen(path(name).as_ptr(), read_mode.as_ptr());
+            if stream.is_null() {
+                return false;
+            }
+            libc::fclose(stream);
+            true
+        };
+
+        assert!(exists("data/readme.txt"));
+        assert!(!exists("data/new.txt"));
+
+        // Files created with `compat_*` functions are visible immediately.
+        unsafe {
+            let write_mode = CString::new("wb").expect("");
+            let stream = rust_compat_fopen(path("DATA/New.txt").as_ptr(), write_mode.as_ptr());
+            assert!(!stream.is_null());
+            libc::fclose(stream);
+        }
+        assert!(directory.join("Data/New.txt").exists());
+        assert!(exists("data/new.txt"));
+
+        // Other changes are visible after cache is cleared.
+        fs::write(directory.join("Data/Other.txt"), b"other").expect("");
+        rust_compat_clear_path_cache();
+        assert!(exists("data/other.txt"));
+
+        unsafe {
+            assert_eq!(rust_compat_remove(path("data/NEW.TXT").as_ptr()), 0);
+        }
+        assert!(!exists("data/new.txt"));
+
+        // Exact match wins when names only differ by case.
+        fs::write(directory.join("Data/README.TXT"), b"README").expect("");
+        compat_path_changed(&directory.join("Data"));
+        let native_path = unsafe { compat_native_path(path("data/README.TXT").as_ptr()) };
+        assert_eq!(native_path, directory.join("Data/README.TXT"));
+        let native_path = unsafe { compat_native_path(path("data/readme.txt").as_ptr()) };
+        assert_eq!(native_path, directory.join("Data/README.TXT"));
+
+        // Such directories are remembered, so they are only reported once.
+        let key = compat_path_cache_key(directory.join("Data").as_os_str().as_bytes()).expect("");
+        assert!(COMPAT_PATH_CACHE.lock().reported_directories.contains(&key));
+
+        // Directories reached through `..` are not cached with keys which are
+        // never invalidated.
+        fs::create_dir_all(directory.join("Other")).expect("");
+        compat_path_changed(&directory.join("Other"));
+        let native_path = unsafe { compat_native_path(path("other/../data/NEW.TXT").as_ptr()) };
+        assert_eq!(native_path, directory.join("Other/../Data/NEW.TXT"));
+        unsafe {
+            let write_mode = CString::new("wb").expect("");
+            let stream = rust_compat_fopen(path("Data/New.txt").as_ptr(), write_mode.as_ptr());
+            assert!(!stream.is_null());
+            libc::fclose(stream);
+        }
+        let native_path = unsafe { compat_native_path(path("other/../data/NEW.TXT").as_ptr()) };
+        assert_eq!(native_path, directory.join("Other/../Data/New.txt"));
+        assert!(!COMPAT_PATH_CACHE
+            .lock()
+            .listings
+            .keys()
+            .any(|key| key.ends_with(b"Other/../Data")));
+
+        fs::remove_dir_all(&directory).expect("");
+    }
+
+    #[test]
+    fn test_compat_stricmp_ord() {
+        let compare = |string1: &str, string2: &[u8]| {
+            let string1 = CString::new(string1).expect("");
+            let string2 = CString::new(string2).expect("");
+            compat_stricmp_ord(string1.as_ptr(), string2.as_ptr())
+        };
+
+        assert_eq!(compare("MAPS\\ARTEMPLE.SAV", b"maps\\artemple.sav"), Ordering::Equal);
+        // Same as `SDL_strcasecmp`, which compares uppercased characters.
+        assert_eq!(compare("a_b", b"AZB"), Ordering::Greater);
+        assert_eq!(compare("a", b"AB"), Ordering::Less);
+
+        assert_eq!(compare("J\u{fc}rgen", "J\u{dc}RGEN".as_bytes()), Ordering::Equal);
+        assert_eq!(compare("Stra\u{df}e", b"STRASSE"), Ordering::Equal);
+        assert_eq!(compare("\u{e9}t\u{e9}", "\u{c9}T\u{c9}S".as_bytes()), Ordering::Less);
+
+        // Invalid UTF-8.
+        assert_eq!(compare("Z", b"\xC9T\xC9"), Ordering::Less);
+        assert_eq!(compare("caf\u{e9}", b"CAF\xC9"), Ordering::Less);
+        assert_eq!(compare("Ab\u{ff}", b"aB\xFF"), Ordering::Less);
+    }
+
+    #[test]
+    fn test_compat_strnicmp_ord() {
+        let compare = |string1: &str, string2: &[u8], size: usize| {
+            let string1 = CString::new(string1).expect("");
+            let string2 = CString::new(string2).expect("");
+            compat_strnicmp_ord(string1.as_ptr(), string2.as_ptr(), size)
+        };
+
+        assert_eq!(compare("MAPS\\ARTEMPLE.SAV", b"maps\\arcaves.sav", 7), Ordering::Equal);
+        assert_eq!(compare("MAPS\\ARTEMPLE.SAV", b"maps\\arcaves.sav", 8), Ordering::Greater);
+        assert_eq!(compare("a", b"AB", 2), Ordering::Less);
+        assert_eq!(compare("a", b"AB", 1), Ordering::Equal);
+
+        // Same folding as [compat_stricmp_ord].
+        assert_eq!(compare("J\u{fc}rgen", "J\u{dc}RGEN".as_bytes(), 16), Ordering::Equal);
+        assert_eq!(compare("J\u{fc}rgen", "J\u{dc}RGEN".as_bytes(), 3), Ordering::Equal);
+        assert_eq!(compare("\u{e9}t\u{e9}", "\u{c9}T\u{c9}S".as_bytes(), 5), Ordering::Equal);
+        assert_eq!(compare("caf\u{e9}", b"CAF\xC9", 5), Ordering::Less);
+        assert_eq!(compare("caf\u{e9}", b"CAF\xC9", 3), Ordering::Equal);
+
+        // Both exported functions fold UTF-8 the same way.
+        let string1 = CString::new("Stra\u{df}e").expect("");
+        let string2 = CString::new("STRA\u{df}E").expect("");
+        assert_eq!(rust_compat_stricmp(string1.as_ptr(), string2.as_ptr()), 0);
+        assert_eq!(rust_compat_strnicmp(string1.as_ptr(), string2.as_ptr(), 6), 0);
+    }
+
+    #[test]
+    fn test_compat_directory_part() {
+        assert_eq!(compat_directory_part(b"MAPS/*.SAV"), b"MAPS/");
+        assert_eq!(compat_directory_part(b"/a/b/"), b"/a/b/");
+        assert_eq!(compat_directory_part(b"*.SAV"), b"");
+    }
+
+    #[cfg(not(target_family = "windows"))]
+    #[test]
+    fn test_compat_long_path() {
+        let root = temp_path("long");
+
+        // Longer than [COMPAT_MAX_PATH], with non-ASCII components.
+        let mut directory = root.clone();
+        for _ in 0..4 {
+            directory.push("J\u{fc}rgen".repeat(10));
+        }
+        fs::create_dir_all(&directory).expect("");
+        fs::write(directory.join("Save.Dat"), b"save").expect("");
+        assert!(directory.as_os_str().len() > COMPAT_MAX_PATH);
+
+        let mut path = root.clone().into_os_string().into_vec();
+        for _ in 0..4 {
+            path.push(b'/');
+            path.extend_from_slice("J\u{dc}RGEN".repeat(10).as_bytes());
+        }
+        path.extend_from_slice(b"\\save.dat");
+        let path = CString::new(path).expect("");
+
+        unsafe {
+            assert_eq!(compat_native_path(path.as_ptr()), directory.join("Save.Dat"));
+            assert_eq!(rust_compat_access(path.as_ptr(), libc::R_OK), 0);
+
+            let read_mode = CString::new("rb").expect("");
+            let stream = rust_compat_fopen(path.as_ptr(), read_mode.as_ptr());
+            assert!(!stream.is_null());
+            assert_eq!(rust_get_file_size(stream), 4);
+            libc::fclose(stream);
+
+            assert_eq!(rust_compat_remove(path.as_ptr()), 0);
+        }
+        assert!(!directory.join("Save.Dat").exists());
+
+        fs::remove_dir_all(&root).expect("");
+    }
 }
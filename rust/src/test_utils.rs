@@ -0,0 +1,47 @@
+// Helpers shared by unit tests.
+
+use crate::xfile::{rust_xfile_close, rust_xfile_open, xfile_read};
+use libc::c_void;
+use std::ffi::{CStr, CString};
+use std::panic::Location;
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, MutexGuard};
+
+// Tests which use the global [Vfs] (through FFI functions) share its list of
+// xbases, so they are run one at a time.
+static GLOBAL_VFS_TEST_LOCK: Mutex<()> = Mutex::new(());
+
+pub(crate) fn lock_global_vfs() -> MutexGuard<'static, ()> {
+    GLOBAL_VFS_TEST_LOCK
+        .lock()
+        .unwrap_or_else(|err| err.into_inner())
+}
+
+// Returns the path of temporary file (or directory) [name], which is unique
+// to the calling module, so that tests running in parallel do not clash.
+#[track_caller]
+pub(crate) fn temp_path(name: &str) -> PathBuf {
+    let module = Path::new(Location::caller().file())
+        .file_stem()
+        .and_then(|stem| stem.to_str())
+        .unwrap_or("test");
+    std::env::temp_dir().join(format!("{}_{}_{}", module, std::process::id(), name))
+}
+
+pub(crate) fn cstring(value: &str) -> CString {
+    CString::new(value).expect("valid string")
+}
+
+// Reads up to 100 bytes of [file_path] with [rust_xfile_open].
+pub(crate) unsafe fn read_file(file_path: &CStr) -> Option<Vec<u8>> {
+    let stream = rust_xfile_open(file_path.as_ptr(), cstring("rb").as_ptr());
+    if stream.is_null() {
+        return None;
+    }
+
+    let mut data = vec![0u8; 100];
+    let bytes_read = xfile_read(data.as_mut_ptr() as *mut c_void, 1, data.len(), stream);
+    data.truncate(bytes_read);
+    rust_xfile_close(stream);
+    Some(data)
+}
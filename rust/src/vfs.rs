@@ -0,0 +1,535 @@
+// A virtual file system, which is an ordered list of xbases (plus optional
+// overlay directory, see [rust_xbase_set_overlay], and embedded fallback
+// resources, see [XBaseEmbedded]). Directory xbases can be watched for
+// changes, see [Vfs::set_watching].
+//
+// All `rust_xfile_*` and `rust_xbase_*` functions operate on the global
+// instance (see [Vfs::global]). Other instances are completely independent,
+// so that several game installations can be opened side by side.
+
+use crate::xbase_backend::{XBaseBackend, XBaseStat};
+use crate::xbase_embedded::{XBaseEmbedded, XBASE_EMBEDDED};
+use crate::xbase_overlay::XBaseOverlay;
+use crate::xbase_watcher::{XBaseChange, XBaseWatcher};
+use crate::xfile::{
+    vfs_open_xbase, xbase_push, xfile_enumerate, xfile_escapes_root, xfile_open, xfile_read_all,
+    XBase, XFileEnumerationEntryType,
+};
+use crate::xfile_trace::{XFileTraceHandler, XFileTracer};
+use parking_lot::Mutex;
+use spin::RwLock;
+use std::ffi::{CStr, CString};
+use std::io;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::Arc;
+
+pub struct Vfs {
+    // The top of search order.
+    head: RwLock<Option<Arc<RwLock<XBase>>>>,
+
+    // Incremented every time the list of xbases is changed.
+    generation: AtomicU64,
+
+    overlay: RwLock<Option<Arc<XBaseOverlay>>>,
+
+    // Specifies that embedded fallback resources are used.
+    embedded: AtomicBool,
+
+    // Specifies that paths cannot escape xbases, see [Vfs::set_sandboxed].
+    sandboxed: AtomicBool,
+
+    // Watcher of directory xbases, see [Vfs::set_watching].
+    watcher: Mutex<Option<XBaseWatcher>>,
+
+    // File resolution tracing, see [Vfs::set_trace_handler].
+    tracer: XFileTracer,
+}
+
+// 0x6B24D0
+static G_VFS: Vfs = Vfs::new();
+
+impl Vfs {
+    pub const fn new() -> Self {
+        Self {
+            head: RwLock::new(None),
+            generation: AtomicU64::new(0),
+            overlay: RwLock::new(None),
+            embedded: AtomicBool::new(true),
+            sandboxed: AtomicBool::new(false),
+            watcher: Mutex::new(None),
+            tracer: XFileTracer::new(),
+        }
+    }
+
+    // Returns the instance used by FFI functions.
+    pub fn global() -> &'static Vfs {
+        &G_VFS
+    }
+
+    pub(crate) fn head(&self) -> Option<Arc<RwLock<XBase>>> {
+        self.head.read().clone()
+    }
+
+    pub(crate) fn set_head(&self, value: Option<Arc<RwLock<XBase>>>) {
+        *self.head.write() = value;
+        self.generation.fetch_add(1, Ordering::Relaxed);
+    }
+
+    // Returns the number of changes to the list of xbases, which allows to
+    // detect them cheaply.
+    pub(crate) fn generation(&self) -> u64 {
+        self.generation.load(Ordering::Relaxed)
+    }
+
+    pub(crate) fn overlay(&self) -> Option<Arc<XBaseOverlay>> {
+        self.overlay.read().clone()
+    }
+
+    pub(crate) fn embedded(&self) -> Option<&'static XBaseEmbedded> {
+        match self.embedded.load(Ordering::Relaxed) {
+            true => Some(&XBASE_EMBEDDED),
+            false => None,
+        }
+    }
+
+    // Enables or disables embedded fallback resources (enabled by default),
+    // which are used when the file is not found anywhere else.
+    pub fn set_embedded_fallback(&self, enabled: bool) {
+        self.embedded.store(enabled, Ordering::Relaxed);
+    }
+
+    // Enables or disables sandbox mode, which is meant for processing paths
+    // taken from untrusted data.
+    //
+    // In sandbox mode relative paths which escape xbases (with `..`
+    // components) and absolute paths are rejected by [Vfs::read_file],
+    // [Vfs::enumerate], [Vfs::stat], and their FFI counterparts, and files
+    // are not looked up in the current working directory. Xbases
+    // themselves are trusted, so they should be opened before sandbox is
+    // enabled (opening xbases at absolute paths which do not exist yet fails).
+    pub fn set_sandboxed(&self, enabled: bool) {
+        self.sandboxed.store(enabled, Ordering::Relaxed);
+    }
+
+    pub(crate) fn is_sandboxed(&self) -> bool {
+        self.sandboxed.load(Ordering::Relaxed)
+    }
+
+    // Checks that [file_path] can be used in this instance, see
+    // [Vfs::set_sandboxed].
+    pub fn check_path(&self, file_path: &CStr) -> io::Result<()> {
+        if !self.is_sandboxed() || !xfile_escapes_root(file_path) {
+            return Ok(());
+        }
+
+        Err(io::Error::new(
+            io::ErrorKind::PermissionDenied,
+            format!("{}: path escapes xbases", file_path.to_string_lossy()),
+        ))
+    }
+
+    pub(crate) fn tracer(&self) -> &XFileTracer {
+        &self.tracer
+    }
+
+    // Sets [handler] to receive file resolution events of this instance (see
+    // [XFileTraceEvent]), or disables it if [handler] is None.
+    pub fn set_trace_handler(&self, handler: Option<XFileTraceHandler>) {
+        self.tracer.set_handler(handler);
+    }
+
+    // Enables or disables logging file resolution events of this instance
+    // with [debug_print].
+    pub fn set_trace_logging(&self, enabled: bool) {
+        self.tracer.set_logging(enabled);
+    }
+
+    // Enables or disables watching directory xbases (disabled by default),
+    // see [Vfs::poll_changes]. Only supported on Linux.
+    pub fn set_watching(&self, enabled: bool) -> io::Result<()> {
+        let mut watcher = self.watcher.lock();
+        if !enabled {
+            *watcher = None;
+        } else if watcher.is_none() {
+            let mut new_watcher = XBaseWatcher::new()?;
+            new_watcher.sync(self);
+            *watcher = Some(new_watcher);
+        }
+
+        Ok(())
+    }
+
+    // Returns files which are changed in directory xbases since the previous
+    // call (or since watching is enabled). Xbases opened in the meantime are
+    // only watched from now on.
+    //
+    // Returns nothing when watching is disabled.
+    pub fn poll_changes(&self) -> Vec<XBaseChange> {
+        match self.watcher.lock().as_mut() {
+            Some(watcher) => watcher.poll(self),
+            None => Vec::new(),
+        }
+    }
+
+    // Opens xbase at [path] (directory, .DAT file, or custom source) and puts
+    // it at the top of search order. If it's already open, it's simply moved
+    // to the top.
+    //
+    // Missing directory is created, the error is returned if it's not
+    // possible.
+    pub fn open_xbase(&self, path: &CStr) -> io::Result<()> {
+        unsafe { vfs_open_xbase(self, path) }
+    }
+
+    // Closes all open xbases and opens a set of xbases specified by [paths],
+    // which are separated by semicolon.
+    pub fn reopen_all(&self, paths: &CStr) -> io::Result<()> {
+        self.close_all();
+
+        paths
+            .to_bytes()
+            .split(|&ch| ch == b';')
+            .filter(|path| !path.is_empty())
+            .try_for_each(|path| {
+                let path = CString::new(path).expect("no null characters");
+                self.open_xbase(&path)
+            })
+    }
+
+    pub fn close_all(&self) {
+        self.set_head(None);
+    }
+
+    // Adds [backend] at the top of search order under [name], which is only
+    // used to identify the xbase (see [Vfs::open_xbase]).
+    pub fn mount(&self, name: &CStr, backend: Box<dyn XBaseBackend>) {
+        xbase_push(self, name.to_owned(), backend);
+    }
+
+    // Finds file at relative [file_path] in open xbases (in search order).
+    pub fn stat(&self, file_path: &CStr) -> Option<XBaseStat> {
+        self.check_path(file_path).ok()?;
+
+        let mut optional_curr = self.head();
+        while let Some(curr) = optional_curr {
+            let curr = curr.read();
+            if let Some(stat) = curr.backend.stat(file_path) {
+                return Some(stat);
+            }
+            optional_curr = curr.next.clone();
+        }
+
+        self.embedded()?.stat(file_path)
+    }
+
+    pub fn exists(&self, file_path: &CStr) -> bool {
+        if self.check_path(file_path).is_err() {
+            return false;
+        }
+
+        let mut optional_curr = self.head();
+        while let Some(curr) = optional_curr {
+            let curr = curr.read();
+            if curr.backend.exists(file_path) {
+                return true;
+            }
+            optional_curr = curr.next.clone();
+        }
+
+        self.embedded()
+            .is_some_and(|embedded| embedded.exists(file_path))
+    }
+
+    // Calls [visitor] with the path and backend of every open xbase (in search
+    // order).
+    pub(crate) fn visit_xbases(&self, visitor: &mut dyn FnMut(&CStr, &dyn XBaseBackend)) {
+        let mut optional_curr = self.head();
+        while let Some(curr) = optional_curr {
+            let curr = curr.read();
+            visitor(&curr.path, curr.backend.as_ref());
+            optional_curr = curr.next.clone();
+        }
+    }
+
+    // Sets [path] as overlay directory (creating it if needed), or removes
+    // overlay if [path] is None.
+    pub fn set_overlay(&self, path: Option<&CStr>, whiteouts: bool) -> bool {
+        let overlay = match path {
+            Some(path) => match XBaseOverlay::new(path, whiteouts) {
+                Some(overlay) => Some(Arc::new(overlay)),
+                None => return false,
+            },
+            None => None,
+        };
+
+        *self.overlay.write() = overlay;
+
+        true
+    }
+
+    // Reads entire (decompressed) content of [file_path], which is resolved
+    // the same way as [rust_xfile_open] does.
+    pub fn read_file(&self, file_path: &CStr) -> Option<Vec<u8>> {
+        let mode = CString::new("rb").expect("valid string");
+        let file = unsafe { xfile_open(self, file_path, &mode) }?;
+        Some(unsafe { xfile_read_all(file) })
+    }
+
+    // Calls [visitor] for every file matching [pattern] (see [xlist_init]).
+    //
+    // Returns false if [visitor] stopped enumeration.
+    pub fn enumerate(
+        &self,
+        pattern: &CStr,
+        visitor: &mut dyn FnMut(&CStr, XFileEnumerationEntryType) -> bool,
+    ) -> bool {
+        unsafe { xfile_enumerate(self, pattern, visitor) }
+    }
+}
+
+impl Default for Vfs {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dbase_writer::DBaseWriter;
+    use crate::test_utils::{cstring, temp_path};
+    use crate::xfile_trace::XFileTraceEvent;
+    use std::fs;
+    use std::sync::atomic::AtomicUsize;
+
+    // The number of events received by [trace_handler].
+    static TRACE_EVENT_COUNT: AtomicUsize = AtomicUsize::new(0);
+
+    unsafe extern "C" fn trace_handler(_event: *const XFileTraceEvent) {
+        TRACE_EVENT_COUNT.fetch_add(1, Ordering::Relaxed);
+    }
+
+    #[test]
+    fn test_vfs_instances() {
+        let vanilla = temp_path("vanilla.dat");
+        let modded = temp_path("mod");
+        fs::create_dir_all(&modded).expect("");
+        fs::write(modded.join("readme.txt"), b"mod").expect("");
+
+        let mut writer = DBaseWriter::new();
+        writer
+            .add_data("readme.txt", b"vanilla".to_vec())
+            .expect("");
+        writer
+            .add_data("vanilla.txt", b"vanilla".to_vec())
+            .expect("");
+        writer.write(&vanilla).expect("");
+
+        let first = Vfs::new();
+        let second = Vfs::new();
+        first
+            .reopen_all(&cstring(vanilla.to_str().expect("")))
+            .expect("");
+        second
+            .reopen_all(&cstring(&format!(
+                "{};{}",
+                vanilla.display(),
+                modded.display()
+            )))
+            .expect("");
+
+        let readme = cstring("readme.txt");
+        first.set_trace_handler(Some(trace_handler));
+        assert_eq!(second.read_file(&readme), Some(b"mod".to_vec()));
+        assert_eq!(TRACE_EVENT_COUNT.load(Ordering::Relaxed), 0);
+        assert_eq!(first.read_file(&readme), Some(b"vanilla".to_vec()));
+        assert_eq!(TRACE_EVENT_COUNT.load(Ordering::Relaxed), 1);
+        first.set_trace_handler(None);
+        assert!(second.exists(&cstring("vanilla.txt")));
+
+        let mut names = Vec::new();
+        assert!(second.enumerate(&cstring("*.txt"), &mut |name, _| {
+            names.push(name.to_string_lossy().into_owned());
+            true
+        }));
+        names.sort();
+        names.dedup();
+        assert_eq!(names, ["readme.txt", "vanilla.txt"]);
+
+        // Instance can be used from worker thread.
+        std::thread::scope(|scope| {
+            let worker = scope.spawn(|| first.read_file(&readme));
+            assert_eq!(second.read_file(&readme), Some(b"mod".to_vec()));
+            assert_eq!(worker.join().expect(""), Some(b"vanilla".to_vec()));
+        });
+
+        first.close_all();
+        assert_eq!(first.read_file(&cstring("vanilla.txt")), None);
+        assert_eq!(second.read_file(&readme), Some(b"mod".to_vec()));
+
+        second.close_all();
+        fs::remove_dir_all(&modded).expect("");
+        fs::remove_file(&vanilla).expect("");
+    }
+
+    #[test]
+    fn test_vfs_embedded_fallback() {
+        let data = temp_path("data");
+        fs::create_dir_all(&data).expect("");
+        fs::write(data.join("color.pal"), b"palette").expect("");
+
+        let vfs = Vfs::new();
+        let font = vfs.read_file(&cstring("FONT0.FON")).expect("");
+        assert_eq!(&font[..4], 128i32.to_le_bytes());
+        assert!(vfs.exists(&cstring("fallout2.cfg")));
+        assert_eq!(
+            vfs.stat(&cstring("color.pal")).map(|stat| stat.size),
+            Some(768 + 0x8000)
+        );
+
+        // Embedded files are read-only.
+        let embedded = vfs.embedded().expect("");
+        assert!(embedded
+            .open(&cstring("font0.fon"), &cstring("wb"))
+            .is_none());
+
+        // Any other source takes precedence.
+        vfs.open_xbase(&cstring(data.to_str().expect("")))
+            .expect("");
+        assert_eq!(
+            vfs.read_file(&cstring("color.pal")),
+            Some(b"palette".to_vec())
+        );
+
+        let mut names = Vec::new();
+        assert!(vfs.enumerate(&cstring("*.fon"), &mut |name, _| {
+            names.push(name.to_string_lossy().into_owned());
+            true
+        }));
+        assert_eq!(names, ["font0.fon"]);
+
+        vfs.set_embedded_fallback(false);
+        assert!(!vfs.exists(&cstring("font0.fon")));
+        assert_eq!(vfs.read_file(&cstring("font0.fon")), None);
+
+        vfs.close_all();
+        fs::remove_dir_all(&data).expect("");
+    }
+
+    #[test]
+    fn test_vfs_sandbox() {
+        let sandbox = temp_path("sandbox");
+        let root = sandbox.join("root");
+        fs::create_dir_all(root.join("art")).expect("");
+        fs::write(root.join("readme.txt"), b"root").expect("");
+        fs::write(sandbox.join("secret.txt"), b"secret").expect("");
+
+        let vfs = Vfs::new();
+        vfs.open_xbase(&cstring(root.to_str().expect("")))
+            .expect("");
+
+        let escaping = cstring("art\\..\\..\\secret.txt");
+        let absolute = cstring(sandbox.join("secret.txt").to_str().expect(""));
+        assert_eq!(vfs.read_file(&escaping), Some(b"secret".to_vec()));
+        assert_eq!(vfs.read_file(&absolute), Some(b"secret".to_vec()));
+
+        vfs.set_sandboxed(true);
+        assert_eq!(
+            vfs.read_file(&cstring("art/../readme.txt")),
+            Some(b"root".to_vec())
+        );
+        assert_eq!(vfs.read_file(&escaping), None);
+        assert_eq!(vfs.read_file(&absolute), None);
+        assert!(vfs.stat(&escaping).is_none());
+        assert!(!vfs.exists(&cstring("../secret.txt")));
+        assert_eq!(
+            vfs.check_path(&escaping).map_err(|err| err.kind()),
+            Err(io::ErrorKind::PermissionDenied)
+        );
+        assert!(!vfs.enumerate(&cstring("..\\*.txt"), &mut |_, _| true));
+
+        // The current working directory (the crate root when running tests)
+        // is not looked up either, including paths starting with `.`.
+        assert_eq!(vfs.read_file(&cstring("Cargo.toml")), None);
+        assert_eq!(vfs.read_file(&cstring(".\\Cargo.toml")), None);
+        assert_eq!(
+            vfs.read_file(&cstring("./readme.txt")),
+            Some(b"root".to_vec())
+        );
+        let mut names = Vec::new();
+        vfs.enumerate(&cstring("*.toml"), &mut |name, _| {
+            names.push(name.to_owned());
+            true
+        });
+        assert!(names.is_empty());
+
+        // Missing directories are not created outside of xbases.
+        let outside = temp_path("sandbox_outside");
+        assert!(vfs
+            .open_xbase(&cstring(outside.to_str().expect("")))
+            .is_err());
+        assert!(!outside.exists());
+
+        vfs.close_all();
+        fs::remove_dir_all(&sandbox).expect("");
+    }
+
+    #[test]
+    fn test_vfs_long_dat_paths() {
+        let dat_path = temp_path("long.dat");
+
+        // Longer than [COMPAT_MAX_PATH].
+        let long_path = format!("{}\\{}.txt", "a".repeat(200), "b".repeat(100));
+        let mut writer = DBaseWriter::new();
+        writer.add_data(&long_path, b"long".to_vec()).expect("");
+        writer.add_data("short.txt", b"short".to_vec()).expect("");
+        writer.write(&dat_path).expect("");
+
+        let vfs = Vfs::new();
+        vfs.open_xbase(&cstring(dat_path.to_str().expect("")))
+            .expect("");
+        assert_eq!(
+            vfs.read_file(&cstring(&long_path.to_uppercase())),
+            Some(b"long".to_vec())
+        );
+
+        let mut names = Vec::new();
+        let pattern = format!("{}\\*.txt", "a".repeat(200));
+        assert!(vfs.enumerate(&cstring(&pattern), &mut |name, _| {
+            names.push(name.to_owned());
+            true
+        }));
+        assert_eq!(names, [cstring(&long_path)]);
+
+        vfs.close_all();
+        fs::remove_file(&dat_path).expect("");
+    }
+
+    #[test]
+    fn test_vfs_open_directory() {
+        let root = temp_path("nested");
+        let working_directory = std::env::current_dir().expect("");
+
+        // Longer than [COMPAT_MAX_PATH].
+        let long = root
+            .join("a".repeat(100))
+            .join("b".repeat(100))
+            .join("c".repeat(100));
+        let vfs = Vfs::new();
+        vfs.open_xbase(&cstring(long.to_str().expect("")))
+            .expect("");
+        assert!(long.is_dir());
+        assert_eq!(std::env::current_dir().expect(""), working_directory);
+
+        // File is in the way.
+        fs::write(root.join("file"), b"").expect("");
+        let blocked = root.join("file").join("directory");
+        assert!(vfs
+            .open_xbase(&cstring(blocked.to_str().expect("")))
+            .is_err());
+        assert_eq!(std::env::current_dir().expect(""), working_directory);
+
+        vfs.close_all();
+        fs::remove_dir_all(&root).expect("");
+    }
+}
@@ -0,0 +1,514 @@
+use crate::dfile::{
+    dbase_find_close, dbase_find_first_entry, dbase_find_next_entry, dbase_open, rust_dfile_open,
+    DBase, DFileFindData,
+};
+use crate::file_find::{
+    file_find_close, file_find_first, file_find_get_name, file_find_is_directory, file_find_next,
+    DirectoryFileFindData,
+};
+use crate::platform_compat::{
+    compat_directory_part, compat_native_path, compat_windows_path_to_native,
+};
+use crate::vfs::Vfs;
+use crate::xfile::{xfile_open_plain, XFileEnumerationEntryType, XFileType};
+use libc::c_char;
+use spin::RwLock;
+use std::ffi::{CStr, CString};
+use std::fs;
+use std::ptr;
+
+// Information about a file provided by [XBaseBackend::stat].
+pub struct XBaseStat {
+    // The size of file (uncompressed size for .DAT entries).
+    pub size: u64,
+
+    pub is_directory: bool,
+}
+
+// A source of files of [XBase].
+//
+// Paths passed to backends are always relative to the root of the source, and
+// can use both slashes and backslashes as separators.
+pub trait XBaseBackend: Send + Sync {
+    // Opens file at [file_path] with [mode] (as in `fopen`).
+    fn open(&self, file_path: &CStr, mode: &CStr) -> Option<XFileType>;
+
+    // Calls [visitor] with the path and type of every entry matching
+    // [pattern]. Entry paths are relative, so that they can be passed to
+    // [open] as is.
+    //
+    // Returns false if [visitor] requested to stop enumeration (by returning
+    // false).
+    fn enumerate(
+        &self,
+        pattern: &CStr,
+        visitor: &mut dyn FnMut(&CStr, XFileEnumerationEntryType) -> bool,
+    ) -> bool;
+
+    fn stat(&self, file_path: &CStr) -> Option<XBaseStat>;
+
+    fn exists(&self, file_path: &CStr) -> bool {
+        self.stat(file_path).is_some()
+    }
+
+    // Calls [visitor] with the path of every file (including files in
+    // subdirectories) of this source.
+    //
+    // Returns false if [visitor] requested to stop enumeration.
+    fn files(&self, visitor: &mut dyn FnMut(&CStr) -> bool) -> bool {
+        xbase_backend_walk(self, None, visitor)
+    }
+
+    // The directory where files are created by [xbase_make_directory].
+    // Read-only sources do not have one.
+    fn directory(&self) -> Option<&CStr> {
+        None
+    }
+}
+
+// Lists files of [backend] in [directory] (root if None) and its
+// subdirectories with [XBaseBackend::enumerate].
+fn xbase_backend_walk<B: XBaseBackend + ?Sized>(
+    backend: &B,
+    directory: Option<&CStr>,
+    visitor: &mut dyn FnMut(&CStr) -> bool,
+) -> bool {
+    let mut pattern = Vec::new();
+    if let Some(directory) = directory {
+        pattern.extend_from_slice(directory.to_bytes());
+        pattern.push(b'\\');
+    }
+    pattern.push(b'*');
+    let pattern = CString::new(pattern).expect("no null characters");
+
+    let mut subdirectories = Vec::new();
+    let completed = backend.enumerate(&pattern, &mut |name, entry_type| {
+        if entry_type == XFileEnumerationEntryType::XfileEnumerationEntryTypeDirectory {
+            subdirectories.push(name.to_owned());
+            true
+        } else {
+            visitor(name)
+        }
+    });
+    if !completed {
+        return false;
+    }
+
+    subdirectories
+        .iter()
+        .all(|subdirectory| xbase_backend_walk(backend, Some(subdirectory), visitor))
+}
+
+// Creates backend for the source at [path], or returns None if the source is
+// not supported by this factory.
+pub type XBaseBackendFactory = fn(path: &CStr) -> Option<Box<dyn XBaseBackend>>;
+
+// Additional factories registered with [xbase_register_backend_factory].
+static XBASE_BACKEND_FACTORIES: RwLock<Vec<XBaseBackendFactory>> = RwLock::new(Vec::new());
+
+// Registers [factory] that is used to open search paths passed to
+// [rust_xbase_reopen_all].
+//
+// Registered factories are tried in order of registration, before .DAT files.
+// Paths which are not accepted by any factory are treated as directories.
+pub fn xbase_register_backend_factory(factory: XBaseBackendFactory) {
+    XBASE_BACKEND_FACTORIES.write().push(factory);
+}
+
+// Unregisters [factory] registered with [xbase_register_backend_factory].
+// Xbases which are already open with it are not affected.
+//
+// Returns false if [factory] is not registered.
+pub fn xbase_unregister_backend_factory(factory: XBaseBackendFactory) -> bool {
+    let mut factories = XBASE_BACKEND_FACTORIES.write();
+    match factories
+        .iter()
+        .rposition(|&registered| ptr::fn_addr_eq(registered, factory))
+    {
+        Some(index) => {
+            factories.remove(index);
+            true
+        }
+        None => false,
+    }
+}
+
+// Adds [backend] at the top of search order of the global [Vfs], see
+// [Vfs::mount].
+pub fn xbase_mount(name: &CStr, backend: Box<dyn XBaseBackend>) {
+    Vfs::global().mount(name, backend);
+}
+
+// Finds file at relative [file_path] in open xbases of the global [Vfs].
+pub fn xbase_stat(file_path: &CStr) -> Option<XBaseStat> {
+    Vfs::global().stat(file_path)
+}
+
+pub fn xbase_exists(file_path: &CStr) -> bool {
+    Vfs::global().exists(file_path)
+}
+
+// Opens backend for the source at [path] with registered factories, or as a
+// .DAT file (which also covers ZIP archives, see [DBase::open]).
+//
+// Returns None if it's neither, in this case [path] is expected to be a
+// directory.
+pub(crate) fn xbase_open_backend(path: &CStr) -> Option<Box<dyn XBaseBackend>> {
+    let factories = XBASE_BACKEND_FACTORIES.read().clone();
+    for factory in factories {
+        if let Some(backend) = factory(path) {
+            return Some(backend);
+        }
+    }
+
+    let dbase = unsafe { dbase_open(path.as_ptr()) }?;
+    Some(Box::new(dbase))
+}
+
+impl XBaseBackend for DBase {
+    fn open(&self, file_path: &CStr, mode: &CStr) -> Option<XFileType> {
+        let dfile = unsafe { rust_dfile_open(self, file_path.as_ptr(), mode.as_ptr()) }?;
+        Some(XFileType::DFile(dfile))
+    }
+
+    fn enumerate(
+        &self,
+        pattern: &CStr,
+        visitor: &mut dyn FnMut(&CStr, XFileEnumerationEntryType) -> bool,
+    ) -> bool {
+        let mut dbase_find_data = DFileFindData::default();
+        unsafe {
+            if !dbase_find_first_entry(self, &mut dbase_find_data, pattern.as_ptr()) {
+                return true;
+            }
+
+            let mut result = true;
+            loop {
+                if !visitor(
+                    dbase_find_data.file_name,
+                    XFileEnumerationEntryType::XfileEnumerationEntryTypeDfile,
+                ) {
+                    result = false;
+                    break;
+                }
+                if !dbase_find_next_entry(self, &mut dbase_find_data) {
+                    break;
+                }
+            }
+
+            dbase_find_close(self, &dbase_find_data);
+
+            result
+        }
+    }
+
+    fn files(&self, visitor: &mut dyn FnMut(&CStr) -> bool) -> bool {
+        self.entries().all(|entry| visitor(entry.path()))
+    }
+
+    fn stat(&self, file_path: &CStr) -> Option<XBaseStat> {
+        let entry = self.entry(file_path.to_str().ok()?)?;
+        Some(XBaseStat {
+            size: entry.uncompressed_size() as u64,
+            is_directory: false,
+        })
+    }
+}
+
+// A directory in file system.
+pub struct XBaseDirectory {
+    path: CString,
+}
+
+impl XBaseDirectory {
+    pub fn new(path: CString) -> Self {
+        Self { path }
+    }
+
+    pub(crate) fn path(&self) -> &CStr {
+        &self.path
+    }
+
+    // Builds path of [file_path] relative to this directory.
+    pub(crate) fn join(&self, file_path: &CStr) -> CString {
+        let mut path = self.path.as_bytes().to_vec();
+        path.push(b'\\');
+        path.extend_from_slice(file_path.to_bytes());
+        CString::new(path).expect("no null characters")
+    }
+}
+
+impl XBaseBackend for XBaseDirectory {
+    fn open(&self, file_path: &CStr, mode: &CStr) -> Option<XFileType> {
+        xfile_open_plain(&self.join(file_path), mode)
+    }
+
+    fn enumerate(
+        &self,
+        pattern: &CStr,
+        visitor: &mut dyn FnMut(&CStr, XFileEnumerationEntryType) -> bool,
+    ) -> bool {
+        let mut native_pattern = pattern.to_bytes_with_nul().to_vec();
+        let mut path = self.join(pattern).into_bytes_with_nul();
+        unsafe {
+            compat_windows_path_to_native(native_pattern.as_mut_ptr() as *mut c_char);
+            compat_windows_path_to_native(path.as_mut_ptr() as *mut c_char);
+            xbase_enumerate_directory(
+                path.as_ptr() as *const c_char,
+                native_pattern.as_ptr() as *const c_char,
+                visitor,
+            )
+        }
+    }
+
+    fn stat(&self, file_path: &CStr) -> Option<XBaseStat> {
+        let path = unsafe { compat_native_path(self.join(file_path).as_ptr()) };
+        let metadata = fs::metadata(path).ok()?;
+        Some(XBaseStat {
+            size: metadata.len(),
+            is_directory: metadata.is_dir(),
+        })
+    }
+
+    fn directory(&self) -> Option<&CStr> {
+        Some(&self.path)
+    }
+}
+
+// Calls [visitor] with every file system entry matching [path] (native path
+// with wildcards). Entry names are reported with the directory part of
+// [name_pattern].
+//
+// Returns false if [visitor] requested to stop enumeration.
+pub(crate) unsafe fn xbase_enumerate_directory(
+    path: *const c_char,
+    name_pattern: *const c_char,
+    visitor: &mut dyn FnMut(&CStr, XFileEnumerationEntryType) -> bool,
+) -> bool {
+    let directory = compat_directory_part(CStr::from_ptr(name_pattern).to_bytes());
+
+    let mut directory_file_find_data = DirectoryFileFindData::default();
+    if !file_find_first(path, &mut directory_file_find_data) {
+        return true;
+    }
+
+    let mut result = true;
+    loop {
+        let entry_name = CStr::from_ptr(file_find_get_name(&directory_file_find_data));
+        let entry_type = if file_find_is_directory(&directory_file_find_data) {
+            XFileEnumerationEntryType::XfileEnumerationEntryTypeDirectory
+        } else {
+            XFileEnumerationEntryType::XfileEnumerationEntryTypeFile
+        };
+
+        if entry_name.to_bytes() != b"." && entry_name.to_bytes() != b".." {
+            let mut name = directory.to_vec();
+            name.extend_from_slice(entry_name.to_bytes());
+            let name = CString::new(name).expect("no null characters");
+
+            if !visitor(&name, entry_type) {
+                result = false;
+                break;
+            }
+        }
+
+        if !file_find_next(&mut directory_file_find_data) {
+            break;
+        }
+    }
+
+    file_find_close(&directory_file_find_data);
+
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dbase_writer::DBaseWriter;
+    use crate::test_utils::{cstring, lock_global_vfs, read_file, temp_path};
+    use crate::xfile::{rust_xbase_reopen_all, xlist_free, xlist_init, XList};
+    use crate::xfile_trace::{
+        rust_xfile_set_trace_handler, XFileTraceEvent, XFileTraceResult, XFileTraceSource,
+    };
+    use std::ptr::null_mut;
+    use std::sync::Mutex;
+
+    // Serves "alias.txt" from the file specified in factory path.
+    struct AliasBackend {
+        target: CString,
+    }
+
+    impl XBaseBackend for AliasBackend {
+        fn open(&self, file_path: &CStr, mode: &CStr) -> Option<XFileType> {
+            if file_path.to_bytes() != b"alias.txt" {
+                return None;
+            }
+            xfile_open_plain(&self.target, mode)
+        }
+
+        fn enumerate(
+            &self,
+            _pattern: &CStr,
+            visitor: &mut dyn FnMut(&CStr, XFileEnumerationEntryType) -> bool,
+        ) -> bool {
+            visitor(
+                &cstring("alias.txt"),
+                XFileEnumerationEntryType::XfileEnumerationEntryTypeFile,
+            )
+        }
+
+        fn stat(&self, file_path: &CStr) -> Option<XBaseStat> {
+            (file_path.to_bytes() == b"alias.txt").then_some(XBaseStat {
+                size: 5,
+                is_directory: false,
+            })
+        }
+    }
+
+    fn alias_factory(path: &CStr) -> Option<Box<dyn XBaseBackend>> {
+        let target = path.to_bytes().strip_prefix(b"alias:")?;
+        Some(Box::new(AliasBackend {
+            target: CString::new(target).ok()?,
+        }))
+    }
+
+    #[test]
+    fn test_xbase_backends() {
+        let _lock = lock_global_vfs();
+
+        let directory = temp_path("directory");
+        let dat_path = temp_path("backends.dat");
+        fs::create_dir_all(directory.join("sub")).expect("");
+        fs::write(directory.join("readme.txt"), b"directory").expect("");
+        fs::write(directory.join("only_directory.txt"), b"directory").expect("");
+        fs::write(directory.join("sub").join("nested.txt"), b"nested").expect("");
+
+        let mut writer = DBaseWriter::new();
+        writer.add_data("readme.txt", b"dat".to_vec()).expect("");
+        writer.add_data("only_dat.txt", b"dat".to_vec()).expect("");
+        writer.write(&dat_path).expect("");
+
+        xbase_register_backend_factory(alias_factory);
+
+        // The last path has the highest priority.
+        let paths = format!(
+            "{};{};alias:{}",
+            directory.display(),
+            dat_path.display(),
+            directory.join("sub").join("nested.txt").display()
+        );
+        let mut paths = CString::new(paths).expect("").into_bytes_with_nul();
+        unsafe {
+            assert!(rust_xbase_reopen_all(paths.as_mut_ptr() as *mut c_char));
+
+            assert_eq!(read_file(&cstring("readme.txt")), Some(b"dat".to_vec()));
+            assert_eq!(
+                read_file(&cstring("only_directory.txt")),
+                Some(b"directory".to_vec())
+            );
+            assert_eq!(
+                read_file(&cstring("sub\\nested.txt")),
+                Some(b"nested".to_vec())
+            );
+            assert_eq!(read_file(&cstring("alias.txt")), Some(b"nested".to_vec()));
+            assert_eq!(read_file(&cstring("missing.txt")), None);
+
+            assert_eq!(
+                xbase_stat(&cstring("readme.txt")).map(|stat| stat.size),
+                Some(3)
+            );
+            assert_eq!(
+                xbase_stat(&cstring("alias.txt")).map(|stat| stat.size),
+                Some(5)
+            );
+            assert!(xbase_stat(&cstring("sub")).expect("").is_directory);
+            assert!(xbase_exists(&cstring("only_dat.txt")));
+            assert!(!xbase_exists(&cstring("missing.txt")));
+
+            let mut xlist = XList {
+                file_names_length: 0,
+                file_names: null_mut(),
+            };
+            assert!(xlist_init(cstring("*.txt").as_ptr(), &mut xlist));
+            let mut names = (0..xlist.file_names_length)
+                .map(|index| {
+                    let name = *xlist.file_names.offset(index as isize);
+                    CStr::from_ptr(name).to_string_lossy().into_owned()
+                })
+                .collect::<Vec<_>>();
+            xlist_free(&mut xlist);
+            names.sort();
+            names.dedup();
+            assert_eq!(
+                names,
+                [
+                    "alias.txt",
+                    "only_dat.txt",
+                    "only_directory.txt",
+                    "readme.txt"
+                ]
+            );
+
+            assert!(rust_xbase_reopen_all(null_mut()));
+        }
+
+        assert!(xbase_unregister_backend_factory(alias_factory));
+        assert!(!xbase_unregister_backend_factory(alias_factory));
+
+        fs::remove_dir_all(&directory).expect("");
+        fs::remove_file(&dat_path).expect("");
+    }
+
+    // Events of "traced.txt" received by [trace_handler].
+    static TRACE_EVENTS: Mutex<Vec<(XFileTraceSource, XFileTraceResult)>> = Mutex::new(Vec::new());
+
+    unsafe extern "C" fn trace_handler(event: *const XFileTraceEvent) {
+        if CStr::from_ptr((*event).file_path).to_bytes() == b"traced.txt" {
+            TRACE_EVENTS
+                .lock()
+                .expect("")
+                .push(((*event).source, (*event).result));
+        }
+    }
+
+    #[test]
+    fn test_xfile_trace() {
+        let _lock = lock_global_vfs();
+
+        let directory = temp_path("traced");
+        let dat_path = temp_path("traced.dat");
+        fs::create_dir_all(&directory).expect("");
+        fs::write(directory.join("traced.txt"), b"directory").expect("");
+
+        let mut writer = DBaseWriter::new();
+        writer.add_data("other.txt", b"dat".to_vec()).expect("");
+        writer.write(&dat_path).expect("");
+
+        let paths = format!("{};{}", directory.display(), dat_path.display());
+        let mut paths = CString::new(paths).expect("").into_bytes_with_nul();
+        unsafe {
+            assert!(rust_xbase_reopen_all(paths.as_mut_ptr() as *mut c_char));
+
+            rust_xfile_set_trace_handler(Some(trace_handler));
+            assert_eq!(
+                read_file(&cstring("traced.txt")),
+                Some(b"directory".to_vec())
+            );
+            rust_xfile_set_trace_handler(None);
+
+            assert!(rust_xbase_reopen_all(null_mut()));
+        }
+
+        assert_eq!(
+            *TRACE_EVENTS.lock().expect(""),
+            [
+                (XFileTraceSource::Archive, XFileTraceResult::NotFound),
+                (XFileTraceSource::Directory, XFileTraceResult::Found),
+            ]
+        );
+
+        fs::remove_dir_all(&directory).expect("");
+        fs::remove_file(&dat_path).expect("");
+    }
+}
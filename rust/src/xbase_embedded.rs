@@ -0,0 +1,94 @@
+// Fallback resources compiled into the executable.
+//
+// The embedded source sits below every other source in search order (including
+// current working directory, see [xfile_open]), so it only serves files which
+// are missing from game data. It provides just enough to show readable error
+// instead of crashing when data directory is missing or incomplete: default
+// config (see `gameConfigInit`), a text font and a palette (see
+// `gameShowFatalError`).
+//
+// Fonts and palette are generated from `resources` by build script. The
+// source can be disabled with [Vfs::set_embedded_fallback].
+
+use crate::dfile::dbase_normalize_path;
+use crate::fpattern::fpattern_match;
+use crate::xbase_backend::{XBaseBackend, XBaseStat};
+use crate::xbase_overlay::xfile_is_write_mode;
+use crate::xfile::{XFileEnumerationEntryType, XFileType};
+use crate::xfile_memory::MemoryFile;
+use std::ffi::{CStr, CString};
+
+struct XBaseEmbeddedFile {
+    path: &'static str,
+    data: &'static [u8],
+}
+
+static XBASE_EMBEDDED_FILES: &[XBaseEmbeddedFile] = &[
+    XBaseEmbeddedFile {
+        path: "fallout2.cfg",
+        data: include_bytes!("../resources/fallout2.cfg"),
+    },
+    XBaseEmbeddedFile {
+        path: "font0.fon",
+        data: include_bytes!(concat!(env!("OUT_DIR"), "/font0.fon")),
+    },
+    XBaseEmbeddedFile {
+        path: "color.pal",
+        data: include_bytes!(concat!(env!("OUT_DIR"), "/color.pal")),
+    },
+];
+
+pub(crate) struct XBaseEmbedded;
+
+pub(crate) static XBASE_EMBEDDED: XBaseEmbedded = XBaseEmbedded;
+
+impl XBaseEmbedded {
+    fn find(&self, file_path: &CStr) -> Option<&'static XBaseEmbeddedFile> {
+        let key = dbase_normalize_path(file_path.to_bytes());
+        XBASE_EMBEDDED_FILES
+            .iter()
+            .find(|file| dbase_normalize_path(file.path.as_bytes()) == key)
+    }
+}
+
+impl XBaseBackend for XBaseEmbedded {
+    fn open(&self, file_path: &CStr, mode: &CStr) -> Option<XFileType> {
+        // Embedded files are read-only.
+        if xfile_is_write_mode(mode) {
+            return None;
+        }
+
+        let file = self.find(file_path)?;
+        Some(XFileType::Memory(MemoryFile::from_static(file.data)))
+    }
+
+    fn enumerate(
+        &self,
+        pattern: &CStr,
+        visitor: &mut dyn FnMut(&CStr, XFileEnumerationEntryType) -> bool,
+    ) -> bool {
+        // NOTE: Unlike .DAT files, matching is case-insensitive.
+        let pattern =
+            CString::new(pattern.to_bytes().to_ascii_uppercase()).expect("no null characters");
+        XBASE_EMBEDDED_FILES.iter().all(|file| {
+            let path = CString::new(file.path).expect("no null characters");
+            let key = CString::new(file.path.to_ascii_uppercase()).expect("no null characters");
+            if !unsafe { fpattern_match(pattern.as_ptr(), key.as_ptr()) } {
+                return true;
+            }
+
+            visitor(
+                &path,
+                XFileEnumerationEntryType::XfileEnumerationEntryTypeDfile,
+            )
+        })
+    }
+
+    fn stat(&self, file_path: &CStr) -> Option<XBaseStat> {
+        let file = self.find(file_path)?;
+        Some(XBaseStat {
+            size: file.data.len() as u64,
+            is_directory: false,
+        })
+    }
+}
@@ -0,0 +1,301 @@
+// A writable directory on top of xbases.
+//
+// When overlay is set, every relative [rust_xfile_open] with write mode goes
+// to the overlay directory, so that xbases (game data and mod directories)
+// are never modified. Reads look into the overlay first, then fall back to
+// xbases in search order.
+//
+// Deleting a file which only exists in lower xbases is not possible, instead
+// an empty marker file (whiteout) can be created next to where overlay copy
+// would be. Whiteouts are opt-in, see [rust_xbase_set_overlay].
+
+use crate::debug_print::debug_print;
+use crate::platform_compat::{compat_native_path, compat_path_changed};
+use crate::vfs::Vfs;
+use crate::xbase_backend::{XBaseBackend, XBaseDirectory};
+use crate::xfile::{
+    xfile_exists_below_overlay, xfile_open_below_overlay, xfile_read_all, XFileType,
+};
+use libc::{c_char, c_int};
+use std::ffi::{CStr, CString};
+use std::fs;
+use std::ptr::null;
+
+// The prefix of whiteout marker file name.
+const XBASE_OVERLAY_WHITEOUT_PREFIX: &[u8] = b".wh.";
+
+pub(crate) struct XBaseOverlay {
+    directory: XBaseDirectory,
+
+    // Specifies that whiteout markers are used to hide files of lower
+    // xbases.
+    whiteouts: bool,
+}
+
+// Specifies that stream opened with [mode] can modify the file.
+pub(crate) fn xfile_is_write_mode(mode: &CStr) -> bool {
+    mode.to_bytes()
+        .iter()
+        .any(|&ch| ch == b'w' || ch == b'a' || ch == b'+')
+}
+
+// Sets [path] as overlay directory (creating it if needed), or removes
+// overlay if [path] is NULL.
+//
+// When [whiteouts] is set, files which are removed with
+// [rust_xbase_overlay_remove] are hidden in lower xbases.
+#[no_mangle]
+pub unsafe extern "C" fn rust_xbase_set_overlay(path: *const c_char, whiteouts: bool) -> bool {
+    let path = match path.is_null() {
+        true => None,
+        false => Some(CStr::from_ptr(path)),
+    };
+    Vfs::global().set_overlay(path, whiteouts)
+}
+
+// Removes [file_path] from overlay, and hides it in lower xbases (if
+// whiteouts are enabled).
+//
+// Returns 0 if the file was removed or hidden, -1 otherwise.
+#[no_mangle]
+pub unsafe extern "C" fn rust_xbase_overlay_remove(file_path: *const c_char) -> c_int {
+    assert_ne!(file_path, null());
+
+    let vfs = Vfs::global();
+    let Some(overlay) = vfs.overlay() else {
+        return -1;
+    };
+
+    let file_path = CStr::from_ptr(file_path);
+    if let Err(err) = vfs.check_path(file_path) {
+        debug_print(format_args!("xfile: {}", err));
+        return -1;
+    }
+
+    match overlay.remove(vfs, file_path) {
+        true => 0,
+        false => -1,
+    }
+}
+
+impl XBaseOverlay {
+    // Creates overlay at [path], which is created if needed.
+    pub(crate) fn new(path: &CStr, whiteouts: bool) -> Option<Self> {
+        let native_path = unsafe { compat_native_path(path.as_ptr()) };
+        fs::create_dir_all(&native_path).ok()?;
+        compat_path_changed(&native_path);
+
+        Some(Self {
+            directory: XBaseDirectory::new(path.to_owned()),
+            whiteouts,
+        })
+    }
+
+    pub(crate) fn directory(&self) -> &XBaseDirectory {
+        &self.directory
+    }
+
+    // Builds path of whiteout marker of [file_path], which is the file name
+    // with prefix in the same directory.
+    fn whiteout_path(file_path: &CStr) -> CString {
+        let bytes = file_path.to_bytes();
+        let name_start = bytes
+            .iter()
+            .rposition(|&ch| ch == b'\\' || ch == b'/')
+            .map(|index| index + 1)
+            .unwrap_or(0);
+
+        let mut path = bytes[..name_start].to_vec();
+        path.extend_from_slice(XBASE_OVERLAY_WHITEOUT_PREFIX);
+        path.extend_from_slice(&bytes[name_start..]);
+        CString::new(path).expect("no null characters")
+    }
+
+    // Specifies that [file_path] is hidden by whiteout marker.
+    pub(crate) fn hides(&self, file_path: &CStr) -> bool {
+        self.whiteouts && self.directory.exists(&Self::whiteout_path(file_path))
+    }
+
+    // Specifies that enumerated entry at [file_path] should not be listed:
+    // either it's hidden, or it's a whiteout marker itself.
+    pub(crate) fn hides_entry(&self, file_path: &CStr) -> bool {
+        if !self.whiteouts {
+            return false;
+        }
+
+        let bytes = file_path.to_bytes();
+        let name = bytes
+            .rsplit(|&ch| ch == b'\\' || ch == b'/')
+            .next()
+            .unwrap_or(bytes);
+        name.starts_with(XBASE_OVERLAY_WHITEOUT_PREFIX) || self.hides(file_path)
+    }
+
+    // Opens [file_path] in overlay directory for writing.
+    //
+    // Missing directories are created. When file is opened for update (as
+    // opposed to truncating it), but only exists below overlay (see
+    // [xfile_open_below_overlay]), it's copied to overlay first.
+    pub(crate) fn open_for_writing(
+        &self,
+        vfs: &Vfs,
+        file_path: &CStr,
+        mode: &CStr,
+    ) -> Option<XFileType> {
+        let path = self.directory.join(file_path);
+        let native_path = unsafe { compat_native_path(path.as_ptr()) };
+        if let Some(parent) = native_path.parent() {
+            fs::create_dir_all(parent).ok()?;
+            compat_path_changed(parent);
+        }
+
+        let truncates = mode.to_bytes().contains(&b'w');
+        if !truncates && !self.directory.exists(file_path) && !self.hides(file_path) {
+            let read_mode = CString::new("rb").expect("valid string");
+            if let Some(file) = unsafe { xfile_open_below_overlay(vfs, file_path, &read_mode) } {
+                let data = unsafe { xfile_read_all(file) };
+                fs::write(&native_path, data).ok()?;
+                compat_path_changed(&native_path);
+            }
+        }
+
+        let file = self.directory.open(file_path, mode)?;
+
+        // File is visible again.
+        if self.whiteouts {
+            let whiteout_path = self.directory.join(&Self::whiteout_path(file_path));
+            let native_whiteout_path = unsafe { compat_native_path(whiteout_path.as_ptr()) };
+            if fs::remove_file(&native_whiteout_path).is_ok() {
+                compat_path_changed(&native_whiteout_path);
+            }
+        }
+
+        Some(file)
+    }
+
+    fn remove(&self, vfs: &Vfs, file_path: &CStr) -> bool {
+        let path = self.directory.join(file_path);
+        let native_path = unsafe { compat_native_path(path.as_ptr()) };
+        let removed = fs::remove_file(&native_path).is_ok();
+        if removed {
+            compat_path_changed(&native_path);
+        }
+
+        if self.whiteouts && !self.hides(file_path) && xfile_exists_below_overlay(vfs, file_path) {
+            let whiteout_path = self.directory.join(&Self::whiteout_path(file_path));
+            let native_whiteout_path = unsafe { compat_native_path(whiteout_path.as_ptr()) };
+            let written = fs::write(&native_whiteout_path, []).is_ok();
+            compat_path_changed(&native_whiteout_path);
+            return written;
+        }
+
+        removed
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::{cstring, lock_global_vfs, read_file, temp_path};
+    use crate::xfile::{
+        rust_xbase_reopen_all, rust_xfile_close, rust_xfile_open, rust_xfile_write, xlist_free,
+        xlist_init, XList,
+    };
+    use libc::c_void;
+    use std::ptr::null_mut;
+
+    unsafe fn write_file(file_path: &CStr, mode: &str, data: &[u8]) -> bool {
+        let stream = rust_xfile_open(file_path.as_ptr(), cstring(mode).as_ptr());
+        if stream.is_null() {
+            return false;
+        }
+
+        rust_xfile_write(data.as_ptr() as *const c_void, 1, data.len(), stream);
+        rust_xfile_close(stream);
+        true
+    }
+
+    #[test]
+    fn test_xbase_overlay() {
+        let _lock = lock_global_vfs();
+
+        let directory = temp_path("lower");
+        let overlay = temp_path("overlay");
+        fs::create_dir_all(directory.join("data")).expect("");
+        fs::write(directory.join("data").join("saved.txt"), b"lower").expect("");
+        fs::write(directory.join("removed.txt"), b"lower").expect("");
+
+        let mut paths = CString::new(directory.to_str().expect(""))
+            .expect("")
+            .into_bytes_with_nul();
+        let overlay_path = cstring(overlay.to_str().expect(""));
+        unsafe {
+            assert!(rust_xbase_reopen_all(paths.as_mut_ptr() as *mut c_char));
+            assert!(rust_xbase_set_overlay(overlay_path.as_ptr(), true));
+
+            // Appending copies file up, lower file is intact.
+            assert!(write_file(&cstring("data\\saved.txt"), "ab", b"+upper"));
+            assert_eq!(
+                read_file(&cstring("data\\saved.txt")),
+                Some(b"lower+upper".to_vec())
+            );
+            assert_eq!(
+                fs::read(directory.join("data").join("saved.txt")).expect(""),
+                b"lower"
+            );
+
+            // New files (with missing directories) are created in overlay.
+            assert!(write_file(&cstring("new\\created.txt"), "wb", b"new"));
+            assert_eq!(
+                fs::read(overlay.join("new").join("created.txt")).expect(""),
+                b"new"
+            );
+
+            // Removing lower file hides it.
+            assert_eq!(
+                rust_xbase_overlay_remove(cstring("removed.txt").as_ptr()),
+                0
+            );
+            assert_eq!(read_file(&cstring("removed.txt")), None);
+            assert!(directory.join("removed.txt").exists());
+
+            let mut xlist = XList {
+                file_names_length: 0,
+                file_names: null_mut(),
+            };
+            assert!(xlist_init(cstring("*.*").as_ptr(), &mut xlist));
+            let names = (0..xlist.file_names_length)
+                .map(|index| {
+                    let name = *xlist.file_names.offset(index as isize);
+                    CStr::from_ptr(name).to_string_lossy().into_owned()
+                })
+                .collect::<Vec<_>>();
+            xlist_free(&mut xlist);
+            assert!(!names.iter().any(|name| name.contains("removed.txt")));
+
+            // Files served from the current working directory (tests are run
+            // in the package directory) are copied up too, and can be hidden
+            // as well.
+            let manifest = fs::read("Cargo.toml").expect("");
+            assert!(write_file(&cstring("Cargo.toml"), "ab", b"#"));
+            assert_eq!(
+                fs::read(overlay.join("Cargo.toml")).expect(""),
+                [manifest.as_slice(), b"#"].concat()
+            );
+            assert_eq!(fs::read("Cargo.toml").expect(""), manifest);
+            assert_eq!(rust_xbase_overlay_remove(cstring("Cargo.toml").as_ptr()), 0);
+            assert_eq!(read_file(&cstring("Cargo.toml")), None);
+
+            // Writing file again makes it visible.
+            assert!(write_file(&cstring("removed.txt"), "wb", b"upper"));
+            assert_eq!(read_file(&cstring("removed.txt")), Some(b"upper".to_vec()));
+
+            assert!(rust_xbase_set_overlay(null(), false));
+            assert_eq!(read_file(&cstring("removed.txt")), Some(b"lower".to_vec()));
+            assert!(rust_xbase_reopen_all(null_mut()));
+        }
+
+        fs::remove_dir_all(&directory).expect("");
+        fs::remove_dir_all(&overlay).expect("");
+    }
+}
@@ -0,0 +1,606 @@
+// Watching of directory xbases for changes.
+//
+// When watching is enabled (see [Vfs::set_watching]), every directory xbase
+// is watched (with its subdirectories) with inotify, so that modders can
+// iterate on loose files (art, message lists, etc.) without restarting the
+// game. Changes are collected by [Vfs::poll_changes], or by
+// [rust_xbase_poll_changes] which reports them to the handler registered with
+// [rust_xbase_set_change_handler]. It's expected to be called once per frame.
+//
+// Watching is disabled by default, in this case nothing is watched and polling
+// is a no-op. It's only supported on Linux.
+
+use crate::debug_print::debug_print;
+#[cfg(target_os = "linux")]
+use crate::platform_compat::{
+    compat_native_path, compat_path_changed, rust_compat_clear_path_cache,
+};
+use crate::vfs::Vfs;
+use libc::{c_char, c_int};
+#[cfg(target_os = "linux")]
+use libc::{
+    inotify_add_watch, inotify_event, inotify_init1, inotify_rm_watch, IN_CLOEXEC, IN_CLOSE_WRITE,
+    IN_CREATE, IN_DELETE, IN_IGNORED, IN_ISDIR, IN_MOVED_FROM, IN_MOVED_TO, IN_NONBLOCK,
+    IN_ONLYDIR, IN_Q_OVERFLOW,
+};
+use spin::RwLock;
+#[cfg(target_os = "linux")]
+use std::collections::{HashMap, HashSet};
+use std::ffi::CString;
+#[cfg(target_os = "linux")]
+use std::ffi::{CStr, OsStr};
+#[cfg(target_os = "linux")]
+use std::fs::{self, File};
+use std::io;
+#[cfg(target_os = "linux")]
+use std::io::Read;
+#[cfg(target_os = "linux")]
+use std::mem;
+#[cfg(target_os = "linux")]
+use std::os::fd::{AsRawFd, FromRawFd};
+#[cfg(target_os = "linux")]
+use std::os::unix::ffi::{OsStrExt, OsStringExt};
+#[cfg(target_os = "linux")]
+use std::path::{Path, PathBuf};
+#[cfg(target_os = "linux")]
+use std::ptr;
+
+#[repr(C)]
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum XBaseChangeKind {
+    // The file is created or modified (including being moved in).
+    Modified = 0,
+
+    // The file is removed (or moved away).
+    Removed = 1,
+}
+
+#[derive(Clone, PartialEq, Debug)]
+pub struct XBaseChange {
+    // The path of changed file relative to xbase (with backslashes as
+    // separators), as it's passed to [rust_xfile_open].
+    pub file_path: CString,
+
+    // The path of xbase containing the file.
+    pub source_path: CString,
+
+    pub kind: XBaseChangeKind,
+}
+
+#[repr(C)]
+pub struct XBaseChangeEvent {
+    pub file_path: *const c_char,
+    pub source_path: *const c_char,
+    pub kind: XBaseChangeKind,
+}
+
+pub type XBaseChangeHandler = unsafe extern "C" fn(*const XBaseChangeEvent);
+
+static G_XBASE_CHANGE_HANDLER: RwLock<Option<XBaseChangeHandler>> = RwLock::new(None);
+
+// Enables or disables watching directory xbases of the global [Vfs].
+//
+// Returns false if watching cannot be enabled.
+#[no_mangle]
+pub extern "C" fn rust_xbase_set_watching(enabled: bool) -> bool {
+    match Vfs::global().set_watching(enabled) {
+        Ok(()) => true,
+        Err(err) => {
+            debug_print(format_args!("xbase: cannot watch xbases: {}", err));
+            false
+        }
+    }
+}
+
+// Sets [handler] to receive change events, or disables it if [handler] is
+// NULL.
+#[no_mangle]
+pub extern "C" fn rust_xbase_set_change_handler(handler: Option<XBaseChangeHandler>) {
+    *G_XBASE_CHANGE_HANDLER.write() = handler;
+}
+
+// Reports files changed since the previous call to the handler (see
+// [rust_xbase_set_change_handler]).
+//
+// Returns the number of changes.
+#[no_mangle]
+pub extern "C" fn rust_xbase_poll_changes() -> c_int {
+    let changes = Vfs::global().poll_changes();
+    if changes.is_empty() {
+        return 0;
+    }
+
+    let handler = *G_XBASE_CHANGE_HANDLER.read();
+    if let Some(handler) = handler {
+        for change in &changes {
+            let event = XBaseChangeEvent {
+                file_path: change.file_path.as_ptr(),
+                source_path: change.source_path.as_ptr(),
+                kind: change.kind,
+            };
+            unsafe { handler(&event) };
+        }
+    }
+
+    changes.len() as c_int
+}
+
+// Events of files (and subdirectories) in watched directories.
+#[cfg(target_os = "linux")]
+const XBASE_WATCH_MASK: u32 =
+    IN_CLOSE_WRITE | IN_CREATE | IN_DELETE | IN_MOVED_FROM | IN_MOVED_TO | IN_ONLYDIR;
+
+// A directory watched on behalf of xbase.
+#[cfg(target_os = "linux")]
+struct XBaseWatch {
+    source_path: CString,
+
+    // The path relative to xbase (empty for xbase itself).
+    directory: Vec<u8>,
+
+    native_path: PathBuf,
+
+    // Names of files in the directory, which are reported as removed when
+    // the directory is moved away (there are no events for them).
+    files: HashSet<Vec<u8>>,
+}
+
+#[cfg(target_os = "linux")]
+pub(crate) struct XBaseWatcher {
+    inotify: File,
+
+    // Watched xbases (xbase path and its native directory).
+    roots: Vec<(CString, PathBuf)>,
+
+    // The generation of xbases list (see [Vfs::generation]) at the time of
+    // the last [XBaseWatcher::sync].
+    generation: Option<u64>,
+
+    // Watched directories by watch descriptor. There can be several of them
+    // when xbases share directories.
+    watches: HashMap<c_int, Vec<XBaseWatch>>,
+}
+
+#[cfg(target_os = "linux")]
+impl XBaseWatcher {
+    pub(crate) fn new() -> io::Result<Self> {
+        let fd = unsafe { inotify_init1(IN_NONBLOCK | IN_CLOEXEC) };
+        if fd == -1 {
+            return Err(io::Error::last_os_error());
+        }
+
+        Ok(Self {
+            inotify: unsafe { File::from_raw_fd(fd) },
+            roots: Vec::new(),
+            generation: None,
+            watches: HashMap::new(),
+        })
+    }
+
+    // Returns files changed in directory xbases of [vfs] since the previous
+    // call. Xbases opened in the meantime are watched from now on, and closed
+    // ones are no longer watched.
+    pub(crate) fn poll(&mut self, vfs: &Vfs) -> Vec<XBaseChange> {
+        let mut changes = self.read_changes();
+        self.sync(vfs);
+
+        // Only the last change of every file matters (for example, editors
+        // often remove and recreate files on save).
+        let mut seen = HashSet::new();
+        changes.reverse();
+        changes
+            .retain(|change| seen.insert((change.source_path.clone(), change.file_path.clone())));
+        changes.reverse();
+
+        changes
+    }
+
+    // Starts watching directory xbases of [vfs] which are not watched yet,
+    // and stops watching ones which are closed. Does nothing if xbases have
+    // not changed since the previous call.
+    pub(crate) fn sync(&mut self, vfs: &Vfs) {
+        let generation = vfs.generation();
+        if self.generation == Some(generation) {
+            return;
+        }
+        self.generation = Some(generation);
+
+        let mut roots = Vec::new();
+        vfs.visit_xbases(&mut |path, backend| {
+            if let Some(directory) = backend.directory() {
+                let native_path = unsafe { compat_native_path(directory.as_ptr()) };
+                roots.push((path.to_owned(), native_path));
+            }
+        });
+
+        if roots == self.roots {
+            return;
+        }
+
+        for (source_path, _) in mem::take(&mut self.roots) {
+            if !roots.iter().any(|root| root.0 == source_path) {
+                self.unwatch(&source_path, None, None);
+            }
+        }
+
+        for (source_path, native_path) in &roots {
+            self.watch(source_path, native_path, Vec::new(), None);
+        }
+
+        self.roots = roots;
+    }
+
+    // Watches [native_path] (which is [directory] of xbase at [source_path])
+    // with its subdirectories. Files found there are reported to [changes].
+    fn watch(
+        &mut self,
+        source_path: &CStr,
+        native_path: &Path,
+        directory: Vec<u8>,
+        mut changes: Option<&mut Vec<XBaseChange>>,
+    ) {
+        let Ok(path) = CString::new(native_path.as_os_str().as_bytes()) else {
+            return;
+        };
+
+        let wd =
+            unsafe { inotify_add_watch(self.inotify.as_raw_fd(), path.as_ptr(), XBASE_WATCH_MASK) };
+        if wd == -1 {
+            debug_print(format_args!(
+                "xbase: cannot watch {}: {}",
+                native_path.display(),
+                io::Error::last_os_error()
+            ));
+            return;
+        }
+
+        // The directory is already watched (which also prevents loops through
+        // symbolic links).
+        let watches = self.watches.entry(wd).or_default();
+        if watches
+            .iter()
+            .any(|watch| watch.source_path.as_c_str() == source_path)
+        {
+            return;
+        }
+
+        watches.push(XBaseWatch {
+            source_path: source_path.to_owned(),
+            directory: directory.clone(),
+            native_path: native_path.to_owned(),
+            files: HashSet::new(),
+        });
+
+        let Ok(entries) = fs::read_dir(native_path) else {
+            return;
+        };
+
+        let mut files = HashSet::new();
+        for entry in entries.flatten() {
+            let entry_path = entry.path();
+            let file_path = xbase_watch_join(&directory, entry.file_name().as_bytes());
+            if entry_path.is_dir() {
+                self.watch(source_path, &entry_path, file_path, changes.as_deref_mut());
+                continue;
+            }
+
+            files.insert(entry.file_name().into_vec());
+            if let Some(changes) = changes.as_deref_mut() {
+                changes.push(XBaseChange {
+                    file_path: CString::new(file_path).expect("no null characters"),
+                    source_path: source_path.to_owned(),
+                    kind: XBaseChangeKind::Modified,
+                });
+            }
+        }
+
+        if let Some(watch) = self.watches.get_mut(&wd).and_then(|watches| {
+            watches
+                .iter_mut()
+                .find(|watch| watch.source_path.as_c_str() == source_path)
+        }) {
+            watch.files = files;
+        }
+    }
+
+    // Stops watching [directory] (with its subdirectories) of xbase at
+    // [source_path], or the entire xbase if [directory] is None. Files which
+    // were there are reported to [changes] as removed.
+    fn unwatch(
+        &mut self,
+        source_path: &CStr,
+        directory: Option<&[u8]>,
+        mut changes: Option<&mut Vec<XBaseChange>>,
+    ) {
+        let is_affected = |watch: &XBaseWatch| {
+            watch.source_path.as_c_str() == source_path
+                && directory.is_none_or(|directory| {
+                    watch.directory.starts_with(directory)
+                        && (watch.directory.len() == directory.len()
+                            || watch.directory[directory.len()] == b'\\')
+                })
+        };
+
+        let fd = self.inotify.as_raw_fd();
+        self.watches.retain(|&wd, watches| {
+            watches.retain(|watch| {
+                if !is_affected(watch) {
+                    return true;
+                }
+
+                if let Some(changes) = changes.as_deref_mut() {
+                    for name in &watch.files {
+                        changes.push(XBaseChange {
+                            file_path: CString::new(xbase_watch_join(&watch.directory, name))
+                                .expect("no null characters"),
+                            source_path: watch.source_path.clone(),
+                            kind: XBaseChangeKind::Removed,
+                        });
+                    }
+                }
+                false
+            });
+            if !watches.is_empty() {
+                return true;
+            }
+
+            unsafe { inotify_rm_watch(fd, wd) };
+            false
+        });
+    }
+
+    fn read_changes(&mut self) -> Vec<XBaseChange> {
+        let mut changes = Vec::new();
+
+        let header_size = mem::size_of::<inotify_event>();
+        let mut buffer = [0u8; 4096];
+        loop {
+            let size = match self.inotify.read(&mut buffer) {
+                Ok(size) => size,
+                Err(err) if err.kind() == io::ErrorKind::WouldBlock => break,
+                Err(err) => {
+                    debug_print(format_args!("xbase: cannot read changes: {}", err));
+                    break;
+                }
+            };
+
+            let mut offset = 0;
+            while offset + header_size <= size {
+                let event = unsafe {
+                    ptr::read_unaligned(buffer[offset..].as_ptr() as *const inotify_event)
+                };
+                let name_start = offset + header_size;
+                let name = &buffer[name_start..name_start + event.len as usize];
+                let name_length = name.iter().position(|&ch| ch == 0).unwrap_or(name.len());
+                offset = name_start + event.len as usize;
+
+                self.handle_event(&event, &name[..name_length], &mut changes);
+            }
+        }
+
+        changes
+    }
+
+    fn handle_event(&mut self, event: &inotify_event, name: &[u8], changes: &mut Vec<XBaseChange>) {
+        if event.mask & IN_Q_OVERFLOW != 0 {
+            debug_print(format_args!(
+                "xbase: too many changes at once, some of them are lost"
+            ));
+            rust_compat_clear_path_cache();
+            return;
+        }
+
+        // The directory is removed (or no longer watched).
+        if event.mask & IN_IGNORED != 0 {
+            self.watches.remove(&event.wd);
+            return;
+        }
+
+        let Some(watches) = self.watches.get_mut(&event.wd) else {
+            return;
+        };
+
+        if event.mask & IN_ISDIR == 0 {
+            for watch in watches.iter_mut() {
+                if event.mask & (IN_CREATE | IN_CLOSE_WRITE | IN_MOVED_TO) != 0 {
+                    watch.files.insert(name.to_vec());
+                } else if event.mask & (IN_DELETE | IN_MOVED_FROM) != 0 {
+                    watch.files.remove(name);
+                }
+            }
+        }
+
+        let targets = watches
+            .iter()
+            .map(|watch| {
+                (
+                    watch.source_path.clone(),
+                    xbase_watch_join(&watch.directory, name),
+                    watch.native_path.join(OsStr::from_bytes(name)),
+                )
+            })
+            .collect::<Vec<_>>();
+
+        for (source_path, file_path, native_path) in targets {
+            // Changed entries can affect case-insensitive resolution of paths
+            // (see [compat_path_changed]).
+            compat_path_changed(&native_path);
+
+            if event.mask & IN_ISDIR != 0 {
+                if event.mask & (IN_CREATE | IN_MOVED_TO) != 0 {
+                    self.watch(&source_path, &native_path, file_path, Some(changes));
+                } else if event.mask & IN_MOVED_FROM != 0 {
+                    // The directory keeps its watches when moved, so they are
+                    // removed explicitly (removed directories are handled with
+                    // `IN_IGNORED`). Its files are no longer in xbase.
+                    self.unwatch(&source_path, Some(&file_path), Some(changes));
+                }
+                continue;
+            }
+
+            let kind = if event.mask & (IN_CLOSE_WRITE | IN_MOVED_TO) != 0 {
+                XBaseChangeKind::Modified
+            } else if event.mask & (IN_DELETE | IN_MOVED_FROM) != 0 {
+                XBaseChangeKind::Removed
+            } else {
+                continue;
+            };
+
+            changes.push(XBaseChange {
+                file_path: CString::new(file_path).expect("no null characters"),
+                source_path,
+                kind,
+            });
+        }
+    }
+}
+
+// Appends [name] to relative [directory] path.
+#[cfg(target_os = "linux")]
+fn xbase_watch_join(directory: &[u8], name: &[u8]) -> Vec<u8> {
+    let mut path = directory.to_vec();
+    if !path.is_empty() {
+        path.push(b'\\');
+    }
+    path.extend_from_slice(name);
+    path
+}
+
+#[cfg(not(target_os = "linux"))]
+pub(crate) struct XBaseWatcher;
+
+#[cfg(not(target_os = "linux"))]
+impl XBaseWatcher {
+    pub(crate) fn new() -> io::Result<Self> {
+        Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "watching xbases is only supported on Linux",
+        ))
+    }
+
+    pub(crate) fn poll(&mut self, _vfs: &Vfs) -> Vec<XBaseChange> {
+        Vec::new()
+    }
+
+    pub(crate) fn sync(&mut self, _vfs: &Vfs) {}
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::{cstring, temp_path};
+    use std::fs;
+
+    fn changes(vfs: &Vfs) -> Vec<(String, XBaseChangeKind)> {
+        let mut changes = vfs
+            .poll_changes()
+            .into_iter()
+            .map(|change| (change.file_path.to_string_lossy().into_owned(), change.kind))
+            .collect::<Vec<_>>();
+        changes.sort_by(|a, b| a.0.cmp(&b.0));
+        changes
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_xbase_watcher() {
+        let data = temp_path("data");
+        fs::create_dir_all(data.join("art")).expect("");
+        fs::write(data.join("art/a.frm"), b"a").expect("");
+
+        let vfs = Vfs::new();
+        vfs.open_xbase(&cstring(data.to_str().expect("")))
+            .expect("");
+
+        // Disabled by default.
+        fs::write(data.join("art/a.frm"), b"b").expect("");
+        assert_eq!(changes(&vfs), []);
+
+        vfs.set_watching(true).expect("");
+        fs::write(data.join("art/a.frm"), b"c").expect("");
+        fs::write(data.join("art/a.frm"), b"d").expect("");
+        fs::write(data.join("readme.txt"), b"readme").expect("");
+        assert_eq!(
+            changes(&vfs),
+            [
+                ("art\\a.frm".to_string(), XBaseChangeKind::Modified),
+                ("readme.txt".to_string(), XBaseChangeKind::Modified),
+            ]
+        );
+        assert_eq!(changes(&vfs), []);
+
+        // New directories are watched too.
+        fs::create_dir_all(data.join("text/english")).expect("");
+        fs::write(data.join("text/english/misc.msg"), b"{100}{}{Hi}").expect("");
+        fs::remove_file(data.join("readme.txt")).expect("");
+        assert_eq!(
+            changes(&vfs),
+            [
+                ("readme.txt".to_string(), XBaseChangeKind::Removed),
+                (
+                    "text\\english\\misc.msg".to_string(),
+                    XBaseChangeKind::Modified
+                ),
+            ]
+        );
+        fs::write(data.join("text/english/misc.msg"), b"{100}{}{Hello}").expect("");
+        assert_eq!(
+            changes(&vfs),
+            [(
+                "text\\english\\misc.msg".to_string(),
+                XBaseChangeKind::Modified
+            )]
+        );
+
+        // Changed files are visible to case-insensitive path resolution.
+        let resolve = |name: &str| unsafe {
+            compat_native_path(cstring(data.join(name).to_str().expect("")).as_ptr())
+        };
+        assert_eq!(resolve("ART/B.FRM"), data.join("art/B.FRM"));
+        fs::write(data.join("art/b.frm"), b"b").expect("");
+        assert_eq!(
+            changes(&vfs),
+            [("art\\b.frm".to_string(), XBaseChangeKind::Modified)]
+        );
+        assert_eq!(resolve("ART/B.FRM"), data.join("art/b.frm"));
+
+        // Moved directories are reported with new paths, and files at old
+        // paths are removed.
+        fs::rename(data.join("text"), data.join("moved")).expect("");
+        fs::write(data.join("moved/english/misc.msg"), b"{100}{}{Bye}").expect("");
+        assert_eq!(
+            changes(&vfs),
+            [
+                (
+                    "moved\\english\\misc.msg".to_string(),
+                    XBaseChangeKind::Modified
+                ),
+                (
+                    "text\\english\\misc.msg".to_string(),
+                    XBaseChangeKind::Removed
+                ),
+            ]
+        );
+
+        // Also when moved out of xbase.
+        let outside = temp_path("outside");
+        fs::rename(data.join("moved"), &outside).expect("");
+        assert_eq!(
+            changes(&vfs),
+            [(
+                "moved\\english\\misc.msg".to_string(),
+                XBaseChangeKind::Removed
+            )]
+        );
+        fs::remove_dir_all(&outside).expect("");
+
+        // Closed xbases are no longer watched.
+        vfs.close_all();
+        assert_eq!(changes(&vfs), []);
+        fs::write(data.join("art/a.frm"), b"e").expect("");
+        assert_eq!(changes(&vfs), []);
+
+        vfs.set_watching(false).expect("");
+        fs::remove_dir_all(&data).expect("");
+    }
+}
@@ -1,93 +1,108 @@
+use crate::debug_print::debug_print;
 use crate::dfile::{
-    dbase_find_close, dbase_find_first_entry, dbase_find_next_entry, dbase_open, dfile_eof,
-    dfile_get_size, dfile_print_formatted_args, dfile_read, dfile_read_char, dfile_read_string,
-    dfile_remove_node, dfile_rewind, dfile_seek, dfile_tell, dfile_write, dfile_write_char,
-    dfile_write_string, rust_dfile_open, DBase, DFile, DFileFindData,
+    dfile_eof, dfile_get_size, dfile_print_formatted_args, dfile_read, dfile_read_char,
+    dfile_read_string, dfile_rewind, dfile_seek, dfile_tell, dfile_write, dfile_write_char,
+    dfile_write_string, DFile,
 };
-use crate::file_find::{
-    file_find_close, file_find_first, file_find_get_name, file_find_is_directory, file_find_next,
-    DirectoryFileFindData,
+use crate::inflater::{Inflater, INFLATER_WINDOW_BITS_RAW};
+use crate::xbase_backend::{
+    xbase_enumerate_directory, xbase_open_backend, XBaseBackend, XBaseDirectory,
 };
+use crate::vfs::Vfs;
+use crate::xbase_overlay::xfile_is_write_mode;
+use crate::xfile_memory::{
+    memory_file_eof, memory_file_get_size, memory_file_read, memory_file_read_char,
+    memory_file_read_string, memory_file_rewind, memory_file_seek, memory_file_tell,
+    memory_file_write, memory_file_write_char, memory_file_write_string, MemoryFile,
+};
+use crate::xfile_trace::{xfile_trace, XFileTraceResult, XFileTraceSource};
 use crate::platform_compat::{
-    compat_gzgets, compat_gzopen, rust_compat_fgets, rust_compat_fopen, rust_compat_makepath,
-    rust_compat_mkdir, rust_compat_splitpath, rust_compat_strdup, rust_compat_stricmp,
+    compat_gzgets, compat_gzopen, compat_native_path, compat_path_changed, rust_compat_fgets,
+    rust_compat_fopen, rust_compat_splitpath, rust_compat_strdup, rust_compat_stricmp,
     compat_windows_path_to_native, rust_get_file_size, COMPAT_MAX_DIR, COMPAT_MAX_DRIVE,
-    COMPAT_MAX_EXT, COMPAT_MAX_FNAME, COMPAT_MAX_PATH,
 };
-#[cfg(not(target_family = "windows"))]
-use libc::snprintf;
 use libc::{
-    atexit, c_char, c_long, c_uint, chdir, fclose, feof, fgetc, fputc, fputs, fread, free, fseek,
-    ftell, fwrite, getcwd, memset, realloc, rewind, size_t, strcmp, strcpy, strtok, FILE,
+    atexit, c_char, c_long, c_uint, fclose, feof, fgetc, fputc, fputs, fread, free, fseek,
+    ftell, fwrite, memset, realloc, rewind, size_t, strtok, EOF, FILE, SEEK_CUR, SEEK_SET,
 };
 use libz_sys::{
     gzFile, gzclose, gzeof, gzgetc, gzputc, gzputs, gzread, gzrewind, gzseek, gztell, gzwrite,
     voidp, voidpc, z_off_t,
 };
+use parking_lot::Mutex;
 use spin::RwLock;
-use std::cell::RefCell;
-use std::ffi::{c_int, c_void, CString};
+use std::ffi::{c_int, c_void, CStr, CString};
+use std::fs;
+use std::io;
 use std::mem;
 use std::ptr::{null, null_mut};
-use std::rc::Rc;
+use std::slice;
 use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
 use vsprintf::vsprintf;
 
-enum XFileType {
+pub enum XFileType {
     File(*mut FILE),
-    DFile(Rc<RefCell<DFile>>),
-    GZFile(gzFile),
+    DFile(DFile),
+    GZFile(gzFile, XFileGzipSize),
+    Memory(MemoryFile),
+}
+
+// Uncompressed size of gzipped stream, which is only determined when it's
+// requested with [rust_xfile_get_size].
+pub enum XFileGzipSize {
+    // The stream is opened for reading the file at this path, and the size is
+    // not determined yet.
+    Pending(CString),
+
+    // The stream is opened for writing, so its size is determined by
+    // decompressing it every time.
+    Unknown,
+
+    Known(c_long),
 }
 
+// SAFETY: Plain and gzipped streams are exclusively owned, and neither stdio
+// nor zlib ties them to the thread which opened them. Borrowed buffers of
+// memory streams are only read, and are guaranteed to outlive the stream.
+unsafe impl Send for XFileType {}
+
 pub struct XFile {
-    file: XFileType,
+    // The state of this particular stream, so that streams can be used from
+    // any thread (but one at a time).
+    pub(crate) file: Mutex<XFileType>,
 }
 
-impl Default for XFile {
-    fn default() -> Self {
+impl XFile {
+    pub(crate) fn new(file: XFileType) -> Self {
         Self {
-            file: XFileType::File(null_mut()),
+            file: Mutex::new(file),
         }
     }
 }
 
+impl Default for XFile {
+    fn default() -> Self {
+        Self::new(XFileType::File(null_mut()))
+    }
+}
+
 // A universal database of files.
 pub struct XBase {
     // The path to directory or .DAT file that this xbase represents.
-    path: Option<CString>,
-
-    // The [DBase] instance that this xbase represents.
-    dbase: Option<Rc<RefCell<DBase>>>,
+    pub(crate) path: CString,
 
-    // A flag used to denote that this xbase represents .DAT file (true), or
-    // a directory (false).
-    //
-    // NOTE: Original type is 1 byte, likely unsigned char.
-    is_dbase: bool,
+    // The source of files (directory, .DAT file, or custom source registered
+    // with [xbase_register_backend_factory]).
+    pub(crate) backend: Box<dyn XBaseBackend>,
 
     // Next [XBase] in linked list.
-    next: Option<Arc<RwLock<XBase>>>,
-}
-
-impl Default for XBase {
-    fn default() -> Self {
-        Self {
-            path: None,
-            dbase: None,
-            is_dbase: false,
-            next: None,
-        }
-    }
+    pub(crate) next: Option<Arc<RwLock<XBase>>>,
 }
 
-unsafe impl Send for XBase {}
-
-unsafe impl Sync for XBase {}
-
 impl XBase {
     fn get_path_cstr(&self) -> *const c_char {
-        self.path.as_ref().map(|x| x.as_ptr()).unwrap_or(null())
+        self.path.as_ptr()
     }
 }
 
@@ -97,15 +112,15 @@ pub struct XList {
     pub file_names: *mut *mut c_char,
 }
 
-#[derive(PartialEq)]
-enum XFileEnumerationEntryType {
+#[derive(Clone, Copy, PartialEq)]
+pub enum XFileEnumerationEntryType {
     XfileEnumerationEntryTypeFile = 0,
     XfileEnumerationEntryTypeDirectory = 1,
     XfileEnumerationEntryTypeDfile = 2,
 }
 
 struct XListEnumerationContext {
-    name: [c_char; COMPAT_MAX_PATH],
+    name: CString,
     _type: XFileEnumerationEntryType,
     xlist: *mut XList,
 }
@@ -113,32 +128,15 @@ struct XListEnumerationContext {
 impl Default for XListEnumerationContext {
     fn default() -> Self {
         Self {
-            name: [0 as c_char; COMPAT_MAX_PATH],
+            name: CString::default(),
             _type: XFileEnumerationEntryType::XfileEnumerationEntryTypeFile,
             xlist: null_mut(),
         }
     }
 }
 
-// 0x6B24D0
-static G_X_BASE_HEAD: RwLock<Option<Arc<RwLock<XBase>>>> = RwLock::new(None);
 static G_X_BASE_EXIT_HANDLER_REGISTERED: AtomicBool = AtomicBool::new(false);
 
-#[cfg(target_family = "windows")]
-extern "C" {
-    fn snprintf(s: *mut c_char, n: size_t, format: *const c_char, ...) -> c_int;
-}
-
-pub fn get_g_xbase_head_rc() -> Option<Arc<RwLock<XBase>>> {
-    let read_binding = G_X_BASE_HEAD.read();
-    read_binding.clone()
-}
-
-pub fn set_g_xbase_head(value: Option<Arc<RwLock<XBase>>>) {
-    let mut lock = G_X_BASE_HEAD.write();
-    *lock = value;
-}
-
 pub fn get_g_xbase_exit_handler_registered() -> bool {
     G_X_BASE_EXIT_HANDLER_REGISTERED.load(Ordering::Relaxed)
 }
@@ -153,13 +151,12 @@ pub unsafe extern "C" fn rust_xfile_close(stream: *mut XFile) -> c_int {
 
     let stream = Box::from_raw(stream);
 
-    let rc = match (*stream).file {
-        XFileType::DFile(file) => dfile_remove_node(&file.borrow()),
-        XFileType::GZFile(file) => gzclose(file),
+    match stream.file.into_inner() {
+        // [DFile] is closed (and memory buffer is released) when it's dropped.
+        XFileType::DFile(_) | XFileType::Memory(_) => 0,
+        XFileType::GZFile(file, _) => gzclose(file),
         XFileType::File(file) => fclose(file),
-    };
-
-    rc
+    }
 }
 
 #[no_mangle]
@@ -170,123 +167,262 @@ pub unsafe extern "C" fn rust_xfile_open(
     assert_ne!(file_path, null()); // "filename", "xfile.c", 162
     assert_ne!(mode, null()); // "mode", "xfile.c", 163
 
-    let mut stream = Box::new(XFile::default());
-
-    // NOTE: Compiled code uses different lengths.
-    let mut drive = [0 as c_char; COMPAT_MAX_DRIVE as usize];
-    let mut dir = [0 as c_char; COMPAT_MAX_DIR as usize];
-    rust_compat_splitpath(
-        file_path,
-        drive.as_mut_ptr(),
-        dir.as_mut_ptr(),
-        null_mut(),
-        null_mut(),
+    let stream = Box::new(XFile::default());
+    let file = xfile_open(
+        Vfs::global(),
+        CStr::from_ptr(file_path),
+        CStr::from_ptr(mode),
     );
+    xfile_into_raw(stream, file)
+}
 
-    let mut path = [0 as c_char; COMPAT_MAX_PATH];
-    let sformat = CString::new("%s").expect("valid string");
-    if drive[0] != '\0' as c_char
-        || dir[0] == '\\' as c_char
-        || dir[0] == '/' as c_char
-        || dir[0] == '.' as c_char
-    {
-        // [filePath] is an absolute path. Attempt to open as plain stream.
-        let file = rust_compat_fopen(file_path, mode);
-        if file == null_mut() {
-            return null_mut();
+// Opens stream over a copy of [size] bytes at [data].
+#[no_mangle]
+pub unsafe extern "C" fn rust_xfile_open_memory(data: *const c_void, size: size_t) -> *mut XFile {
+    let data = match size {
+        0 => Vec::new(),
+        _ => slice::from_raw_parts(data as *const u8, size).to_vec(),
+    };
+    Box::into_raw(Box::new(XFile::new(XFileType::Memory(MemoryFile::new(data)))))
+}
+
+// Opens stream over [size] bytes at [data] without copying them.
+//
+// The buffer must stay valid until the stream is closed. It's never modified,
+// the stream switches to its own copy on first write.
+#[no_mangle]
+pub unsafe extern "C" fn rust_xfile_open_memory_borrowed(
+    data: *const c_void,
+    size: size_t,
+) -> *mut XFile {
+    let file = MemoryFile::from_raw_parts(data as *const u8, size);
+    Box::into_raw(Box::new(XFile::new(XFileType::Memory(file))))
+}
+
+// Returns current content of memory [stream] and stores its size into
+// [size_ptr], or returns NULL if [stream] is not a memory stream.
+//
+// The content is only valid until the next write to the stream.
+#[no_mangle]
+pub unsafe extern "C" fn rust_xfile_get_memory(
+    stream: *const XFile,
+    size_ptr: *mut size_t,
+) -> *const c_void {
+    assert_ne!(stream, null());
+    assert_ne!(size_ptr, null_mut());
+
+    match &*(*stream).file.lock() {
+        XFileType::Memory(file) => {
+            *size_ptr = file.data().len();
+            file.data().as_ptr() as *const c_void
         }
+        _ => null(),
+    }
+}
 
-        (*stream).file = XFileType::File(file);
+// Opens [file_path] from [vfs].
+pub(crate) unsafe fn xfile_open(vfs: &Vfs, file_path: &CStr, mode: &CStr) -> Option<XFileType> {
+    if let Err(err) = vfs.check_path(file_path) {
+        debug_print(format_args!("xfile: {}", err));
+        return None;
+    }
 
-        snprintf(
-            path.as_mut_ptr(),
-            mem::size_of_val(&path),
-            sformat.as_ptr(),
+    if xfile_is_absolute_path_in(vfs, file_path) {
+        // [filePath] is an absolute path. Attempt to open as plain stream.
+        let file = xfile_open_plain(file_path, mode);
+        xfile_trace(
+            vfs,
+            file_path,
+            XFileTraceSource::Absolute,
             file_path,
+            XFileTraceResult::of(&file),
         );
+        file
     } else {
-        // [filePath] is a relative path. Loop thru open xbases and attempt to
-        // open [filePath] from appropriate xbase.
-        let mut optional_curr = get_g_xbase_head_rc();
-        let sformat_sformat = CString::new("%s\\%s").expect("valid string");
-        while let Some(curr) = optional_curr {
-            let curr = curr.read();
-            if curr.is_dbase {
-                // Attempt to open dfile stream from dbase.
-                let optional_dfile =
-                    rust_dfile_open(&curr.dbase.as_ref().expect(""), file_path, mode);
-                if let Some(dfile) = optional_dfile {
-                    (*stream).file = XFileType::DFile(dfile);
-                    snprintf(
-                        path.as_mut_ptr(),
-                        mem::size_of_val(&path),
-                        sformat.as_ptr(),
-                        file_path,
-                    );
-                    break;
-                }
-            } else {
-                // Build path relative to directory-based xbase.
-                snprintf(
-                    path.as_mut_ptr(),
-                    mem::size_of_val(&path),
-                    sformat_sformat.as_ptr(),
-                    curr.get_path_cstr(),
+        // [filePath] is a relative path. When overlay is set, it takes
+        // precedence over xbases, and is the only place where files can be
+        // written to.
+        let overlay = vfs.overlay();
+        let mut file = None;
+        if let Some(overlay) = &overlay {
+            let overlay_path = overlay.directory().path();
+            if xfile_is_write_mode(mode) {
+                let file = overlay.open_for_writing(vfs, file_path, mode);
+                xfile_trace(
+                    vfs,
                     file_path,
+                    XFileTraceSource::Overlay,
+                    overlay_path,
+                    XFileTraceResult::of(&file),
                 );
-
-                // Attempt to open plain stream.
-                let file = rust_compat_fopen(path.as_ptr(), mode);
-                if file != null_mut() {
-                    (*stream).file = XFileType::File(rust_compat_fopen(path.as_ptr(), mode));
-                    break;
-                }
+                return file;
             }
-            optional_curr = curr.next.clone();
-        }
 
-        match (*stream).file {
-            XFileType::File(file) if file == null_mut() => {
-                // File was not opened during the loop above. Attempt to open file
-                // relative to the current working directory.
-                let file = rust_compat_fopen(file_path, mode);
-                if file == null_mut() {
-                    return null_mut();
-                }
-                (*stream).file = XFileType::File(file);
-
-                snprintf(
-                    path.as_mut_ptr(),
-                    mem::size_of_val(&path),
-                    sformat.as_ptr(),
+            if overlay.hides(file_path) {
+                xfile_trace(
+                    vfs,
                     file_path,
+                    XFileTraceSource::Overlay,
+                    overlay_path,
+                    XFileTraceResult::Hidden,
                 );
+                return None;
             }
-            _ => (),
+
+            file = overlay.directory().open(file_path, mode);
+            xfile_trace(
+                vfs,
+                file_path,
+                XFileTraceSource::Overlay,
+                overlay_path,
+                XFileTraceResult::of(&file),
+            );
+        }
+
+        if file.is_none() {
+            file = xfile_open_below_overlay(vfs, file_path, mode);
         }
+
+        file
     }
+}
 
-    match (*stream).file {
-        XFileType::File(file) => {
-            // Opened file is a plain stream, which might be gzipped. In this case
-            // first two bytes will contain magic numbers.
-            let ch1 = fgetc(file);
-            let ch2 = fgetc(file);
-            if ch1 == 0x1F && ch2 == 0x8B {
-                // File is gzipped. Close plain stream and reopen this file as
-                // gzipped stream.
-                fclose(file);
-
-                (*stream).file = XFileType::GZFile(compat_gzopen(path.as_ptr(), mode));
-            } else {
-                // File is not gzipped.
-                rewind(file);
-            }
+// Opens relative [file_path] from anywhere but overlay: xbases, the current
+// working directory (unless sandboxed), or embedded resources.
+pub(crate) unsafe fn xfile_open_below_overlay(
+    vfs: &Vfs,
+    file_path: &CStr,
+    mode: &CStr,
+) -> Option<XFileType> {
+    let mut file = xbase_find(vfs, file_path, mode);
+
+    // If file was not found in xbases, attempt to open file relative to
+    // the current working directory.
+    if file.is_none() && !vfs.is_sandboxed() {
+        file = xfile_open_plain(file_path, mode);
+        let working_directory = CString::new(".").expect("valid string");
+        xfile_trace(
+            vfs,
+            file_path,
+            XFileTraceSource::WorkingDirectory,
+            &working_directory,
+            XFileTraceResult::of(&file),
+        );
+    }
+
+    if file.is_none() {
+        if let Some(embedded) = vfs.embedded() {
+            file = embedded.open(file_path, mode);
+            let embedded_name = CString::new("<embedded>").expect("valid string");
+            xfile_trace(
+                vfs,
+                file_path,
+                XFileTraceSource::Embedded,
+                &embedded_name,
+                XFileTraceResult::of(&file),
+            );
+        }
+    }
+
+    file
+}
+
+// Specifies that [file_path] can be opened by [xfile_open_below_overlay].
+pub(crate) fn xfile_exists_below_overlay(vfs: &Vfs, file_path: &CStr) -> bool {
+    vfs.exists(file_path)
+        || (!vfs.is_sandboxed() && unsafe { compat_native_path(file_path.as_ptr()) }.exists())
+}
+
+// Wraps opened [file] into [stream], or returns NULL if the file was not
+// opened.
+fn xfile_into_raw(mut stream: Box<XFile>, file: Option<XFileType>) -> *mut XFile {
+    match file {
+        Some(file) => {
+            *stream.file.get_mut() = file;
+            Box::into_raw(stream)
+        }
+        None => null_mut(),
+    }
+}
+
+// Reads entire (decompressed) content of [file] and closes it.
+pub(crate) unsafe fn xfile_read_all(file: XFileType) -> Vec<u8> {
+    let stream = Box::into_raw(Box::new(XFile::new(file)));
+
+    let mut data = Vec::new();
+    let mut buffer = vec![0u8; 0x4000];
+    loop {
+        let bytes_read = xfile_read(buffer.as_mut_ptr() as *mut c_void, 1, buffer.len(), stream);
+        if bytes_read == 0 {
+            break;
+        }
+        data.extend_from_slice(&buffer[..bytes_read]);
+    }
+
+    rust_xfile_close(stream);
+
+    data
+}
+
+// Loops thru open xbases and attempts to open [file_path] from appropriate
+// xbase.
+//
+// Every consulted xbase is reported to file resolution trace.
+pub(crate) fn xbase_find(vfs: &Vfs, file_path: &CStr, mode: &CStr) -> Option<XFileType> {
+    let mut optional_curr = vfs.head();
+    while let Some(curr) = optional_curr {
+        let curr = curr.read();
+        let file = curr.backend.open(file_path, mode);
+        let source = match curr.backend.directory() {
+            Some(_) => XFileTraceSource::Directory,
+            None => XFileTraceSource::Archive,
+        };
+        xfile_trace(vfs, file_path, source, &curr.path, XFileTraceResult::of(&file));
+        if file.is_some() {
+            return file;
         }
-        _ => (),
+        optional_curr = curr.next.clone();
     }
 
-    Box::into_raw(stream)
+    None
+}
+
+// Opens plain stream at [file_path].
+//
+// The file might be gzipped, in this case it's opened as gzipped stream.
+pub(crate) fn xfile_open_plain(file_path: &CStr, mode: &CStr) -> Option<XFileType> {
+    unsafe {
+        let file = rust_compat_fopen(file_path.as_ptr(), mode.as_ptr());
+        if file == null_mut() {
+            return None;
+        }
+
+        // Gzipped file contains magic numbers in the first two bytes.
+        let ch1 = fgetc(file);
+        let ch2 = fgetc(file);
+        if ch1 == 0x1F && ch2 == 0x8B {
+            // File is gzipped. Close plain stream and reopen this file as
+            // gzipped stream.
+            fclose(file);
+
+            let file = compat_gzopen(file_path.as_ptr(), mode.as_ptr());
+            if file == null_mut() {
+                return None;
+            }
+
+            let size = match xfile_is_write_mode(mode) {
+                true => XFileGzipSize::Unknown,
+                false => XFileGzipSize::Pending(file_path.to_owned()),
+            };
+
+            Some(XFileType::GZFile(file, size))
+        } else {
+            // File is not gzipped.
+            rewind(file);
+
+            Some(XFileType::File(file))
+        }
+    }
 }
 
 #[no_mangle]
@@ -298,9 +434,9 @@ pub unsafe extern "C" fn rust_xfile_print_formatted_args(
     assert_ne!(stream, null()); // "stream", "xfile.c", 332
     assert_ne!(format, null()); // "format", "xfile.c", 333
 
-    match &(*stream).file {
-        XFileType::DFile(file) => dfile_print_formatted_args(&file.borrow(), format, args),
-        XFileType::GZFile(file) => {
+    match &mut *(*stream).file.lock() {
+        XFileType::DFile(file) => dfile_print_formatted_args(file, format, args),
+        XFileType::GZFile(file, _) => {
             let str = vsprintf(format, args).expect("valid");
             gzwrite(file.clone(), str.as_ptr() as voidpc, str.len() as c_uint)
         }
@@ -308,16 +444,21 @@ pub unsafe extern "C" fn rust_xfile_print_formatted_args(
             let str = vsprintf(format, args).expect("valid");
             fwrite(str.as_ptr() as *const c_void, str.len() as size_t, 1, *file) as c_int
         }
+        XFileType::Memory(file) => {
+            let str = vsprintf(format, args).expect("valid");
+            memory_file_write(str.as_ptr() as *const c_void, 1, str.len(), file) as c_int
+        }
     }
 }
 
 pub unsafe fn xfile_read_char(stream: *const XFile) -> c_int {
     assert_ne!(stream, null()); // "stream", "xfile.c", 354
 
-    match &(*stream).file {
-        XFileType::DFile(file) => dfile_read_char(&mut file.borrow_mut()),
-        XFileType::GZFile(file) => gzgetc(file.clone()),
+    match &mut *(*stream).file.lock() {
+        XFileType::DFile(file) => dfile_read_char(file),
+        XFileType::GZFile(file, _) => gzgetc(file.clone()),
         XFileType::File(file) => fgetc(*file),
+        XFileType::Memory(file) => memory_file_read_char(file),
     }
 }
 
@@ -330,20 +471,22 @@ pub unsafe fn xfile_read_string(
     assert_ne!(size, 0); // "n", "xfile.c", 376
     assert_ne!(stream, null()); // "stream", "xfile.c", 377
 
-    match &(*stream).file {
-        XFileType::DFile(file) => dfile_read_string(string, size, &mut file.borrow_mut()),
-        XFileType::GZFile(file) => compat_gzgets(file.clone(), string, size),
+    match &mut *(*stream).file.lock() {
+        XFileType::DFile(file) => dfile_read_string(string, size, file),
+        XFileType::GZFile(file, _) => compat_gzgets(file.clone(), string, size),
         XFileType::File(file) => rust_compat_fgets(string, size, *file),
+        XFileType::Memory(file) => memory_file_read_string(string, size, file),
     }
 }
 
 pub unsafe fn xfile_write_char(ch: c_int, stream: *const XFile) -> c_int {
     assert_ne!(stream, null()); // "stream", "xfile.c", 399
 
-    match &(*stream).file {
-        XFileType::DFile(file) => dfile_write_char(ch, &file.borrow()),
-        XFileType::GZFile(file) => gzputc(file.clone(), ch),
+    match &mut *(*stream).file.lock() {
+        XFileType::DFile(file) => dfile_write_char(ch, file),
+        XFileType::GZFile(file, _) => gzputc(file.clone(), ch),
         XFileType::File(file) => fputc(ch, *file),
+        XFileType::Memory(file) => memory_file_write_char(ch, file),
     }
 }
 
@@ -355,10 +498,11 @@ pub unsafe extern "C" fn rust_xfile_write_string(
     assert_ne!(string, null()); // "s", "xfile.c", 421
     assert_ne!(stream, null()); // "stream", "xfile.c", 422
 
-    match &(*stream).file {
-        XFileType::DFile(file) => dfile_write_string(string, &file.borrow()),
-        XFileType::GZFile(file) => gzputs(file.clone(), string),
+    match &mut *(*stream).file.lock() {
+        XFileType::DFile(file) => dfile_write_string(string, file),
+        XFileType::GZFile(file, _) => gzputs(file.clone(), string),
         XFileType::File(file) => fputs(string, *file),
+        XFileType::Memory(file) => memory_file_write_string(string, file),
     }
 }
 
@@ -371,12 +515,13 @@ pub unsafe fn xfile_read(
     assert_ne!(ptr, null_mut()); // "ptr", "xfile.c", 421
     assert_ne!(stream, null()); // "stream", "xfile.c", 422
 
-    match &(*stream).file {
-        XFileType::DFile(file) => dfile_read(ptr, size, count, &mut file.borrow_mut()),
-        XFileType::GZFile(file) => {
+    match &mut *(*stream).file.lock() {
+        XFileType::DFile(file) => dfile_read(ptr, size, count, file),
+        XFileType::GZFile(file, _) => {
             gzread(file.clone(), ptr as voidp, (size * count) as c_uint) as size_t
         }
         XFileType::File(file) => fread(ptr, size, count, *file),
+        XFileType::Memory(file) => memory_file_read(ptr, size, count, file),
     }
 }
 
@@ -390,10 +535,11 @@ pub unsafe extern "C" fn rust_xfile_write(
     assert_ne!(ptr, null()); // "ptr", "xfile.c", 504
     assert_ne!(stream, null()); // "stream", "xfile.c", 505
 
-    match &(*stream).file {
-        XFileType::DFile(file) => dfile_write(ptr, size, count, &file.borrow()),
-        XFileType::GZFile(file) => gzwrite(file.clone(), ptr, (size * count) as c_uint) as size_t,
+    match &mut *(*stream).file.lock() {
+        XFileType::DFile(file) => dfile_write(ptr, size, count, file),
+        XFileType::GZFile(file, _) => gzwrite(file.clone(), ptr, (size * count) as c_uint) as size_t,
         XFileType::File(file) => fwrite(ptr, size, count, *file),
+        XFileType::Memory(file) => memory_file_write(ptr, size, count, file),
     }
 }
 
@@ -405,10 +551,11 @@ pub unsafe extern "C" fn rust_xfile_seek(
 ) -> c_int {
     assert_ne!(stream, null()); // "stream", "xfile.c", 547
 
-    match &(*stream).file {
-        XFileType::DFile(file) => dfile_seek(&mut file.borrow_mut(), offset, origin),
-        XFileType::GZFile(file) => gzseek(file.clone(), offset as z_off_t, origin) as c_int,
+    match &mut *(*stream).file.lock() {
+        XFileType::DFile(file) => dfile_seek(file, offset, origin),
+        XFileType::GZFile(file, _) => gzseek(file.clone(), offset as z_off_t, origin) as c_int,
         XFileType::File(file) => fseek(*file, offset, origin),
+        XFileType::Memory(file) => memory_file_seek(file, offset, origin),
     }
 }
 
@@ -416,10 +563,11 @@ pub unsafe extern "C" fn rust_xfile_seek(
 pub unsafe extern "C" fn rust_xfile_tell(stream: *const XFile) -> c_long {
     assert_ne!(stream, null()); // "stream", "xfile.c", 588
 
-    match &(*stream).file {
-        XFileType::DFile(file) => dfile_tell(&file.borrow()),
-        XFileType::GZFile(file) => gztell(file.clone()) as c_long,
+    match &mut *(*stream).file.lock() {
+        XFileType::DFile(file) => dfile_tell(file),
+        XFileType::GZFile(file, _) => gztell(file.clone()) as c_long,
         XFileType::File(file) => ftell(*file),
+        XFileType::Memory(file) => memory_file_tell(file),
     }
 }
 
@@ -427,12 +575,13 @@ pub unsafe extern "C" fn rust_xfile_tell(stream: *const XFile) -> c_long {
 pub unsafe extern "C" fn rust_xfile_rewind(stream: *const XFile) {
     assert_ne!(stream, null()); // "stream", "xfile.c", 608
 
-    match &(*stream).file {
-        XFileType::DFile(file) => dfile_rewind(&mut file.borrow_mut()),
-        XFileType::GZFile(file) => {
+    match &mut *(*stream).file.lock() {
+        XFileType::DFile(file) => dfile_rewind(file),
+        XFileType::GZFile(file, _) => {
             gzrewind(file.clone());
         }
         XFileType::File(file) => rewind(*file),
+        XFileType::Memory(file) => memory_file_rewind(file),
     }
 }
 
@@ -440,10 +589,11 @@ pub unsafe extern "C" fn rust_xfile_rewind(stream: *const XFile) {
 pub unsafe extern "C" fn rust_xfile_eof(stream: *const XFile) -> c_int {
     assert_ne!(stream, null()); // "stream", "xfile.c", 648
 
-    match &(*stream).file {
-        XFileType::DFile(file) => dfile_eof(&file.borrow()),
-        XFileType::GZFile(file) => gzeof(file.clone()),
+    match &mut *(*stream).file.lock() {
+        XFileType::DFile(file) => dfile_eof(file),
+        XFileType::GZFile(file, _) => gzeof(file.clone()),
         XFileType::File(file) => feof(*file),
+        XFileType::Memory(file) => memory_file_eof(file),
     }
 }
 
@@ -451,117 +601,267 @@ pub unsafe extern "C" fn rust_xfile_eof(stream: *const XFile) -> c_int {
 pub unsafe extern "C" fn rust_xfile_get_size(stream: *const XFile) -> c_long {
     assert_ne!(stream, null()); // "stream", "xfile.c", 690
 
-    match &(*stream).file {
-        XFileType::DFile(file) => dfile_get_size(&file.borrow()),
-        XFileType::GZFile(_) => 0,
+    match &mut *(*stream).file.lock() {
+        XFileType::DFile(file) => dfile_get_size(file),
+        XFileType::GZFile(file, size) => xfile_gzip_get_size(*file, size),
         XFileType::File(file) => rust_get_file_size(*file),
+        XFileType::Memory(file) => memory_file_get_size(file),
     }
 }
 
-// Closes all xbases.
-extern "C" fn xbase_close_all() {
-    set_g_xbase_head(None);
+// The size of gzip header without optional fields.
+const GZIP_HEADER_SIZE: usize = 10;
+
+// The size of gzip trailer (CRC32 and ISIZE).
+const GZIP_TRAILER_SIZE: c_long = 8;
+
+// Flags of gzip header (FLG field).
+const GZIP_FLAG_HCRC: u8 = 0x02;
+const GZIP_FLAG_EXTRA: u8 = 0x04;
+const GZIP_FLAG_NAME: u8 = 0x08;
+const GZIP_FLAG_COMMENT: u8 = 0x10;
+const GZIP_FLAG_RESERVED: u8 = 0xE0;
+
+// Returns uncompressed size of gzipped [file], determining it first if it's
+// not known yet.
+unsafe fn xfile_gzip_get_size(file: gzFile, size: &mut XFileGzipSize) -> c_long {
+    if let XFileGzipSize::Pending(path) = size {
+        let value = xfile_gzip_trailer_size(path)
+            .unwrap_or_else(|| xfile_gzip_decompressed_size(file));
+        *size = XFileGzipSize::Known(value);
+    }
+
+    match size {
+        XFileGzipSize::Known(value) => *value,
+        _ => xfile_gzip_decompressed_size(file),
+    }
 }
 
-#[cfg(target_family = "windows")]
-type GetcwdSize = c_int;
+// Returns uncompressed size of gzipped file at [file_path] from ISIZE field of
+// gzip trailer.
+//
+// ISIZE is the size of the last gzip member modulo 2^32, so it's only
+// reliable when the file consists of a single member. To make sure of that,
+// the first member is inflated (without checking CRC) and it should end
+// exactly at the trailer, otherwise `None` is returned.
+unsafe fn xfile_gzip_trailer_size(file_path: &CStr) -> Option<c_long> {
+    let mode = CString::new("rb").expect("valid string");
+    let stream = rust_compat_fopen(file_path.as_ptr(), mode.as_ptr());
+    if stream.is_null() {
+        return None;
+    }
 
-#[cfg(not(target_family = "windows"))]
-type GetcwdSize = size_t;
+    let size = xfile_gzip_read_trailer_size(stream);
+    fclose(stream);
 
-// Recursively creates specified file path.
-pub unsafe fn xbase_make_directory(file_path: *mut c_char) -> c_int {
-    let mut working_directory = [0 as c_char; COMPAT_MAX_PATH];
-    if getcwd(
-        working_directory.as_mut_ptr(),
-        COMPAT_MAX_PATH as GetcwdSize,
-    ) == null_mut()
-    {
-        return -1;
+    size
+}
+
+unsafe fn xfile_gzip_read_trailer_size(stream: *mut FILE) -> Option<c_long> {
+    let file_size = rust_get_file_size(stream);
+
+    let mut header = [0u8; GZIP_HEADER_SIZE];
+    if fread(header.as_mut_ptr() as *mut c_void, 1, header.len(), stream) != header.len() {
+        return None;
     }
 
-    let mut drive = [0 as c_char; COMPAT_MAX_DRIVE as usize];
-    let mut dir = [0 as c_char; COMPAT_MAX_DIR as usize];
-    rust_compat_splitpath(
-        file_path,
-        drive.as_mut_ptr(),
-        dir.as_mut_ptr(),
-        null_mut(),
-        null_mut(),
-    );
+    let flags = header[3];
+    if header[..3] != [0x1F, 0x8B, 0x08] || flags & GZIP_FLAG_RESERVED != 0 {
+        return None;
+    }
 
-    let mut path = [0 as c_char; COMPAT_MAX_PATH];
-    let sformat_sformat = CString::new("%s\\%s").expect("valid string");
+    if flags & GZIP_FLAG_EXTRA != 0 {
+        let extra_size = fgetc(stream) | (fgetc(stream) << 8);
+        if extra_size < 0 || fseek(stream, extra_size as c_long, SEEK_CUR) != 0 {
+            return None;
+        }
+    }
 
-    if drive[0] != '\0' as c_char
-        || dir[0] == '\\' as c_char
-        || dir[0] == '/' as c_char
-        || dir[0] == '.' as c_char
-    {
-        // [filePath] is an absolute path.
-        strcpy(path.as_mut_ptr(), file_path);
-    } else {
-        // Find first directory-based xbase.
-        let mut optional_curr = get_g_xbase_head_rc();
-        while let Some(curr) = optional_curr.clone() {
-            let curr = curr.read();
-            if !curr.is_dbase {
-                snprintf(
-                    path.as_mut_ptr(),
-                    mem::size_of_val(&path),
-                    sformat_sformat.as_ptr(),
-                    curr.get_path_cstr(),
-                    file_path,
-                );
-                break;
+    for flag in [GZIP_FLAG_NAME, GZIP_FLAG_COMMENT] {
+        if flags & flag != 0 {
+            // Zero-terminated string.
+            loop {
+                match fgetc(stream) {
+                    0 => break,
+                    EOF => return None,
+                    _ => {}
+                }
             }
-            optional_curr = curr.next.clone();
         }
+    }
 
-        if optional_curr.is_none() {
-            // Either there are no directory-based xbase, or there are no open
-            // xbases at all - resolve path against current working directory.
-            snprintf(
-                path.as_mut_ptr(),
-                mem::size_of_val(&path),
-                sformat_sformat.as_ptr(),
-                working_directory,
-                file_path,
-            );
-        }
+    if flags & GZIP_FLAG_HCRC != 0 && fseek(stream, 2, SEEK_CUR) != 0 {
+        return None;
     }
 
-    let mut pch = path.as_mut_ptr();
+    let data_offset = ftell(stream);
+    let data_size = file_size - data_offset - GZIP_TRAILER_SIZE;
+    if data_offset < 0 || data_size < 0 || fseek(stream, file_size - 4, SEEK_SET) != 0 {
+        return None;
+    }
 
-    if *pch == '\\' as c_char || *pch == '/' as c_char {
-        pch = pch.offset(1);
+    let mut trailer = [0u8; 4];
+    if fread(trailer.as_mut_ptr() as *mut c_void, 1, trailer.len(), stream) != trailer.len() {
+        return None;
     }
 
-    while *pch != '\0' as c_char {
-        if *pch == '\\' as c_char || *pch == '/' as c_char {
-            let temp = *pch;
-            *pch = '\0' as c_char;
+    if fseek(stream, data_offset, SEEK_SET) != 0 {
+        return None;
+    }
 
-            if chdir(path.as_ptr()) != 0 {
-                if rust_compat_mkdir(path.as_ptr()) != 0 {
-                    chdir(working_directory.as_mut_ptr());
-                    return -1;
+    // Inflate compressed data of the first member, which should take
+    // everything up to the trailer.
+    let mut inflater = Inflater::new(INFLATER_WINDOW_BITS_RAW)?;
+    let mut input = vec![0u8; 0x4000];
+    let mut output = vec![0u8; 0x4000];
+    let mut remaining = data_size as usize;
+    let mut size: u64 = 0;
+    while remaining > 0 {
+        let chunk_size = remaining.min(input.len());
+        if fread(input.as_mut_ptr() as *mut c_void, 1, chunk_size, stream) != chunk_size {
+            return None;
+        }
+        remaining -= chunk_size;
+
+        let mut offset = 0;
+        while offset < chunk_size {
+            let progress = inflater.inflate(&input[offset..chunk_size], &mut output).ok()?;
+            offset += progress.consumed;
+            size += progress.produced as u64;
+
+            if progress.finished {
+                let is_last_member = offset == chunk_size && remaining == 0;
+                if !is_last_member || size as u32 != u32::from_le_bytes(trailer) {
+                    return None;
                 }
-            } else {
-                chdir(working_directory.as_ptr());
+                return c_long::try_from(size).ok();
+            }
+
+            if progress.consumed == 0 && progress.produced == 0 {
+                return None;
             }
+        }
+    }
+
+    None
+}
 
-            *pch = temp;
+// Returns uncompressed size of gzipped [file] by decompressing it entirely.
+//
+// The position of [file] is preserved.
+unsafe fn xfile_gzip_decompressed_size(file: gzFile) -> c_long {
+    let position = gztell(file);
+    if position == -1 || gzrewind(file) == -1 {
+        return 0;
+    }
+
+    let mut size: c_long = 0;
+    let mut buffer = [0u8; 0x4000];
+    loop {
+        let bytes_read = gzread(file, buffer.as_mut_ptr() as voidp, buffer.len() as c_uint);
+        if bytes_read <= 0 {
+            break;
         }
-        pch = pch.offset(1);
+        size += bytes_read as c_long;
     }
 
-    // Last path component.
-    rust_compat_mkdir(path.as_ptr());
+    gzseek(file, position, SEEK_SET);
 
-    chdir(working_directory.as_ptr());
+    size
+}
 
-    0
+// Closes all xbases.
+extern "C" fn xbase_close_all() {
+    Vfs::global().close_all();
+}
+
+// Recursively creates directory at [file_path].
+//
+// Relative paths are resolved against overlay directory (if any), which is
+// the only writable one, or against the first directory-based xbase, or
+// against the current working directory.
+fn xbase_make_directory(vfs: &Vfs, file_path: &CStr) -> io::Result<()> {
+    vfs.check_path(file_path)?;
+
+    let path = if xfile_is_absolute_path_in(vfs, file_path) {
+        file_path.to_owned()
+    } else {
+        let overlay = vfs.overlay();
+        let mut directory = overlay
+            .as_ref()
+            .map(|overlay| overlay.directory().path().to_owned());
+
+        let mut optional_curr = vfs.head();
+        while directory.is_none() {
+            let Some(curr) = optional_curr else {
+                break;
+            };
+            let curr = curr.read();
+            directory = curr.backend.directory().map(CStr::to_owned);
+            optional_curr = curr.next.clone();
+        }
+
+        match directory {
+            Some(directory) => XBaseDirectory::new(directory).join(file_path),
+            None => file_path.to_owned(),
+        }
+    };
+
+    let native_path = unsafe { compat_native_path(path.as_ptr()) };
+    fs::create_dir_all(&native_path)?;
+    compat_path_changed(&native_path);
+    Ok(())
+}
+
+// Specifies that [file_path] is absolute in terms of [vfs].
+//
+// In sandbox mode every path which passes [Vfs::check_path] is looked up in
+// xbases, including the ones starting with `.` (which are otherwise resolved
+// against the current working directory).
+fn xfile_is_absolute_path_in(vfs: &Vfs, file_path: &CStr) -> bool {
+    !vfs.is_sandboxed() && xfile_is_absolute_path(file_path)
+}
+
+// Specifies that [file_path] is absolute (in terms of xbases), that is it's
+// not looked up in xbases.
+fn xfile_is_absolute_path(file_path: &CStr) -> bool {
+    let mut drive = [0 as c_char; COMPAT_MAX_DRIVE as usize];
+    let mut dir = [0 as c_char; COMPAT_MAX_DIR as usize];
+    unsafe {
+        rust_compat_splitpath(
+            file_path.as_ptr(),
+            drive.as_mut_ptr(),
+            dir.as_mut_ptr(),
+            null_mut(),
+            null_mut(),
+        );
+    }
+
+    drive[0] != '\0' as c_char
+        || dir[0] == '\\' as c_char
+        || dir[0] == '/' as c_char
+        || dir[0] == '.' as c_char
+}
+
+// Specifies that [file_path] is not confined to xbases (or current working
+// directory), that is it's rooted, or goes above its root with `..`.
+pub(crate) fn xfile_escapes_root(file_path: &CStr) -> bool {
+    let bytes = file_path.to_bytes();
+    if bytes.first().is_some_and(|&ch| ch == b'\\' || ch == b'/') || bytes.get(1) == Some(&b':') {
+        return true;
+    }
+
+    let mut depth = 0;
+    for component in bytes.split(|&ch| ch == b'\\' || ch == b'/') {
+        match component {
+            b"" | b"." => {}
+            b".." if depth == 0 => return true,
+            b".." => depth -= 1,
+            _ => depth += 1,
+        }
+    }
+
+    false
 }
 
 pub unsafe fn xbase_open(path: *mut c_char) -> bool {
@@ -574,11 +874,16 @@ pub unsafe fn xbase_open(path: *mut c_char) -> bool {
         set_g_xbase_exit_handler_registered(true);
     }
 
-    let mut optional_curr = get_g_xbase_head_rc();
+    vfs_open_xbase(Vfs::global(), CStr::from_ptr(path)).is_ok()
+}
+
+// Opens xbase at [path] in [vfs], see [Vfs::open_xbase].
+pub(crate) unsafe fn vfs_open_xbase(vfs: &Vfs, path: &CStr) -> io::Result<()> {
+    let mut optional_curr = vfs.head();
     let mut optional_prev = None;
     while let Some(curr) = optional_curr.clone() {
         let curr = curr.read();
-        if rust_compat_stricmp(path, curr.get_path_cstr()) == 0 {
+        if rust_compat_stricmp(path.as_ptr(), curr.get_path_cstr()) == 0 {
             break;
         }
 
@@ -590,59 +895,45 @@ pub unsafe fn xbase_open(path: *mut c_char) -> bool {
         if let Some(prev) = optional_prev {
             // Move found xbase to the top.
             prev.write().next = curr.read().next.clone();
-            curr.write().next = get_g_xbase_head_rc();
-            set_g_xbase_head(Some(curr));
+            curr.write().next = vfs.head();
+            vfs.set_head(Some(curr));
         }
-        return true;
+        return Ok(());
     }
 
-    let mut xbase = XBase::default();
+    let xbase_path = path.to_owned();
 
-    xbase.path = Some(CString::from_raw(rust_compat_strdup(path)));
-    if xbase.path == None {
-        return false;
+    if let Some(backend) = xbase_open_backend(&xbase_path) {
+        xbase_push(vfs, xbase_path, backend);
+        return Ok(());
     }
 
-    let dbase = dbase_open(path);
-    if dbase.is_some() {
-        xbase.is_dbase = true;
-        xbase.dbase = dbase;
-        xbase.next = get_g_xbase_head_rc();
-        let xbase = Some(Arc::new(RwLock::new(xbase)));
-        set_g_xbase_head(xbase);
-        return true;
+    // Neither .DAT file nor custom source, so it's a directory, which is
+    // created if needed.
+    if !compat_native_path(path.as_ptr()).is_dir() {
+        xbase_make_directory(vfs, path)?;
     }
 
-    let mut working_directory = [0 as c_char; COMPAT_MAX_PATH];
-    if getcwd(
-        working_directory.as_mut_ptr(),
-        COMPAT_MAX_PATH as GetcwdSize,
-    ) == null_mut()
-    {
-        // FIXME: Leaking xbase and path.
-        return false;
-    }
-
-    if chdir(path) == 0 {
-        chdir(working_directory.as_ptr());
-        xbase.next = get_g_xbase_head_rc();
-        let xbase = Some(Arc::new(RwLock::new(xbase)));
-        set_g_xbase_head(xbase);
-        return true;
-    }
-
-    if xbase_make_directory(path) != 0 {
-        // FIXME: Leaking xbase and path.
-        return false;
-    }
+    xbase_push(vfs, xbase_path.clone(), Box::new(XBaseDirectory::new(xbase_path)));
 
-    chdir(working_directory.as_ptr());
+    Ok(())
+}
 
-    xbase.next = get_g_xbase_head_rc();
-    let xbase = Some(Arc::new(RwLock::new(xbase)));
-    set_g_xbase_head(xbase);
+// Adds xbase at the top of search order of [vfs].
+pub(crate) fn xbase_push(vfs: &Vfs, path: CString, backend: Box<dyn XBaseBackend>) {
+    let xbase = XBase {
+        path,
+        backend,
+        next: vfs.head(),
+    };
+    vfs.set_head(Some(Arc::new(RwLock::new(xbase))));
+}
 
-    true
+// Enables or disables sandbox mode of the global [Vfs], see
+// [Vfs::set_sandboxed].
+#[no_mangle]
+pub extern "C" fn rust_xbase_set_sandboxed(enabled: bool) {
+    Vfs::global().set_sandboxed(enabled);
 }
 
 // Closes all open xbases and opens a set of xbases specified by [paths].
@@ -683,193 +974,82 @@ unsafe fn xlist_enumerate(
         )
     ); // "enumfunc", "xfile.c", 846
 
-    let mut directory_file_find_data = DirectoryFileFindData::default();
     let mut context = XListEnumerationContext::default();
 
     context.xlist = xlist;
 
-    let mut native_pattern = [0 as c_char; COMPAT_MAX_PATH];
-    strcpy(native_pattern.as_mut_ptr(), pattern);
-    compat_windows_path_to_native(native_pattern.as_mut_ptr());
+    let mut visitor = |name: &CStr, entry_type| {
+        context.name = name.to_owned();
+        context._type = entry_type;
+        handler(&context)
+    };
 
-    let mut drive = [0 as c_char; COMPAT_MAX_DRIVE as usize];
-    let mut dir = [0 as c_char; COMPAT_MAX_DIR as usize];
-    let mut file_name = [0 as c_char; COMPAT_MAX_FNAME as usize];
-    let mut extension = [0 as c_char; COMPAT_MAX_EXT as usize];
-    rust_compat_splitpath(
-        native_pattern.as_mut_ptr(),
-        drive.as_mut_ptr(),
-        dir.as_mut_ptr(),
-        file_name.as_mut_ptr(),
-        extension.as_mut_ptr(),
-    );
-    if drive[0] != '\0' as c_char
-        || dir[0] == '\\' as c_char
-        || dir[0] == '/' as c_char
-        || dir[0] == '.' as c_char
-    {
-        if file_find_first(native_pattern.as_ptr(), &mut directory_file_find_data) {
-            loop {
-                let is_directory = file_find_is_directory(&directory_file_find_data);
-                let entry_name = file_find_get_name(&directory_file_find_data);
-
-                if is_directory {
-                    let dot_dot = CString::new("..").expect("valid string");
-                    let dot = CString::new(".").expect("valid string");
-                    if strcmp(entry_name, dot_dot.as_ptr()) == 0
-                        || strcmp(entry_name, dot.as_ptr()) == 0
-                    {
-                        continue;
-                    }
-
-                    context._type = XFileEnumerationEntryType::XfileEnumerationEntryTypeDirectory;
-                } else {
-                    context._type = XFileEnumerationEntryType::XfileEnumerationEntryTypeFile;
-                }
+    xfile_enumerate(Vfs::global(), CStr::from_ptr(pattern), &mut visitor);
 
-                rust_compat_makepath(
-                    context.name.as_mut_ptr(),
-                    drive.as_ptr(),
-                    dir.as_ptr(),
-                    entry_name,
-                    null(),
-                );
+    true
+}
 
-                if !handler(&context) {
-                    break;
-                }
+// Calls [visitor] for every file in [vfs] matching [pattern].
+//
+// Returns false if [visitor] stopped enumeration.
+pub(crate) unsafe fn xfile_enumerate(
+    vfs: &Vfs,
+    pattern: &CStr,
+    visitor: &mut dyn FnMut(&CStr, XFileEnumerationEntryType) -> bool,
+) -> bool {
+    if let Err(err) = vfs.check_path(pattern) {
+        debug_print(format_args!("xfile: {}", err));
+        return false;
+    }
 
-                if !file_find_next(&mut directory_file_find_data) {
-                    break;
-                }
+    let overlay = vfs.overlay();
+    let mut visitor = |name: &CStr, entry_type| {
+        if let Some(overlay) = &overlay {
+            if overlay.hides_entry(name) {
+                return true;
             }
         }
-        return file_find_close(&mut directory_file_find_data);
+
+        visitor(name, entry_type)
+    };
+
+    let mut native_pattern = pattern.to_bytes_with_nul().to_vec();
+    compat_windows_path_to_native(native_pattern.as_mut_ptr() as *mut c_char);
+    let native_pattern = CString::from_vec_with_nul(native_pattern).expect("valid string");
+
+    if xfile_is_absolute_path_in(vfs, &native_pattern) {
+        return xbase_enumerate_directory(
+            native_pattern.as_ptr(),
+            native_pattern.as_ptr(),
+            &mut visitor,
+        );
+    }
+
+    if let Some(overlay) = &overlay {
+        if !overlay.directory().enumerate(pattern, &mut visitor) {
+            return false;
+        }
     }
 
-    let mut optional_xbase = get_g_xbase_head_rc();
+    let mut optional_xbase = vfs.head();
     while let Some(xbase) = optional_xbase {
         let xbase = xbase.read();
-        if xbase.is_dbase {
-            let mut dbase_find_data = DFileFindData::default();
-            let dbase = &xbase.dbase.as_ref().expect("").borrow();
-            if dbase_find_first_entry(dbase, &mut dbase_find_data, pattern) {
-                context._type = XFileEnumerationEntryType::XfileEnumerationEntryTypeDfile;
-
-                loop {
-                    strcpy(
-                        context.name.as_mut_ptr(),
-                        dbase_find_data.file_name.as_ptr(),
-                    );
-                    if !handler(&context) {
-                        return dbase_find_close(dbase, &dbase_find_data);
-                    }
-                    if !dbase_find_next_entry(dbase, &mut dbase_find_data) {
-                        break;
-                    }
-                }
-
-                dbase_find_close(dbase, &dbase_find_data);
-            }
-        } else {
-            let mut path = [0 as c_char; COMPAT_MAX_PATH];
-            let sformat_sformat = CString::new("%s\\%s").expect("valid string");
-            snprintf(
-                path.as_mut_ptr(),
-                mem::size_of_val(&path),
-                sformat_sformat.as_ptr(),
-                xbase.get_path_cstr(),
-                pattern,
-            );
-            compat_windows_path_to_native(path.as_mut_ptr());
-
-            if file_find_first(path.as_mut_ptr(), &mut directory_file_find_data) {
-                loop {
-                    let is_directory = file_find_is_directory(&directory_file_find_data);
-                    let entry_name = file_find_get_name(&directory_file_find_data);
-
-                    if is_directory {
-                        let dot_dot = CString::new("..").expect("valid string");
-                        let dot = CString::new(".").expect("valid string");
-                        if strcmp(entry_name, dot_dot.as_ptr()) == 0
-                            || strcmp(entry_name, dot.as_ptr()) == 0
-                        {
-                            continue;
-                        }
-
-                        context._type =
-                            XFileEnumerationEntryType::XfileEnumerationEntryTypeDirectory;
-                    } else {
-                        context._type = XFileEnumerationEntryType::XfileEnumerationEntryTypeFile;
-                    }
-
-                    rust_compat_makepath(
-                        context.name.as_mut_ptr(),
-                        drive.as_ptr(),
-                        dir.as_ptr(),
-                        entry_name,
-                        null(),
-                    );
-
-                    if !handler(&context) {
-                        break;
-                    }
-
-                    if !file_find_next(&mut directory_file_find_data) {
-                        break;
-                    }
-                }
-            }
-            file_find_close(&directory_file_find_data);
+        if !xbase.backend.enumerate(pattern, &mut visitor) {
+            return false;
         }
         optional_xbase = xbase.next.clone();
     }
 
-    rust_compat_splitpath(
-        native_pattern.as_ptr(),
-        drive.as_mut_ptr(),
-        dir.as_mut_ptr(),
-        file_name.as_mut_ptr(),
-        extension.as_mut_ptr(),
-    );
-    if file_find_first(native_pattern.as_ptr(), &mut directory_file_find_data) {
-        loop {
-            let is_directory = file_find_is_directory(&directory_file_find_data);
-            let entry_name = file_find_get_name(&directory_file_find_data);
-
-            if is_directory {
-                let dot_dot = CString::new("..").expect("valid string");
-                let dot = CString::new(".").expect("valid string");
-                if strcmp(entry_name, dot_dot.as_ptr()) == 0
-                    || strcmp(entry_name, dot.as_ptr()) == 0
-                {
-                    continue;
-                }
-
-                context._type = XFileEnumerationEntryType::XfileEnumerationEntryTypeDirectory;
-            } else {
-                context._type = XFileEnumerationEntryType::XfileEnumerationEntryTypeFile;
-            }
-
-            rust_compat_makepath(
-                context.name.as_mut_ptr(),
-                drive.as_ptr(),
-                dir.as_ptr(),
-                entry_name,
-                null(),
-            );
-
-            if !handler(&context) {
-                break;
-            }
-
-            if !file_find_next(&mut directory_file_find_data) {
-                break;
-            }
-        }
+    if !vfs.is_sandboxed()
+        && !xbase_enumerate_directory(native_pattern.as_ptr(), native_pattern.as_ptr(), &mut visitor)
+    {
+        return false;
     }
 
-    file_find_close(&directory_file_find_data)
+    match vfs.embedded() {
+        Some(embedded) => embedded.enumerate(pattern, &mut visitor),
+        None => true,
+    }
 }
 
 pub unsafe fn xlist_free(xlist: *mut XList) {
@@ -897,7 +1077,7 @@ unsafe extern "C" fn enumerate_handler(context: *const XListEnumerationContext)
 
     let file_names = realloc(
         (*xlist).file_names as *mut c_void,
-        mem::size_of_val(&*(*xlist).file_names) * ((*xlist).file_names_length + 1) as usize,
+        mem::size_of::<*mut c_char>() * ((*xlist).file_names_length + 1) as usize,
     ) as *mut *mut c_char;
     if file_names == null_mut() {
         xlist_free(xlist);
@@ -924,3 +1104,145 @@ pub unsafe fn xlist_init(pattern: *const c_char, xlist: *mut XList) -> bool {
     xlist_enumerate(pattern, enumerate_handler, xlist);
     (*xlist).file_names_length != -1
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::{cstring, temp_path};
+    use crate::db::{rust_file_read_int16, rust_file_read_int32};
+    use crate::dfile::DBase;
+    use libz_sys::gzopen;
+    use std::fs;
+    use std::path::Path;
+
+    unsafe fn write_gzip(path: &Path, data: &[u8]) {
+        let path = cstring(path.to_str().expect(""));
+        let file = gzopen(path.as_ptr(), cstring("wb").as_ptr());
+        assert!(!file.is_null());
+        gzwrite(file, data.as_ptr() as voidpc, data.len() as c_uint);
+        gzclose(file);
+    }
+
+    #[test]
+    fn test_xfile_gzip_size() {
+        let single_path = temp_path("single.gz");
+        let multi_path = temp_path("multi.gz");
+        let data = (0..10000).map(|index| (index % 251) as u8).collect::<Vec<_>>();
+
+        unsafe {
+            write_gzip(&single_path, &data);
+
+            let single = cstring(single_path.to_str().expect(""));
+            assert_eq!(xfile_gzip_trailer_size(&single), Some(10000));
+            let single = xfile_open_plain(&single, &cstring("rb")).expect("");
+            assert!(matches!(
+                single,
+                XFileType::GZFile(_, XFileGzipSize::Pending(_))
+            ));
+            let stream = Box::into_raw(Box::new(XFile::new(single)));
+            assert_eq!(rust_xfile_get_size(stream), 10000);
+            rust_xfile_close(stream);
+
+            // ISIZE of the last member is not the size of the whole file,
+            // even when it looks plausible.
+            let zeros = vec![0u8; 1 << 20];
+            write_gzip(&multi_path, &zeros);
+            let mut multi = fs::read(&multi_path).expect("");
+            multi.extend(multi.clone());
+            fs::write(&multi_path, multi).expect("");
+            let multi = cstring(multi_path.to_str().expect(""));
+            assert_eq!(xfile_gzip_trailer_size(&multi), None);
+            let multi = xfile_open_plain(&multi, &cstring("rb")).expect("");
+            let stream = Box::into_raw(Box::new(XFile::new(multi)));
+            assert_eq!(rust_xfile_get_size(stream), 2 << 20);
+            rust_xfile_close(stream);
+
+            write_gzip(&multi_path, &data[..9990]);
+            let mut multi = fs::read(&multi_path).expect("");
+            write_gzip(&multi_path, &data[9990..]);
+            multi.extend(fs::read(&multi_path).expect(""));
+            fs::write(&multi_path, multi).expect("");
+            let multi = cstring(multi_path.to_str().expect(""));
+            assert_eq!(xfile_gzip_trailer_size(&multi), None);
+            let multi = xfile_open_plain(&multi, &cstring("rb")).expect("");
+            let stream = Box::into_raw(Box::new(XFile::new(multi)));
+            assert_eq!(xfile_read_char(stream), 0);
+            assert_eq!(xfile_read_char(stream), 1);
+            assert_eq!(rust_xfile_get_size(stream), 10000);
+            assert_eq!(rust_xfile_tell(stream), 2);
+            assert_eq!(xfile_read_char(stream), 2);
+            rust_xfile_close(stream);
+        }
+
+        fs::remove_file(&single_path).expect("");
+        fs::remove_file(&multi_path).expect("");
+    }
+
+    #[test]
+    fn test_xfile_memory() {
+        unsafe {
+            // Big-endian values, as stored in game files.
+            let data = [0x12, 0x34, 0x56, 0x78, 0xFF, 0xFE, b'a', b'\n', b'b'];
+            let stream =
+                rust_xfile_open_memory_borrowed(data.as_ptr() as *const c_void, data.len());
+            let mut int32 = 0;
+            assert_eq!(rust_file_read_int32(stream, &mut int32), 0);
+            assert_eq!(int32, 0x12345678);
+            let mut int16 = 0;
+            assert_eq!(rust_file_read_int16(stream, &mut int16), 0);
+            assert_eq!(int16, -2);
+            assert_eq!(rust_xfile_tell(stream), 6);
+            assert_eq!(rust_xfile_get_size(stream), 9);
+
+            let mut line = [0 as c_char; 16];
+            assert_eq!(xfile_read_string(line.as_mut_ptr(), 16, stream), line.as_ptr());
+            assert_eq!(CStr::from_ptr(line.as_ptr()), cstring("a\n").as_c_str());
+            assert_eq!(xfile_read_char(stream), b'b' as c_int);
+            assert_eq!(rust_xfile_eof(stream), 0);
+            assert_eq!(xfile_read_char(stream), -1);
+            assert_eq!(rust_xfile_eof(stream), 1);
+
+            // Writing to borrowed buffer does not modify it.
+            assert_eq!(rust_xfile_seek(stream, -3, libc::SEEK_END), 0);
+            assert_eq!(rust_xfile_eof(stream), 0);
+            assert_eq!(rust_xfile_write_string(cstring("xy").as_ptr(), stream), 0);
+            assert_eq!(rust_xfile_seek(stream, 2, libc::SEEK_CUR), 0);
+            assert_eq!(xfile_write_char(b'z' as c_int, stream), b'z' as c_int);
+            assert_eq!(data[6], b'a');
+
+            let mut size = 0;
+            let content = rust_xfile_get_memory(stream, &mut size) as *const u8;
+            assert_eq!(
+                slice::from_raw_parts(content, size),
+                b"\x12\x34\x56\x78\xFF\xFExyb\0z"
+            );
+            rust_xfile_close(stream);
+
+            let stream = rust_xfile_open_memory(null(), 0);
+            let mut buffer = [0u8; 4];
+            assert_eq!(xfile_read(buffer.as_mut_ptr() as *mut c_void, 4, 1, stream), 0);
+            assert_eq!(rust_xfile_eof(stream), 1);
+            rust_xfile_rewind(stream);
+            assert_eq!(rust_xfile_eof(stream), 0);
+            assert_eq!(rust_xfile_write(b"data".as_ptr() as *const c_void, 2, 2, stream), 2);
+            assert_eq!(rust_xfile_get_size(stream), 4);
+            let overflowing_count = usize::MAX / 2 + 1;
+            assert_eq!(
+                rust_xfile_write(b"data".as_ptr() as *const c_void, 2, overflowing_count, stream),
+                0
+            );
+            assert_eq!(rust_xfile_get_size(stream), 4);
+            rust_xfile_close(stream);
+        }
+    }
+
+    fn assert_send_sync<T: Send + Sync>() {}
+
+    #[test]
+    fn test_xfile_send_sync() {
+        assert_send_sync::<DBase>();
+        assert_send_sync::<DFile>();
+        assert_send_sync::<XFile>();
+        assert_send_sync::<Vfs>();
+    }
+}
@@ -0,0 +1,244 @@
+// In-memory streams.
+//
+// [MemoryFile] lets [XFile] wrap a byte buffer instead of a file, so that
+// decompressed or generated content can be handed to loaders which expect a
+// regular stream (see [rust_xfile_open_memory]).
+//
+// Memory streams are binary, and always both readable and writable. Writing
+// past the end grows the buffer (the gap, if any, is filled with zeros).
+// Borrowed buffers are never modified, instead they are copied on first write.
+
+use libc::{c_char, c_int, c_long, size_t, EOF, SEEK_CUR, SEEK_END, SEEK_SET};
+use std::ffi::{c_void, CStr};
+use std::ptr::{null, null_mut};
+use std::slice;
+
+enum MemoryFileBuffer {
+    Owned(Vec<u8>),
+
+    // The buffer which is owned by the caller (who guarantees it outlives the
+    // stream).
+    Borrowed(*const u8, usize),
+}
+
+pub struct MemoryFile {
+    buffer: MemoryFileBuffer,
+    position: usize,
+    eof: bool,
+}
+
+impl MemoryFile {
+    pub(crate) fn new(data: Vec<u8>) -> Self {
+        Self::with_buffer(MemoryFileBuffer::Owned(data))
+    }
+
+    pub(crate) fn from_static(data: &'static [u8]) -> Self {
+        unsafe { Self::from_raw_parts(data.as_ptr(), data.len()) }
+    }
+
+    // Creates stream over [len] bytes at [ptr] without copying them.
+    //
+    // The buffer must stay valid (and unchanged) until the stream is dropped.
+    pub(crate) unsafe fn from_raw_parts(ptr: *const u8, len: usize) -> Self {
+        Self::with_buffer(MemoryFileBuffer::Borrowed(ptr, len))
+    }
+
+    fn with_buffer(buffer: MemoryFileBuffer) -> Self {
+        Self {
+            buffer,
+            position: 0,
+            eof: false,
+        }
+    }
+
+    pub(crate) fn data(&self) -> &[u8] {
+        match &self.buffer {
+            MemoryFileBuffer::Owned(data) => data,
+            // NOTE: [slice::from_raw_parts] requires non-null pointer even for
+            // empty slices.
+            MemoryFileBuffer::Borrowed(_, 0) => &[],
+            MemoryFileBuffer::Borrowed(ptr, len) => unsafe { slice::from_raw_parts(*ptr, *len) },
+        }
+    }
+
+    fn data_mut(&mut self) -> &mut Vec<u8> {
+        if let MemoryFileBuffer::Borrowed(..) = self.buffer {
+            self.buffer = MemoryFileBuffer::Owned(self.data().to_vec());
+        }
+
+        match &mut self.buffer {
+            MemoryFileBuffer::Owned(data) => data,
+            MemoryFileBuffer::Borrowed(..) => unreachable!(),
+        }
+    }
+
+    fn remaining(&self) -> &[u8] {
+        let data = self.data();
+        &data[self.position.min(data.len())..]
+    }
+
+    fn write_bytes(&mut self, bytes: &[u8]) {
+        let position = self.position;
+        let data = self.data_mut();
+        if data.len() < position {
+            data.resize(position, 0);
+        }
+
+        let overwritten = bytes.len().min(data.len() - position);
+        data[position..position + overwritten].copy_from_slice(&bytes[..overwritten]);
+        data.extend_from_slice(&bytes[overwritten..]);
+
+        self.position += bytes.len();
+    }
+}
+
+// [fgetc].
+pub(crate) fn memory_file_read_char(stream: &mut MemoryFile) -> c_int {
+    match stream.remaining().first() {
+        Some(&ch) => {
+            stream.position += 1;
+            ch as c_int
+        }
+        None => {
+            stream.eof = true;
+            EOF
+        }
+    }
+}
+
+// [fgets].
+pub(crate) unsafe fn memory_file_read_string(
+    string: *mut c_char,
+    size: c_int,
+    stream: &mut MemoryFile,
+) -> *const c_char {
+    assert_ne!(string, null_mut());
+    assert_ne!(size, 0);
+
+    let remaining = stream.remaining();
+    if remaining.is_empty() {
+        stream.eof = true;
+        return null();
+    }
+
+    // Read up to size - 1 characters saving space for the null terminator.
+    let mut length = remaining.len().min((size - 1) as usize);
+    if let Some(index) = remaining[..length].iter().position(|&ch| ch == b'\n') {
+        length = index + 1;
+    }
+
+    let buffer = slice::from_raw_parts_mut(string as *mut u8, length + 1);
+    buffer[..length].copy_from_slice(&remaining[..length]);
+    buffer[length] = b'\0';
+
+    stream.position += length;
+
+    string
+}
+
+// [fread].
+pub(crate) unsafe fn memory_file_read(
+    ptr: *mut c_void,
+    size: size_t,
+    count: size_t,
+    stream: &mut MemoryFile,
+) -> size_t {
+    assert_ne!(ptr, null_mut());
+
+    if size == 0 || count == 0 {
+        return 0;
+    }
+
+    let remaining = stream.remaining();
+    let requested = size.saturating_mul(count);
+    let bytes_to_read = requested.min(remaining.len());
+
+    slice::from_raw_parts_mut(ptr as *mut u8, bytes_to_read)
+        .copy_from_slice(&remaining[..bytes_to_read]);
+
+    stream.position += bytes_to_read;
+    if bytes_to_read < requested {
+        stream.eof = true;
+    }
+
+    bytes_to_read / size
+}
+
+// [fputc].
+pub(crate) fn memory_file_write_char(ch: c_int, stream: &mut MemoryFile) -> c_int {
+    let ch = ch as u8;
+    stream.write_bytes(&[ch]);
+    ch as c_int
+}
+
+// [fputs].
+pub(crate) unsafe fn memory_file_write_string(
+    string: *const c_char,
+    stream: &mut MemoryFile,
+) -> c_int {
+    assert_ne!(string, null());
+
+    stream.write_bytes(CStr::from_ptr(string).to_bytes());
+    0
+}
+
+// [fwrite].
+pub(crate) unsafe fn memory_file_write(
+    ptr: *const c_void,
+    size: size_t,
+    count: size_t,
+    stream: &mut MemoryFile,
+) -> size_t {
+    assert_ne!(ptr, null());
+
+    if size == 0 || count == 0 {
+        return 0;
+    }
+
+    // Such buffer cannot exist.
+    let Some(length) = size.checked_mul(count) else {
+        return 0;
+    };
+
+    stream.write_bytes(slice::from_raw_parts(ptr as *const u8, length));
+    count
+}
+
+// [fseek].
+//
+// Seeking past the end is allowed, the buffer only grows when something is
+// written there.
+pub(crate) fn memory_file_seek(stream: &mut MemoryFile, offset: c_long, origin: c_int) -> c_int {
+    let base = match origin {
+        SEEK_SET => 0,
+        SEEK_CUR => stream.position,
+        SEEK_END => stream.data().len(),
+        _ => return -1,
+    };
+
+    match (base as c_long).checked_add(offset) {
+        Some(position) if position >= 0 => {
+            stream.position = position as usize;
+            stream.eof = false;
+            0
+        }
+        _ => -1,
+    }
+}
+
+pub(crate) fn memory_file_tell(stream: &MemoryFile) -> c_long {
+    stream.position as c_long
+}
+
+pub(crate) fn memory_file_rewind(stream: &mut MemoryFile) {
+    stream.position = 0;
+    stream.eof = false;
+}
+
+pub(crate) fn memory_file_eof(stream: &MemoryFile) -> c_int {
+    stream.eof as c_int
+}
+
+pub(crate) fn memory_file_get_size(stream: &MemoryFile) -> c_long {
+    stream.data().len() as c_long
+}
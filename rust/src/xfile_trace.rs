@@ -0,0 +1,170 @@
+// Tracing of file resolution.
+//
+// Every [rust_xfile_open] consults a number of sources (overlay, xbases in
+// search order, and current working directory) until the file is found. When
+// tracing is enabled, every consulted source is reported to the handler
+// registered with [rust_xfile_set_trace_handler], and/or logged with
+// [debug_print] (see [rust_xfile_set_trace_logging]).
+//
+// Tracing is configured per [Vfs], FFI functions configure the global
+// instance (see [Vfs::set_trace_handler] for other instances).
+
+use crate::debug_print::debug_print;
+use crate::vfs::Vfs;
+use crate::xfile::XFileType;
+use libc::c_char;
+use spin::RwLock;
+use std::ffi::CStr;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+#[repr(C)]
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum XFileTraceSource {
+    // Overlay directory, see [rust_xbase_set_overlay].
+    Overlay = 0,
+
+    // Directory-based xbase.
+    Directory = 1,
+
+    // Any other xbase (.DAT/.ZIP file, or custom backend).
+    Archive = 2,
+
+    // Current working directory, which is used when the file is not found in
+    // xbases.
+    WorkingDirectory = 3,
+
+    // The file path is absolute, so xbases are not consulted.
+    Absolute = 4,
+
+    // Fallback resources compiled into the executable, which are used when
+    // the file is not found anywhere else.
+    Embedded = 5,
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum XFileTraceResult {
+    NotFound = 0,
+    Found = 1,
+
+    // The file is found, and it's gzipped.
+    FoundGzipped = 2,
+
+    // The file is hidden by whiteout marker in overlay.
+    Hidden = 3,
+}
+
+#[repr(C)]
+pub struct XFileTraceEvent {
+    // The requested file path.
+    pub file_path: *const c_char,
+
+    // The path of consulted xbase (or overlay directory).
+    pub source_path: *const c_char,
+
+    pub source: XFileTraceSource,
+    pub result: XFileTraceResult,
+}
+
+pub type XFileTraceHandler = unsafe extern "C" fn(*const XFileTraceEvent);
+
+// Tracing settings of [Vfs].
+pub(crate) struct XFileTracer {
+    handler: RwLock<Option<XFileTraceHandler>>,
+    logging: AtomicBool,
+}
+
+// Sets [handler] to receive resolution events of the global [Vfs], or
+// disables it if [handler] is NULL.
+#[no_mangle]
+pub extern "C" fn rust_xfile_set_trace_handler(handler: Option<XFileTraceHandler>) {
+    Vfs::global().set_trace_handler(handler);
+}
+
+// Enables or disables logging resolution events of the global [Vfs] with
+// [debug_print].
+#[no_mangle]
+pub extern "C" fn rust_xfile_set_trace_logging(enabled: bool) {
+    Vfs::global().set_trace_logging(enabled);
+}
+
+impl XFileTracer {
+    pub(crate) const fn new() -> Self {
+        Self {
+            handler: RwLock::new(None),
+            logging: AtomicBool::new(false),
+        }
+    }
+
+    pub(crate) fn set_handler(&self, handler: Option<XFileTraceHandler>) {
+        *self.handler.write() = handler;
+    }
+
+    pub(crate) fn set_logging(&self, enabled: bool) {
+        self.logging.store(enabled, Ordering::Relaxed);
+    }
+}
+
+impl XFileTraceSource {
+    fn name(self) -> &'static str {
+        match self {
+            XFileTraceSource::Overlay => "overlay",
+            XFileTraceSource::Directory => "directory",
+            XFileTraceSource::Archive => "archive",
+            XFileTraceSource::WorkingDirectory => "working directory",
+            XFileTraceSource::Absolute => "absolute path",
+            XFileTraceSource::Embedded => "embedded",
+        }
+    }
+}
+
+impl XFileTraceResult {
+    pub(crate) fn of(file: &Option<XFileType>) -> Self {
+        match file {
+            Some(XFileType::GZFile(..)) => XFileTraceResult::FoundGzipped,
+            Some(_) => XFileTraceResult::Found,
+            None => XFileTraceResult::NotFound,
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            XFileTraceResult::NotFound => "not found",
+            XFileTraceResult::Found => "found",
+            XFileTraceResult::FoundGzipped => "found (gzipped)",
+            XFileTraceResult::Hidden => "hidden",
+        }
+    }
+}
+
+// Reports that [source] at [source_path] was consulted for [file_path] in
+// [vfs].
+pub(crate) fn xfile_trace(
+    vfs: &Vfs,
+    file_path: &CStr,
+    source: XFileTraceSource,
+    source_path: &CStr,
+    result: XFileTraceResult,
+) {
+    let tracer = vfs.tracer();
+    if tracer.logging.load(Ordering::Relaxed) {
+        debug_print(format_args!(
+            "xfile: {} <- {} ({}): {}",
+            file_path.to_string_lossy(),
+            source_path.to_string_lossy(),
+            source.name(),
+            result.name()
+        ));
+    }
+
+    let handler = *tracer.handler.read();
+    if let Some(handler) = handler {
+        let event = XFileTraceEvent {
+            file_path: file_path.as_ptr(),
+            source_path: source_path.as_ptr(),
+            source,
+            result,
+        };
+        unsafe { handler(&event) };
+    }
+}
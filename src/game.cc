@@ -64,7 +64,9 @@
 #include "tile.h"
 #include "trait.h"
 #include "window_manager.h"
+#include "word_wrap.h"
 #include "worldmap.h"
+#include "xfile.h"
 
 // Migrated
 #include "db.h"
@@ -74,6 +76,10 @@
 extern "C"
 {
     const char* rust_get_version_build_time();
+    void rust_dbase_set_memory_mapping(bool value);
+    void rust_xfile_set_trace_logging(bool enabled);
+    void rust_set_debug_print_handler(void (*handler)(const char* message));
+    bool rust_db_mount_mods(const char* manifest_path, const char* report_path);
 }
 
 namespace fallout {
@@ -90,6 +96,10 @@ static int gameTakeScreenshot(int width, int height, unsigned char* buffer, unsi
 static void gameFreeGlobalVars();
 static void showHelp();
 static int gameDbInit();
+static void gameHandleXbaseChange(const XBaseChangeEvent* event);
+static void gameDebugPrintRustMessage(const char* message);
+static int gameInitFailed(const char* name);
+static void gameShowFatalError(const char* message);
 static void showSplash();
 
 // 0x501C9C
@@ -145,6 +155,10 @@ int gameInitWithOptions(const char* windowTitle, bool isMapper, int font, int a4
 
     gIsMapper = isMapper;
 
+    // CE: Title is required to initialize window system, which can happen
+    // early to report missing data files (see |gameShowFatalError|).
+    programWindowSetTitle(windowTitle);
+
     if (gameDbInit() == -1) {
         settingsExit(false);
         sfallConfigExit();
@@ -155,9 +169,10 @@ int gameInitWithOptions(const char* windowTitle, bool isMapper, int font, int a4
     // it should be initialized early in the process.
     messageListRepositoryInit();
 
-    programWindowSetTitle(windowTitle);
-    _initWindow(1, a4);
-    paletteInit();
+    if (!gWindowSystemInitialized) {
+        _initWindow(1, a4);
+        paletteInit();
+    }
 
     const char* language = settings.system.language.c_str();
     if (compat_stricmp(language, FRENCH) == 0) {
@@ -193,8 +208,7 @@ int gameInitWithOptions(const char* windowTitle, bool isMapper, int font, int a4
     statsInit();
 
     if (partyMembersInit() != 0) {
-        debugPrint("Failed on partyMember_init\n");
-        return -1;
+        return gameInitFailed("partyMember_init");
     }
 
     perksInit();
@@ -215,36 +229,31 @@ int gameInitWithOptions(const char* windowTitle, bool isMapper, int font, int a4
     debugPrint(">initMovie\t\t");
 
     if (gameMoviesInit() != 0) {
-        debugPrint("Failed on gmovie_init\n");
-        return -1;
+        return gameInitFailed("gmovie_init");
     }
 
     debugPrint(">gmovie_init\t");
 
     if (movieEffectsInit() != 0) {
-        debugPrint("Failed on moviefx_init\n");
-        return -1;
+        return gameInitFailed("moviefx_init");
     }
 
     debugPrint(">moviefx_init\t");
 
     if (isoInit() != 0) {
-        debugPrint("Failed on iso_init\n");
-        return -1;
+        return gameInitFailed("iso_init");
     }
 
     debugPrint(">iso_init\t");
 
     if (gameMouseInit() != 0) {
-        debugPrint("Failed on gmouse_init\n");
-        return -1;
+        return gameInitFailed("gmouse_init");
     }
 
     debugPrint(">gmouse_init\t");
 
     if (protoInit() != 0) {
-        debugPrint("Failed on proto_init\n");
-        return -1;
+        return gameInitFailed("proto_init");
     }
 
     debugPrint(">proto_init\t");
@@ -253,29 +262,25 @@ int gameInitWithOptions(const char* windowTitle, bool isMapper, int font, int a4
     debugPrint(">anim_init\t");
 
     if (scriptsInit() != 0) {
-        debugPrint("Failed on scr_init\n");
-        return -1;
+        return gameInitFailed("scr_init");
     }
 
     debugPrint(">scr_init\t");
 
     if (gameLoadGlobalVars() != 0) {
-        debugPrint("Failed on game_load_info\n");
-        return -1;
+        return gameInitFailed("game_load_info");
     }
 
     debugPrint(">game_load_info\t");
 
     if (_scr_game_init() != 0) {
-        debugPrint("Failed on scr_game_init\n");
-        return -1;
+        return gameInitFailed("scr_game_init");
     }
 
     debugPrint(">scr_game_init\t");
 
     if (wmWorldMap_init() != 0) {
-        debugPrint("Failed on wmWorldMap_init\n");
-        return -1;
+        return gameInitFailed("wmWorldMap_init");
     }
 
     debugPrint(">wmWorldMap_init\t");
@@ -291,29 +296,25 @@ int gameInitWithOptions(const char* windowTitle, bool isMapper, int font, int a4
     debugPrint(">InitLoadSave\t");
 
     if (gameDialogInit() != 0) {
-        debugPrint("Failed on gdialog_init\n");
-        return -1;
+        return gameInitFailed("gdialog_init");
     }
 
     debugPrint(">gdialog_init\t");
 
     if (combatInit() != 0) {
-        debugPrint("Failed on combat_init\n");
-        return -1;
+        return gameInitFailed("combat_init");
     }
 
     debugPrint(">combat_init\t");
 
     if (automapInit() != 0) {
-        debugPrint("Failed on automap_init\n");
-        return -1;
+        return gameInitFailed("automap_init");
     }
 
     debugPrint(">automap_init\t");
 
     if (!messageListInit(&gMiscMessageList)) {
-        debugPrint("Failed on message_init\n");
-        return -1;
+        return gameInitFailed("message_init");
     }
 
     debugPrint(">message_init\t");
@@ -321,29 +322,25 @@ int gameInitWithOptions(const char* windowTitle, bool isMapper, int font, int a4
     snprintf(path, sizeof(path), "%s%s", asc_5186C8, "misc.msg");
 
     if (!messageListLoad(&gMiscMessageList, path)) {
-        debugPrint("Failed on message_load\n");
-        return -1;
+        return gameInitFailed("message_load");
     }
 
     debugPrint(">message_load\t");
 
     if (scriptsDisable() != 0) {
-        debugPrint("Failed on scr_disable\n");
-        return -1;
+        return gameInitFailed("scr_disable");
     }
 
     debugPrint(">scr_disable\t");
 
     if (_init_options_menu() != 0) {
-        debugPrint("Failed on init_options_menu\n");
-        return -1;
+        return gameInitFailed("init_options_menu");
     }
 
     debugPrint(">init_options_menu\n");
 
     if (endgameDeathEndingInit() != 0) {
-        debugPrint("Failed on endgameDeathEndingInit");
-        return -1;
+        return gameInitFailed("endgameDeathEndingInit");
     }
 
     debugPrint(">endgameDeathEndingInit\n");
@@ -352,23 +349,19 @@ int gameInitWithOptions(const char* windowTitle, bool isMapper, int font, int a4
     premadeCharactersInit();
 
     if (!sfall_gl_vars_init()) {
-        debugPrint("Failed on sfall_gl_vars_init");
-        return -1;
+        return gameInitFailed("sfall_gl_vars_init");
     }
 
     if (!sfallListsInit()) {
-        debugPrint("Failed on sfallListsInit");
-        return -1;
+        return gameInitFailed("sfallListsInit");
     }
 
     if (!sfallArraysInit()) {
-        debugPrint("Failed on sfallArraysInit");
-        return -1;
+        return gameInitFailed("sfallArraysInit");
     }
 
     if (!sfall_gl_scr_init()) {
-        debugPrint("Failed on sfall_gl_scr_init");
-        return -1;
+        return gameInitFailed("sfall_gl_scr_init");
     }
 
     char* customConfigBasePath;
@@ -1326,6 +1319,10 @@ static int gameDbInit()
     main_file_name = NULL;
     patch_file_name = NULL;
 
+    rust_set_debug_print_handler(gameDebugPrintRustMessage);
+    rust_dbase_set_memory_mapping(settings.system.memory_mapped_dats);
+    rust_xfile_set_trace_logging(settings.debug.trace_file_resolution);
+
     main_file_name = settings.system.master_dat_path.c_str();
     if (*main_file_name == '\0') {
         main_file_name = NULL;
@@ -1338,7 +1335,7 @@ static int gameDbInit()
 
     int master_db_handle = dbOpen(main_file_name, 0, patch_file_name, 1);
     if (master_db_handle == -1) {
-        showMesageBox("Could not find the master datafile. Please make sure the FALLOUT CD is in the drive and that you are running FALLOUT from the directory you installed it to.");
+        gameShowFatalError("Could not find the master datafile. Please make sure the FALLOUT CD is in the drive and that you are running FALLOUT from the directory you installed it to.");
         return -1;
     }
 
@@ -1354,7 +1351,7 @@ static int gameDbInit()
 
     int critter_db_handle = dbOpen(main_file_name, 0, patch_file_name, 1);
     if (critter_db_handle == -1) {
-        showMesageBox("Could not find the critter datafile. Please make sure the FALLOUT CD is in the drive and that you are running FALLOUT from the directory you installed it to.");
+        gameShowFatalError("Could not find the critter datafile. Please make sure the FALLOUT CD is in the drive and that you are running FALLOUT from the directory you installed it to.");
         return -1;
     }
 
@@ -1370,9 +1367,133 @@ static int gameDbInit()
         dbOpen("f2_res.dat", 0, NULL, 1);
     }
 
+    const char* mods_manifest_path = settings.system.mods_manifest_path.c_str();
+    if (*mods_manifest_path != '\0') {
+        if (!rust_db_mount_mods(mods_manifest_path, "mod_conflicts.txt")) {
+            debugPrint("Could not load mods from %s\n", mods_manifest_path);
+        }
+    }
+
+    // CE: Let modders see changes to loose files without restarting the game.
+    if (settings.debug.hot_reload) {
+        if (xbaseSetWatching(true)) {
+            xbaseSetChangeHandler(gameHandleXbaseChange);
+        } else {
+            debugPrint("Could not watch data directories for changes\n");
+        }
+    }
+
     return 0;
 }
 
+// CE: Receives diagnostic messages of file subsystem (Rust code never writes
+// to stderr by itself).
+static void gameDebugPrintRustMessage(const char* message)
+{
+    debugPrint("%s\n", message);
+}
+
+// Drops cached art when art files are changed, so that they are loaded again
+// the next time they're used, and reloads changed message lists.
+static void gameHandleXbaseChange(const XBaseChangeEvent* event)
+{
+    debugPrint("%s changed in %s\n", event->filePath, event->sourcePath);
+
+    // Matches .FRM and directional .FR0-.FR5 files.
+    const char* extension = strrchr(event->filePath, '.');
+    if (extension != NULL && compat_strnicmp(extension, ".fr", 3) == 0) {
+        artCacheFlush();
+    } else if (extension != NULL && compat_stricmp(extension, ".msg") == 0) {
+        messageListReloadFile(event->filePath);
+    }
+}
+
+// CE: Reports that initialization step |name| failed, which usually means
+// that game data files are missing or corrupted.
+static int gameInitFailed(const char* name)
+{
+    debugPrint("Failed on %s\n", name);
+
+    char message[256];
+    snprintf(message, sizeof(message), "Could not initialize the game (%s failed). Please make sure the game data files are not missing or corrupted.", name);
+    gameShowFatalError(message);
+
+    return -1;
+}
+
+// CE: Shows |message| in the game window and waits for any key or mouse
+// click. Unlike system message box, it's visible in fullscreen mode.
+//
+// The window system is initialized if needed. It only requires a text font
+// and a palette, which are embedded into the executable as a fallback, so
+// this works even when game data files are missing.
+static void gameShowFatalError(const char* message)
+{
+    debugPrint("%s\n", message);
+
+    if (!gWindowSystemInitialized) {
+        _initWindow(1, 0);
+        paletteInit();
+    }
+
+    // Text font (as opposed to interface fonts) is always available.
+    int oldFont = fontGetCurrent();
+    fontSetCurrent(0);
+
+    int screenWidth = screenGetWidth();
+    int screenHeight = screenGetHeight();
+    int win = windowCreate(0, 0, screenWidth, screenHeight, _colorTable[0], WINDOW_MODAL | WINDOW_MOVE_ON_TOP);
+    if (win == -1) {
+        fontSetCurrent(oldFont);
+        showMesageBox(message);
+        return;
+    }
+
+    unsigned char* windowBuffer = windowGetBuffer(win);
+    int maxWidth = screenWidth - 64;
+    int color = _colorTable[32767];
+
+    short beginnings[WORD_WRAP_MAX_COUNT];
+    short count;
+    if (wordWrap(message, maxWidth, beginnings, &count) != 0) {
+        beginnings[0] = 0;
+        beginnings[1] = static_cast<short>(strlen(message));
+        count = 2;
+    }
+
+    int y = (screenHeight - (count - 1) * fontGetLineHeight()) / 2;
+    for (int index = 1; index < count; index++) {
+        int length = beginnings[index] - beginnings[index - 1];
+        if (length >= 260) {
+            length = 259;
+        }
+
+        char line[260];
+        strncpy(line, message + beginnings[index - 1], length);
+        line[length] = '\0';
+
+        // Remove trailing space as it affects width calculation.
+        if (length > 0 && line[length - 1] == ' ') {
+            line[length - 1] = '\0';
+        }
+
+        int width = fontGetStringWidth(line);
+        fontDrawText(windowBuffer + screenWidth * y + (screenWidth - width) / 2, line, screenWidth, screenWidth, color);
+        y += fontGetLineHeight();
+    }
+
+    windowRefresh(win);
+
+    while (inputGetInput() == -1) {
+        rust_fps_limiter_mark(sharedFpsLimiter);
+        renderPresent();
+        rust_fps_limiter_throttle(sharedFpsLimiter);
+    }
+
+    windowDestroy(win);
+    fontSetCurrent(oldFont);
+}
+
 // 0x444384
 static void showSplash()
 {
@@ -28,6 +28,8 @@ namespace fallout {
 #define GAME_CONFIG_HASHING_KEY "hashing"
 #define GAME_CONFIG_SPLASH_KEY "splash"
 #define GAME_CONFIG_FREE_SPACE_KEY "free_space"
+#define GAME_CONFIG_MEMORY_MAPPED_DATS_KEY "memory_mapped_dats"
+#define GAME_CONFIG_MODS_MANIFEST_KEY "mods_manifest"
 #define GAME_CONFIG_TIMES_RUN_KEY "times_run"
 #define GAME_CONFIG_GAME_DIFFICULTY_KEY "game_difficulty"
 #define GAME_CONFIG_RUNNING_BURNING_GUY_KEY "running_burning_guy"
@@ -68,6 +70,8 @@ namespace fallout {
 #define GAME_CONFIG_SHOW_SCRIPT_MESSAGES_KEY "show_script_messages"
 #define GAME_CONFIG_SHOW_LOAD_INFO_KEY "show_load_info"
 #define GAME_CONFIG_OUTPUT_MAP_DATA_INFO_KEY "output_map_data_info"
+#define GAME_CONFIG_TRACE_FILE_RESOLUTION_KEY "trace_file_resolution"
+#define GAME_CONFIG_HOT_RELOAD_KEY "hot_reload"
 #define GAME_CONFIG_EXECUTABLE_KEY "executable"
 #define GAME_CONFIG_OVERRIDE_LIBRARIAN_KEY "override_librarian"
 #define GAME_CONFIG_LIBRARIAN_KEY "librarian"
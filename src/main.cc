@@ -38,6 +38,7 @@
 #include "window_manager_private.h"
 #include "word_wrap.h"
 #include "worldmap.h"
+#include "xfile.h"
 
 // Migrated
 #include "db.h"
@@ -363,6 +364,9 @@ static void mainLoop()
 
         int keyCode = inputGetInput();
 
+        // CE: Report changed files (when `hot_reload` is enabled).
+        xbasePollChanges();
+
         // SFALL: MainLoopHook.
         sfall_gl_scr_process_main();
 
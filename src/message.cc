@@ -6,7 +6,9 @@
 #include <cstring>
 
 #include <array>
+#include <string>
 #include <unordered_map>
+#include <vector>
 
 #include "debug.h"
 #include "memory.h"
@@ -49,6 +51,15 @@ static int _message_load_field(File* file, char* str);
 
 static MessageList* messageListRepositoryLoad(const char* path);
 
+// CE: Paths of loaded message lists (as passed to |messageListLoad| and as
+// actually opened), so that they can be reloaded when files are changed.
+struct MessageListSource {
+    std::string path;
+    std::string localizedPath;
+};
+
+static std::unordered_map<MessageList*, MessageListSource> gMessageListSources;
+
 // 0x50B79C
 static char _Error_1[] = "Error";
 
@@ -199,6 +210,8 @@ bool messageListFree(MessageList* messageList)
         messageList->entries = NULL;
     }
 
+    gMessageListSources.erase(messageList);
+
     return true;
 }
 
@@ -274,6 +287,7 @@ bool messageListLoad(MessageList* messageList, const char* path)
 
     if (rc == 1) {
         success = true;
+        gMessageListSources[messageList] = { path, localized_path };
     }
 
 err:
@@ -287,6 +301,33 @@ err:
     return success;
 }
 
+// CE: Reloads message lists which were loaded from |filePath| (relative to
+// xbases, such as "text\\english\\game\\misc.msg"). Returns the number of
+// reloaded message lists.
+//
+// NOTE: Filters applied after loading (bad words, gender words) are not
+// applied again, and previously obtained message texts become invalid.
+int messageListReloadFile(const char* filePath)
+{
+    std::vector<MessageList*> messageLists;
+    for (auto& pair : gMessageListSources) {
+        if (compat_stricmp(pair.second.localizedPath.c_str(), filePath) == 0) {
+            messageLists.push_back(pair.first);
+        }
+    }
+
+    for (MessageList* messageList : messageLists) {
+        std::string path = gMessageListSources[messageList].path;
+        messageListFree(messageList);
+        messageListInit(messageList);
+        if (!messageListLoad(messageList, path.c_str())) {
+            debugPrint("Could not reload message file %s\n", filePath);
+        }
+    }
+
+    return static_cast<int>(messageLists.size());
+}
+
 // 0x484C30
 bool messageListGetItem(MessageList* msg, MessageListItem* entry)
 {
@@ -77,6 +77,7 @@ void badwordsExit();
 bool messageListInit(MessageList* msg);
 bool messageListFree(MessageList* msg);
 bool messageListLoad(MessageList* msg, const char* path);
+int messageListReloadFile(const char* filePath);
 bool messageListGetItem(MessageList* msg, MessageListItem* entry);
 bool _message_make_path(char* dest, size_t size, const char* path);
 char* getmsg(MessageList* msg, MessageListItem* entry, int num);
@@ -33,6 +33,7 @@ extern "C" {
     int rust_compat_access(const char* path, int mode);
     char* rust_compat_strdup(const char* string);
     long rust_get_file_size(FILE* stream);
+    void rust_compat_clear_path_cache();
 }
 
 namespace fallout {
@@ -122,4 +123,9 @@ long getFileSize(FILE* stream)
     return rust_get_file_size(stream);
 }
 
+void compat_clear_path_cache()
+{
+    rust_compat_clear_path_cache();
+}
+
 } // namespace fallout
@@ -37,6 +37,7 @@ int compat_rename(const char* oldFileName, const char* newFileName);
 int compat_access(const char* path, int mode);
 char* compat_strdup(const char* string);
 long getFileSize(FILE* stream);
+void compat_clear_path_cache();
 
 } // namespace fallout
 
@@ -23,6 +23,8 @@ struct SystemSettings {
     int splash = 0;
     int free_space = 20480;
     int times_run = 0;
+    bool memory_mapped_dats = false;
+    std::string mods_manifest_path;
 };
 
 struct PreferencesSettings {
@@ -72,6 +74,8 @@ struct DebugSettings {
     bool show_script_messages = false;
     bool show_load_info = false;
     bool output_map_data_info = false;
+    bool trace_file_resolution = false;
+    bool hot_reload = false;
 };
 
 struct MapperSettings {
@@ -7,6 +7,9 @@
 extern "C" {
     int rust_xfile_close(fallout::XFile* stream);
     fallout::XFile* rust_xfile_open(const char* filePath, const char* mode);
+    fallout::XFile* rust_xfile_open_memory(const void* data, size_t size);
+    fallout::XFile* rust_xfile_open_memory_borrowed(const void* data, size_t size);
+    const void* rust_xfile_get_memory(fallout::XFile* stream, size_t* sizePtr);
     int rust_xfile_print_formatted_args(fallout::XFile* stream, const char* format, va_list args);
     int rust_xfile_write_string(const char* string, fallout::XFile* stream);
     size_t rust_xfile_write(const void* ptr, size_t size, size_t count, fallout::XFile* stream);
@@ -16,6 +19,10 @@ extern "C" {
     int rust_xfile_eof(fallout::XFile* stream);
     long rust_xfile_get_size(fallout::XFile* stream);
     bool rust_xbase_reopen_all(char* paths);
+    void rust_xbase_set_sandboxed(bool enabled);
+    bool rust_xbase_set_watching(bool enabled);
+    void rust_xbase_set_change_handler(fallout::XBaseChangeHandler* handler);
+    int rust_xbase_poll_changes();
 }
 
 namespace fallout {
@@ -32,6 +39,26 @@ XFile* xfileOpen(const char* filePath, const char* mode)
     return rust_xfile_open(filePath, mode);
 }
 
+// Opens stream over a copy of [size] bytes at [data].
+XFile* xfileOpenMemory(const void* data, size_t size)
+{
+    return rust_xfile_open_memory(data, size);
+}
+
+// Opens stream over [size] bytes at [data] without copying them. [data] must
+// stay valid until the stream is closed.
+XFile* xfileOpenMemoryBorrowed(const void* data, size_t size)
+{
+    return rust_xfile_open_memory_borrowed(data, size);
+}
+
+// Returns current content of memory [stream], or NULL if [stream] is not a
+// memory stream.
+const void* xfileGetMemory(XFile* stream, size_t* sizePtr)
+{
+    return rust_xfile_get_memory(stream, sizePtr);
+}
+
 // [vfprintf].
 //
 // 0x4DF1AC
@@ -93,4 +120,31 @@ bool xbaseReopenAll(char* paths)
     return rust_xbase_reopen_all(paths);
 }
 
+// Enables or disables sandbox mode, in which relative paths cannot escape open
+// xbases with `..` components, and absolute paths are rejected.
+void xbaseSetSandboxed(bool enabled)
+{
+    rust_xbase_set_sandboxed(enabled);
+}
+
+// Enables or disables watching directory xbases for changes (disabled by
+// default). Only supported on Linux.
+bool xbaseSetWatching(bool enabled)
+{
+    return rust_xbase_set_watching(enabled);
+}
+
+// Sets [handler] to receive changes reported by [xbasePollChanges].
+void xbaseSetChangeHandler(XBaseChangeHandler* handler)
+{
+    rust_xbase_set_change_handler(handler);
+}
+
+// Reports files changed in watched xbases since the previous call to the
+// change handler. Returns the number of changes.
+int xbasePollChanges()
+{
+    return rust_xbase_poll_changes();
+}
+
 } // namespace fallout
@@ -13,8 +13,28 @@ typedef struct XList {
     char** _fileNames;
 } XList;
 
+typedef enum XBaseChangeKind {
+    XBASE_CHANGE_KIND_MODIFIED,
+    XBASE_CHANGE_KIND_REMOVED,
+} XBaseChangeKind;
+
+typedef struct XBaseChangeEvent {
+    // The path of changed file relative to xbase.
+    const char* filePath;
+
+    // The path of xbase containing the file.
+    const char* sourcePath;
+
+    XBaseChangeKind kind;
+} XBaseChangeEvent;
+
+typedef void(XBaseChangeHandler)(const XBaseChangeEvent* event);
+
 int xfileClose(XFile* stream);
 XFile* xfileOpen(const char* filename, const char* mode);
+XFile* xfileOpenMemory(const void* data, size_t size);
+XFile* xfileOpenMemoryBorrowed(const void* data, size_t size);
+const void* xfileGetMemory(XFile* stream, size_t* sizePtr);
 int xfilePrintFormattedArgs(XFile* stream, const char* format, va_list args);
 int xfileWriteString(const char* s, XFile* stream);
 size_t xfileWrite(const void* buf, size_t size, size_t count, XFile* stream);
@@ -24,6 +44,10 @@ void xfileRewind(XFile* stream);
 int xfileEof(XFile* stream);
 long xfileGetSize(XFile* stream);
 bool xbaseReopenAll(char* paths);
+void xbaseSetSandboxed(bool enabled);
+bool xbaseSetWatching(bool enabled);
+void xbaseSetChangeHandler(XBaseChangeHandler* handler);
+int xbasePollChanges();
 
 } // namespace fallout
 